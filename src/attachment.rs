@@ -0,0 +1,126 @@
+use crate::error::PassHiveError;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+// Вложения записей: сканы, договоры, файлы ключей. Содержимое шифруется
+// потоком (crypto::StreamEncryptor), имя файла и MIME-тип - отдельно в meta
+
+pub const DEFAULT_MIME: &str = "application/octet-stream";
+
+// MIME-тип по расширению; для неизвестных - application/octet-stream
+pub fn guess_mime(filename: &str) -> &'static str {
+    let extension = match Path::new(filename).extension() {
+        Some(extension) => extension.to_string_lossy().to_lowercase(),
+        None => return DEFAULT_MIME,
+    };
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "tif" | "tiff" => "image/tiff",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "pem" | "crt" | "cer" | "key" => "application/x-pem-file",
+        "p12" | "pfx" => "application/x-pkcs12",
+        "kdbx" => "application/x-keepass2",
+        _ => DEFAULT_MIME,
+    }
+}
+
+// Имя вложения - только последний компонент пути: "../a/скан.pdf" -> "скан.pdf"
+pub fn validate_filename(filename: &str) -> Result<String, PassHiveError> {
+    match Path::new(filename.trim()).file_name() {
+        Some(name) => Ok(name.to_string_lossy().to_string()),
+        None => Err(PassHiveError::InvalidInput(format!(
+            "{} - не имя файла",
+            filename
+        ))),
+    }
+}
+
+// Куда извлечь вложение: в каталог - под сохраненным именем, иначе по указанному пути
+pub fn target_path(path: &Path, filename: &str) -> PathBuf {
+    match path.is_dir() {
+        true => path.join(
+            Path::new(filename)
+                .file_name()
+                .unwrap_or("attachment".as_ref()),
+        ),
+        false => path.to_path_buf(),
+    }
+}
+
+// Новый файл только для владельца (0600). Существующий файл не перезаписывается:
+// на его месте может оказаться ссылка на чужой файл
+pub fn create_private_file(path: &Path) -> Result<File, PassHiveError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            PassHiveError::InvalidInput(format!("файл {} уже существует", path.display()))
+        }
+        _ => e.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn mime_is_guessed_by_extension() {
+        assert_eq!(guess_mime("скан.PDF"), "application/pdf");
+        assert_eq!(guess_mime("фото.jpeg"), "image/jpeg");
+        assert_eq!(guess_mime("архив.tar.gz"), "application/gzip");
+        assert_eq!(guess_mime("README"), DEFAULT_MIME);
+        assert_eq!(guess_mime("файл.неизвестно"), DEFAULT_MIME);
+    }
+
+    #[test]
+    fn filename_keeps_only_last_component() {
+        assert_eq!(validate_filename("../a/скан.pdf").unwrap(), "скан.pdf");
+        assert_eq!(validate_filename("  key.pem ").unwrap(), "key.pem");
+        assert!(validate_filename("..").is_err());
+        assert!(validate_filename("").is_err());
+    }
+
+    #[test]
+    fn target_path_and_private_file() {
+        let dir = TempDir::new().unwrap();
+        let target = target_path(dir.path(), "../скан.pdf");
+        assert_eq!(target, dir.path().join("скан.pdf"));
+        let explicit = dir.path().join("out.bin");
+        assert_eq!(target_path(&explicit, "скан.pdf"), explicit);
+
+        create_private_file(&target).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(matches!(
+            create_private_file(&target),
+            Err(PassHiveError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::db::models::{DataType, ShowableData};
+use crate::strength;
+use std::collections::HashMap;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Что считать проблемой при проверке хранилища
+#[derive(Debug, Clone, Copy)]
+pub struct AuditPolicy {
+    // Пароли с оценкой ниже считаются слабыми (0..=4)
+    pub min_score: u8,
+    // Пароли старше стольких дней считаются давно не менявшимися
+    pub max_age_days: u64,
+}
+
+pub struct WeakEntry {
+    pub id: i64,
+    pub score: u8,
+    pub warning: Option<&'static str>,
+}
+
+pub struct OldEntry {
+    pub id: i64,
+    pub days: u64,
+}
+
+// Записи указываются по id из БД: номера строк таблицы знает только интерфейс
+#[derive(Default)]
+pub struct AuditReport {
+    // Группы записей с одинаковым паролем, в каждой не меньше двух
+    pub reused: Vec<Vec<i64>>,
+    pub weak: Vec<WeakEntry>,
+    pub old: Vec<OldEntry>,
+}
+
+impl AuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.reused.is_empty() && self.weak.is_empty() && self.old.is_empty()
+    }
+}
+
+// Проверяются только записи с паролями: Password и WifiConfig.
+// updated_at - время последней смены секрета по id, now - текущее unix-время
+pub fn audit(
+    entries: &[ShowableData],
+    updated_at: &HashMap<i64, i64>,
+    now: i64,
+    policy: &AuditPolicy,
+) -> AuditReport {
+    let mut report = AuditReport::default();
+    let mut by_secret: HashMap<&str, Vec<i64>> = HashMap::new();
+
+    for entry in entries {
+        let (password, inputs) = match &entry.data {
+            DataType::Password {
+                password, username, ..
+            } => (password, vec![entry.name.as_str(), username.as_str()]),
+            DataType::WifiConfig { name, password } => {
+                (password, vec![entry.name.as_str(), name.as_str()])
+            }
+            _ => continue,
+        };
+        // Пустой пароль - открытая сеть или еще не заданный пароль, а не повтор и не слабый
+        if password.is_empty() {
+            continue;
+        }
+
+        by_secret.entry(password).or_default().push(entry.id);
+
+        let strength = strength::estimate(password, &inputs);
+        if strength.score < policy.min_score {
+            report.weak.push(WeakEntry {
+                id: entry.id,
+                score: strength.score,
+                warning: strength.warning,
+            });
+        }
+
+        if let Some(changed) = updated_at.get(&entry.id) {
+            let days = ((now - changed) / SECONDS_PER_DAY).max(0) as u64;
+            if days > policy.max_age_days {
+                report.old.push(OldEntry { id: entry.id, days });
+            }
+        }
+    }
+
+    report.reused = by_secret
+        .into_values()
+        .filter(|ids| ids.len() > 1)
+        .collect();
+    // Порядок HashMap случаен - сортируем, чтобы отчет не прыгал между запусками
+    report.reused.sort();
+    report.weak.sort_by_key(|entry| (entry.score, entry.id));
+    report
+        .old
+        .sort_by_key(|entry| std::cmp::Reverse(entry.days));
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::Labels;
+
+    const STRONG: &str = "t9#Vq2!mLx8&Rz4pW";
+
+    fn entry(id: i64, data: DataType) -> ShowableData {
+        ShowableData {
+            id,
+            name: format!("запись {}", id),
+            notice: String::new(),
+            data,
+            custom_fields: Vec::new(),
+            folder: None,
+            labels: Labels::default(),
+        }
+    }
+
+    fn password(id: i64, password: &str) -> ShowableData {
+        entry(
+            id,
+            DataType::Password {
+                password: password.to_string(),
+                username: String::new(),
+                urls: Vec::new(),
+                totp: None,
+            },
+        )
+    }
+
+    fn policy() -> AuditPolicy {
+        AuditPolicy {
+            min_score: 3,
+            max_age_days: 365,
+        }
+    }
+
+    #[test]
+    fn reused_weak_and_old_passwords_are_reported() {
+        let entries = vec![
+            password(1, STRONG),
+            password(2, "qwerty"),
+            entry(
+                3,
+                DataType::WifiConfig {
+                    name: "home".to_string(),
+                    password: STRONG.to_string(),
+                },
+            ),
+            entry(
+                4,
+                DataType::Token {
+                    token: "qwerty".to_string(),
+                    from: String::new(),
+                },
+            ),
+        ];
+        let now = 1_000 * SECONDS_PER_DAY;
+        let updated_at = HashMap::from([(1, now), (2, now - 400 * SECONDS_PER_DAY)]);
+
+        let report = audit(&entries, &updated_at, now, &policy());
+
+        assert_eq!(report.reused, vec![vec![1, 3]]);
+        assert_eq!(report.weak.len(), 1);
+        assert_eq!(report.weak[0].id, 2);
+        assert_eq!(report.old.len(), 1);
+        assert_eq!((report.old[0].id, report.old[0].days), (2, 400));
+        assert!(!report.is_clean());
+    }
+
+    #[test]
+    fn empty_passwords_are_skipped() {
+        let entries = vec![
+            password(1, ""),
+            password(2, ""),
+            entry(
+                3,
+                DataType::WifiConfig {
+                    name: "кафе".to_string(),
+                    password: String::new(),
+                },
+            ),
+        ];
+        let updated_at = HashMap::from([(1, 0), (2, 0), (3, 0)]);
+
+        let report = audit(&entries, &updated_at, 1_000 * SECONDS_PER_DAY, &policy());
+        assert!(report.is_clean());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::db::models::{DataType, ShowableData};
+use crate::error::PassHiveError;
+use sha1::{Digest, Sha1};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+// Локальная копия базы утекших паролей Have I Been Pwned.
+// Пароль только хэшируется в памяти: ни он, ни его хэш на диск не пишутся
+pub enum BreachIndex {
+    // Один файл "SHA1:количество", отсортированный по хэшу (ordered by hash)
+    SortedFile(PathBuf),
+    // Каталог диапазонов: файлы по первым 5 символам хэша (ABCDE или ABCDE.txt)
+    // со строками "остаток хэша:количество", как в ответах range API
+    RangeDir(PathBuf),
+}
+
+const HASH_LEN: usize = 40;
+const PREFIX_LEN: usize = 5;
+
+impl BreachIndex {
+    pub fn open(path: &Path) -> Result<Self, PassHiveError> {
+        if path.is_dir() {
+            Ok(BreachIndex::RangeDir(path.to_path_buf()))
+        } else if path.is_file() {
+            Ok(BreachIndex::SortedFile(path.to_path_buf()))
+        } else {
+            Err(PassHiveError::InvalidInput(format!(
+                "нет файла или каталога HIBP {}",
+                path.display()
+            )))
+        }
+    }
+
+    // Сколько раз пароль встречался в утечках. None - не встречался
+    pub fn count(&self, password: &str) -> Result<Option<u64>, PassHiveError> {
+        let hash = sha1_hex(password);
+
+        match self {
+            BreachIndex::SortedFile(path) => search_sorted_file(path, &hash),
+            BreachIndex::RangeDir(dir) => search_range(dir, &hash),
+        }
+    }
+}
+
+// Проверяет пароли записей Password и WifiConfig: id -> число утечек
+pub fn check_entries<'a>(
+    entries: impl IntoIterator<Item = &'a ShowableData>,
+    index: &BreachIndex,
+) -> Result<HashMap<i64, u64>, PassHiveError> {
+    let mut breached = HashMap::new();
+
+    for entry in entries {
+        let password = match &entry.data {
+            DataType::Password { password, .. } => password,
+            DataType::WifiConfig { password, .. } => password,
+            _ => continue,
+        };
+        if password.is_empty() {
+            continue;
+        }
+        if let Some(count) = index.count(password)? {
+            breached.insert(entry.id, count);
+        }
+    }
+
+    Ok(breached)
+}
+
+fn sha1_hex(password: &str) -> Zeroizing<String> {
+    let digest = Sha1::digest(password.as_bytes());
+    let mut hex = Zeroizing::new(String::with_capacity(HASH_LEN));
+    for byte in digest.iter() {
+        hex.push_str(&format!("{:02X}", byte));
+    }
+    hex
+}
+
+// Строка "ХЭШ:количество" -> (хэш в верхнем регистре, количество)
+fn parse_line(line: &str) -> Result<(String, u64), PassHiveError> {
+    let bad_line = || PassHiveError::InvalidInput(format!("неверная строка HIBP: {}", line));
+
+    let (hash, count) = line.trim_end().split_once(':').ok_or_else(bad_line)?;
+    let count = count.trim().parse::<u64>().map_err(|_| bad_line())?;
+    Ok((hash.to_ascii_uppercase(), count))
+}
+
+// Двоичный поиск по смещениям в файле. Инвариант: строка с нужным хэшем,
+// если она есть, начинается в [lo, hi). Файл целиком в память не читается
+fn search_sorted_file(path: &Path, hash: &str) -> Result<Option<u64>, PassHiveError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut lo = 0u64;
+    let mut hi = reader.seek(SeekFrom::End(0))?;
+    let mut line = String::new();
+
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+
+        // Первая строка, начинающаяся не раньше mid: дочитываем предыдущую
+        // с mid - 1, чтобы не пропустить строку, начинающуюся ровно в mid
+        let mut start = mid;
+        if mid > 0 {
+            reader.seek(SeekFrom::Start(mid - 1))?;
+            line.clear();
+            start = mid - 1 + reader.read_line(&mut line)? as u64;
+        } else {
+            reader.seek(SeekFrom::Start(0))?;
+        }
+
+        line.clear();
+        let read = reader.read_line(&mut line)? as u64;
+        if read == 0 || line.trim().is_empty() {
+            hi = mid;
+            continue;
+        }
+
+        let (found, count) = parse_line(&line)?;
+        match found.as_str().cmp(hash) {
+            Ordering::Equal => return Ok(Some(count)),
+            Ordering::Less => lo = start + read,
+            Ordering::Greater => hi = mid,
+        }
+    }
+
+    Ok(None)
+}
+
+// Файл диапазона небольшой (сотни строк) - читается построчно
+fn search_range(dir: &Path, hash: &str) -> Result<Option<u64>, PassHiveError> {
+    let (prefix, suffix) = hash.split_at(PREFIX_LEN);
+
+    let plain = dir.join(prefix);
+    let path = match plain.is_file() {
+        true => plain,
+        false => dir.join(format!("{}.txt", prefix)),
+    };
+    // Нет файла диапазона - в выгрузке нет хэшей с таким началом
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    for line in BufReader::new(File::open(&path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (found, count) = parse_line(&line)?;
+        if found == suffix {
+            return Ok(Some(count));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn sha1_matches_hibp_format() {
+        assert_eq!(
+            sha1_hex("password").as_str(),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"
+        );
+    }
+
+    #[test]
+    fn sorted_file_finds_every_line() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("pwned-passwords-sha1-ordered-by-hash.txt");
+        // Строки выгрузки HIBP заканчиваются на \r\n
+        let mut lines: Vec<(Zeroizing<String>, u64)> = (0..300)
+            .map(|i| (sha1_hex(&format!("leaked{}", i)), i + 1))
+            .collect();
+        lines.sort_by(|a, b| a.0.cmp(&b.0));
+        let text: String = lines
+            .iter()
+            .map(|(hash, count)| format!("{}:{}\r\n", hash.as_str(), count))
+            .collect();
+        fs::write(&path, text).unwrap();
+
+        let index = BreachIndex::open(&path).unwrap();
+        for i in 0..300 {
+            assert_eq!(index.count(&format!("leaked{}", i)).unwrap(), Some(i + 1));
+        }
+        assert_eq!(index.count("not leaked").unwrap(), None);
+        assert_eq!(index.count("").unwrap(), None);
+    }
+
+    #[test]
+    fn range_dir_finds_suffix_in_prefix_file() {
+        let dir = TempDir::new().unwrap();
+        fs::write(
+            dir.path().join("5BAA6"),
+            "003D68EB55068C33ACE09247EE4C639306B:3\r\n1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n",
+        )
+        .unwrap();
+        let hash = sha1_hex("123456");
+        fs::write(
+            dir.path().join(format!("{}.txt", &hash[..PREFIX_LEN])),
+            format!("{}:100\n", &hash[PREFIX_LEN..]),
+        )
+        .unwrap();
+
+        let index = BreachIndex::open(dir.path()).unwrap();
+        assert_eq!(index.count("password").unwrap(), Some(9545824));
+        assert_eq!(index.count("123456").unwrap(), Some(100));
+        assert_eq!(index.count("t9#Vq2!mLx8&Rz4pW").unwrap(), None);
+    }
+
+    #[test]
+    fn missing_index_is_an_error() {
+        let dir = TempDir::new().unwrap();
+        assert!(BreachIndex::open(&dir.path().join("нет")).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use crate::db::models::DataType;
+use crate::error::PassHiveError;
+use std::fmt;
+
+// Проверки банковских карт: контрольная сумма Луна, платежная система, срок действия
+
+// За сколько месяцев до конца срока карта подсвечивается в таблице
+pub const EXPIRY_WARNING_MONTHS: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CardBrand {
+    Visa,
+    Mastercard,
+    Mir,
+    Amex,
+}
+
+impl fmt::Display for CardBrand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardBrand::Visa => write!(f, "Visa"),
+            CardBrand::Mastercard => write!(f, "Mastercard"),
+            CardBrand::Mir => write!(f, "Мир"),
+            CardBrand::Amex => write!(f, "American Express"),
+        }
+    }
+}
+
+impl CardBrand {
+    // У American Express 4 цифры кода на лицевой стороне, у остальных 3
+    pub fn cvv_len(&self) -> usize {
+        match self {
+            CardBrand::Amex => 4,
+            _ => 3,
+        }
+    }
+
+    fn number_lens(&self) -> &'static [usize] {
+        match self {
+            CardBrand::Visa => &[13, 16, 19],
+            CardBrand::Mastercard => &[16],
+            CardBrand::Mir => &[16, 17, 18, 19],
+            CardBrand::Amex => &[15],
+        }
+    }
+}
+
+// Номер без пробелов и дефисов: "4111 1111-1111 1111" -> "4111111111111111"
+pub fn normalize_number(num: &str) -> String {
+    num.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+// Платежная система по первым цифрам номера (IIN)
+pub fn detect_brand(num: &str) -> Option<CardBrand> {
+    let prefix = |len: usize| num.get(..len).and_then(|p| p.parse::<u32>().ok());
+
+    match (prefix(1), prefix(2), prefix(4)) {
+        (Some(4), _, _) => Some(CardBrand::Visa),
+        (_, Some(34 | 37), _) => Some(CardBrand::Amex),
+        (_, Some(51..=55), _) => Some(CardBrand::Mastercard),
+        (_, _, Some(2200..=2204)) => Some(CardBrand::Mir),
+        (_, _, Some(2221..=2720)) => Some(CardBrand::Mastercard),
+        _ => None,
+    }
+}
+
+pub fn luhn_valid(num: &str) -> bool {
+    let mut sum = 0u32;
+    for (i, c) in num.chars().rev().enumerate() {
+        let Some(mut digit) = c.to_digit(10) else {
+            return false;
+        };
+        // Каждая вторая цифра справа удваивается
+        if i % 2 == 1 {
+            digit *= 2;
+            if digit > 9 {
+                digit -= 9;
+            }
+        }
+        sum += digit;
+    }
+    !num.is_empty() && sum.is_multiple_of(10)
+}
+
+// Номер уже нормализован. Неизвестная платежная система - не ошибка,
+// но длина и контрольная сумма проверяются всегда
+pub fn validate_number(num: &str) -> Result<Option<CardBrand>, PassHiveError> {
+    if num.is_empty() || !num.chars().all(|c| c.is_ascii_digit()) {
+        return Err(PassHiveError::InvalidInput(
+            "номер карты должен содержать только цифры".to_string(),
+        ));
+    }
+    if !(12..=19).contains(&num.len()) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в номере карты от 12 до 19 цифр, указано {}",
+            num.len()
+        )));
+    }
+
+    let brand = detect_brand(num);
+    if let Some(brand) = brand
+        && !brand.number_lens().contains(&num.len())
+    {
+        return Err(PassHiveError::InvalidInput(format!(
+            "у карт {} не бывает номеров из {} цифр",
+            brand,
+            num.len()
+        )));
+    }
+    if !luhn_valid(num) {
+        return Err(PassHiveError::InvalidInput(
+            "номер карты не проходит проверку контрольной суммы (алгоритм Луна)".to_string(),
+        ));
+    }
+
+    Ok(brand)
+}
+
+// Для неизвестной платежной системы подходит код из 3 или 4 цифр
+pub fn validate_cvv(cvv: &str, brand: Option<CardBrand>) -> Result<String, PassHiveError> {
+    let expected = match brand {
+        Some(brand) => brand.cvv_len()..=brand.cvv_len(),
+        None => 3..=4,
+    };
+    if !cvv.chars().all(|c| c.is_ascii_digit()) || !expected.contains(&cvv.len()) {
+        return Err(PassHiveError::InvalidInput(match brand {
+            Some(brand) => format!("у карт {} в CVV {} цифры", brand, brand.cvv_len()),
+            None => "в CVV 3 или 4 цифры".to_string(),
+        }));
+    }
+
+    Ok(cvv.to_string())
+}
+
+// "ММ/ГГ", "ММ/ГГГГ" или "ММГГ" -> (месяц, полный год)
+pub fn parse_expiry(text: &str) -> Result<(u8, u16), PassHiveError> {
+    let invalid = || {
+        PassHiveError::InvalidInput(
+            "срок действия указывается как ММ/ГГ, например 07/29".to_string(),
+        )
+    };
+
+    let text = text.trim();
+    let (month, year) = match text.split_once(['/', '.', '-']) {
+        Some((month, year)) => (month.trim(), year.trim()),
+        None if text.len() == 4 => text.split_at(2),
+        None => return Err(invalid()),
+    };
+
+    let month = month.parse::<u8>().map_err(|_| invalid())?;
+    let year = match year.len() {
+        2 => 2000 + year.parse::<u16>().map_err(|_| invalid())?,
+        4 => year.parse::<u16>().map_err(|_| invalid())?,
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&month) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "месяц срока действия от 1 до 12, указано {}",
+            month
+        )));
+    }
+
+    Ok((month, year))
+}
+
+pub fn format_expiry(month: u8, year: u16) -> String {
+    format!("{:02}/{:02}", month, year % 100)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpiryStatus {
+    Valid,
+    // Сколько полных месяцев осталось после текущего: 0 - истекает в этом месяце
+    ExpiresSoon(i64),
+    Expired,
+}
+
+// Карта действует до конца указанного месяца включительно
+pub fn expiry_status(month: u8, year: u16, now: u64) -> ExpiryStatus {
+    let (now_year, now_month) = year_month(now);
+    let left = (year as i64 * 12 + month as i64) - (now_year * 12 + now_month);
+
+    match left {
+        ..0 => ExpiryStatus::Expired,
+        left if left < EXPIRY_WARNING_MONTHS => ExpiryStatus::ExpiresSoon(left),
+        _ => ExpiryStatus::Valid,
+    }
+}
+
+// Статус срока для записи-карты с заполненным сроком
+pub fn card_expiry(data: &DataType, now: u64) -> Option<ExpiryStatus> {
+    match data {
+        DataType::Card {
+            exp_month: Some(month),
+            exp_year: Some(year),
+            ..
+        } => Some(expiry_status(*month, *year, now)),
+        _ => None,
+    }
+}
+
+// Проверка карты, собранной из полей командной строки: номер и CVV
+// проверяются только если заданы, срок - только целиком
+pub fn validate(data: &DataType) -> Result<(), PassHiveError> {
+    if let DataType::Card {
+        num,
+        cvv,
+        exp_month,
+        exp_year,
+        ..
+    } = data
+    {
+        let brand = match num.is_empty() {
+            true => None,
+            false => validate_number(num)?,
+        };
+        if !cvv.is_empty() {
+            validate_cvv(cvv, brand)?;
+        }
+        match (exp_month, exp_year) {
+            (Some(month), Some(_)) if !(1..=12).contains(month) => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "месяц срока действия от 1 до 12, указано {}",
+                    month
+                )));
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(PassHiveError::InvalidInput(
+                    "срок действия задается полями exp_month и exp_year вместе".to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Unix-время -> (год, месяц) по UTC, алгоритм civil_from_days Говарда Хиннанта
+pub(crate) fn year_month(now: u64) -> (i64, i64) {
+    let days = (now / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 15.06.2024 00:00 UTC
+    const JUNE_2024: u64 = 1_718_409_600;
+
+    #[test]
+    fn luhn_accepts_test_card_numbers() {
+        for num in [
+            "4111111111111111",
+            "4012888888881881",
+            "5555555555554444",
+            "2223003122003222",
+            "378282246310005",
+            "2200000000000004",
+        ] {
+            assert!(luhn_valid(num), "{}", num);
+        }
+        assert!(!luhn_valid("4111111111111112"));
+        assert!(!luhn_valid("4111 1111 1111 1111"));
+        assert!(!luhn_valid(""));
+    }
+
+    #[test]
+    fn brand_is_detected_by_prefix_and_length() {
+        let brand = |num: &str| validate_number(&normalize_number(num)).unwrap();
+        assert_eq!(brand("4111 1111-1111 1111"), Some(CardBrand::Visa));
+        assert_eq!(brand("5555555555554444"), Some(CardBrand::Mastercard));
+        assert_eq!(brand("2223003122003222"), Some(CardBrand::Mastercard));
+        assert_eq!(brand("378282246310005"), Some(CardBrand::Amex));
+        assert_eq!(brand("2200000000000004"), Some(CardBrand::Mir));
+        assert_eq!(brand("6011111111111117"), None);
+
+        assert!(validate_number("4111111111111112").is_err());
+        assert!(validate_number("37828224631000").is_err());
+        assert!(validate_number("41111111111a").is_err());
+    }
+
+    #[test]
+    fn cvv_length_depends_on_brand() {
+        assert!(validate_cvv("123", Some(CardBrand::Visa)).is_ok());
+        assert!(validate_cvv("1234", Some(CardBrand::Visa)).is_err());
+        assert!(validate_cvv("1234", Some(CardBrand::Amex)).is_ok());
+        assert!(validate_cvv("123", Some(CardBrand::Amex)).is_err());
+        assert_eq!(validate_cvv("012", None).unwrap(), "012");
+        assert!(validate_cvv("12", None).is_err());
+    }
+
+    #[test]
+    fn expiry_is_parsed_and_formatted() {
+        assert_eq!(parse_expiry("07/29").unwrap(), (7, 2029));
+        assert_eq!(parse_expiry("7/2029").unwrap(), (7, 2029));
+        assert_eq!(parse_expiry("0729").unwrap(), (7, 2029));
+        assert!(parse_expiry("13/29").is_err());
+        assert!(parse_expiry("07/9").is_err());
+        assert_eq!(format_expiry(7, 2029), "07/29");
+    }
+
+    #[test]
+    fn card_is_valid_through_its_expiry_month() {
+        assert_eq!(year_month(JUNE_2024), (2024, 6));
+        assert_eq!(expiry_status(5, 2024, JUNE_2024), ExpiryStatus::Expired);
+        assert_eq!(
+            expiry_status(6, 2024, JUNE_2024),
+            ExpiryStatus::ExpiresSoon(0)
+        );
+        assert_eq!(
+            expiry_status(7, 2024, JUNE_2024),
+            ExpiryStatus::ExpiresSoon(1)
+        );
+        assert_eq!(expiry_status(8, 2024, JUNE_2024), ExpiryStatus::Valid);
+    }
+
+    #[test]
+    fn old_numeric_cvv_is_read_as_text() {
+        let data: DataType =
+            serde_json::from_str(r#"{"Card":{"num":"4111111111111111","cvv":12,"bank":""}}"#)
+                .unwrap();
+        assert!(matches!(
+            &data,
+            DataType::Card { cvv, exp_month: None, .. } if cvv == "12"
+        ));
+        assert_eq!(card_expiry(&data, JUNE_2024), None);
+    }
+}
@@ -0,0 +1,1269 @@
+use crate::os_work;
+use clap::{Parser, Subcommand, ValueEnum};
+use passhive::key_generator::{
+    PassphrasePolicy, PasswordPolicy, Wordlist, generate_passphrase, generate_password,
+    load_wordlist,
+};
+#[cfg(unix)]
+use passhive::ssh_agent::{self, SshAgent};
+use passhive::{
+    attachment,
+    breach::{BreachIndex, check_entries},
+    card, custom_fields,
+    db::models::{Attachment, CustomField, DataAndMeta, DataType, EntryWithFolder, ShowableData},
+    error::PassHiveError,
+    folder::{self, Folder},
+    otp, passport, ssh, tag,
+    template::{self, Template, TemplateField},
+    vault::Vault,
+};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, stdin, stdout};
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+// Коды выхода для скриптов. 2 - ошибка в аргументах, его возвращает clap
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_AUTH: i32 = 3;
+pub const EXIT_NOT_FOUND: i32 = 4;
+pub const EXIT_AMBIGUOUS: i32 = 5;
+pub const EXIT_BREACHED: i32 = 6;
+
+const EXIT_CODES_HELP: &str = "Коды выхода:
+  0  успех
+  1  ошибка хранилища или данных
+  2  неверные аргументы
+  3  неверный логин или пароль
+  4  запись или вложение не найдены
+  5  под названием подходит несколько записей
+  6  breach нашел пароли из утечек
+
+Пароль берется из PASSHIVE_PASSWORD, иначе запрашивается с терминала.
+
+Форматы вывода list и get (--format):
+  text  для человека: list - id, тип, название через табуляцию, экранированные как в tsv;
+        get - строки поле: значение,
+        теги - tags: через запятую, избранное - favorite: true или false,
+        папка - folder: путь (пусто - вне папок),
+        дополнительные поля - custom.ИМЯ: значение
+  json  запись - объект {\"id\", \"type\", \"name\", \"notice\", \"fields\", \"custom_fields\",
+        \"tags\", \"favorite\", \"folder\"}, list - массив таких объектов. fields - поля типа
+        (числа остаются числами), custom_fields - массив {\"name\", \"value\", \"hidden\"}
+        в порядке записи, tags - массив строк по алфавиту, favorite - true или false,
+        folder - путь папки вида Работа/AWS или null вне папок
+  yaml  та же схема, что и json
+  tsv   list - заголовок id, type, name, notice и строки; get - пары поле, значение,
+        tags - через запятую, folder - путь папки.
+        Табуляция, переводы строк и \\ в значениях экранируются как \\t, \\n и \\\\
+С --field выводится только значение поля: в json и yaml - в виде значения этого формата.
+
+breach сверяет пароли с локальной выгрузкой Have I Been Pwned (--hibp или PASSHIVE_HIBP):
+файлом SHA-1 хэшей, отсортированным по хэшу, или каталогом диапазонов ABCDE.txt.
+Выводит найденные записи с числом утечек: text и tsv - id, тип, название, количество;
+json и yaml - массив объектов {\"id\", \"type\", \"name\", \"count\"}.";
+
+#[derive(Parser)]
+#[command(
+    name = "passhive",
+    about = "PassHiveCLI - менеджер паролей. Без команды запускается интерактивный режим",
+    after_help = EXIT_CODES_HELP
+)]
+pub struct Args {
+    /// Логин пользователя хранилища
+    #[arg(long, short, env = "PASSHIVE_LOGIN", global = true)]
+    pub login: Option<String>,
+
+    /// Формат вывода list и get
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+
+    /// Минимальная стойкость нового мастер-пароля, от 0 до 4
+    #[arg(
+        long,
+        env = "PASSHIVE_MIN_STRENGTH",
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u8).range(0..=4)
+    )]
+    pub min_strength: u8,
+
+    /// Выгрузка паролей Have I Been Pwned: отсортированный файл SHA-1 или каталог диапазонов
+    #[arg(long, env = "PASSHIVE_HIBP", global = true, value_name = "ПУТЬ")]
+    pub hibp: Option<PathBuf>,
+
+    /// Через сколько дней пароль считается давно не менявшимся в проверке безопасности
+    #[arg(long, env = "PASSHIVE_MAX_AGE_DAYS", default_value_t = 180)]
+    pub max_age_days: u64,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+    Yaml,
+    Tsv,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Список записей: id, тип и название через табуляцию
+    List {
+        /// Только записи из этой папки и вложенных в нее
+        #[arg(long, value_name = "ПАПКА")]
+        folder: Option<String>,
+        /// Только записи с этим тегом; повторенный --tag - со всеми указанными
+        #[arg(long, value_name = "ТЕГ")]
+        tag: Vec<String>,
+        /// Только избранные записи
+        #[arg(long)]
+        favorite: bool,
+    },
+    /// Показать запись по названию
+    Get {
+        name: String,
+        /// Вывести только это поле, например password или custom.PIN
+        #[arg(long)]
+        field: Option<String>,
+    },
+    /// Добавить запись и вывести ее id
+    Add {
+        /// Тип: password, card, document, token, wificonfig, passport, otp, sshkey
+        /// или название шаблона. Ключ sshkey без private_key генерируется (ed25519)
+        kind: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long, default_value = "")]
+        notice: String,
+        /// Значение поля, можно повторять: --set from=github.
+        /// Списки через запятую: --set urls=a.com,b.com; пустое значение убирает totp
+        #[arg(long = "set", value_name = "ПОЛЕ=ЗНАЧЕНИЕ")]
+        set: Vec<String>,
+        /// Прочитать секрет (пароль, токен, текст...) из stdin; для паспорта - MRZ, для ключа SSH - закрытый ключ OpenSSH
+        #[arg(long)]
+        stdin: bool,
+        /// Дополнительное поле, можно повторять: --custom "Секретный вопрос=Кличка кота"
+        #[arg(long, value_name = "ИМЯ=ЗНАЧЕНИЕ")]
+        custom: Vec<String>,
+        /// Дополнительное поле со значением, скрытым в таблице
+        #[arg(long, value_name = "ИМЯ=ЗНАЧЕНИЕ")]
+        hidden: Vec<String>,
+        /// Папка записи, например Работа/AWS; папка должна существовать
+        #[arg(long, value_name = "ПАПКА")]
+        folder: Option<String>,
+        /// Тег записи, можно повторять: --tag работа --tag банк
+        #[arg(long, value_name = "ТЕГ")]
+        tag: Vec<String>,
+        /// Добавить запись в избранное
+        #[arg(long)]
+        favorite: bool,
+    },
+    /// Удалить запись по id
+    Rm { id: i64 },
+    /// Изменить запись по id
+    Edit {
+        id: i64,
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long)]
+        notice: Option<String>,
+        #[arg(long = "set", value_name = "ПОЛЕ=ЗНАЧЕНИЕ")]
+        set: Vec<String>,
+        #[arg(long)]
+        stdin: bool,
+        /// Пустое значение удаляет поле: --custom PIN=
+        #[arg(long, value_name = "ИМЯ=ЗНАЧЕНИЕ")]
+        custom: Vec<String>,
+        #[arg(long, value_name = "ИМЯ=ЗНАЧЕНИЕ")]
+        hidden: Vec<String>,
+        /// Перенести запись в папку; "/" - убрать из папок
+        #[arg(long, value_name = "ПАПКА")]
+        folder: Option<String>,
+        /// Добавить тег, можно повторять
+        #[arg(long, value_name = "ТЕГ")]
+        tag: Vec<String>,
+        /// Убрать тег, можно повторять
+        #[arg(long, value_name = "ТЕГ")]
+        untag: Vec<String>,
+        #[arg(long, conflicts_with = "no_favorite")]
+        favorite: bool,
+        /// Убрать запись из избранного
+        #[arg(long)]
+        no_favorite: bool,
+    },
+    /// Теги записей и число записей с каждым
+    Tags,
+    /// Найти пароли, встречавшиеся в утечках, по локальной выгрузке HIBP
+    Breach,
+    /// Одноразовый код записи otp или пароля с totp; для HOTP счетчик сдвигается и сохраняется
+    Otp { name: String },
+    /// MRZ паспорта, собранная из его полей (формат TD3, 2 строки по 44 символа)
+    Mrz { name: String },
+    /// Агент SSH с ключами хранилища до Ctrl+C; SSH_AUTH_SOCK выводится в stdout
+    SshAgent {
+        /// Путь сокета, по умолчанию в $XDG_RUNTIME_DIR или /tmp/passhive-UID
+        #[arg(long, value_name = "ПУТЬ")]
+        socket: Option<PathBuf>,
+    },
+    /// Шаблоны записей с полями, заданными пользователем
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+    /// Папки записей, вложенные через /: Работа/AWS
+    Folder {
+        #[command(subcommand)]
+        action: FolderAction,
+    },
+    /// Вложения записей: файлы шифруются ключом хранилища частями по 64 КиБ
+    Attachment {
+        #[command(subcommand)]
+        action: AttachmentAction,
+    },
+    /// Сгенерировать пароль, хранилище не открывается
+    Generate(GenerateArgs),
+    /// Сгенерировать парольную фразу; энтропия выводится в stderr
+    Passphrase(PassphraseArgs),
+}
+
+#[derive(Subcommand)]
+pub enum TemplateAction {
+    /// Список шаблонов: название и поля ПОЛЕ:ВИД
+    List,
+    /// Создать шаблон; записи по нему добавляются через add НАЗВАНИЕ
+    Add {
+        name: String,
+        /// Поле шаблона, можно повторять: --field host --field password:secret.
+        /// Виды: text (по умолчанию), secret, number, date, url, multiline
+        #[arg(long = "field", value_name = "ПОЛЕ[:ВИД]", required = true)]
+        fields: Vec<String>,
+    },
+    /// Удалить шаблон; записи по нему остаются
+    Rm { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum FolderAction {
+    /// Список папок: путь и число записей в ней
+    List,
+    /// Создать папку; недостающие родительские создаются тоже
+    Add { path: String },
+    /// Удалить пустую папку
+    Rm { path: String },
+}
+
+#[derive(Subcommand)]
+pub enum AttachmentAction {
+    /// Прикрепить файл к записи и вывести id вложения
+    Add {
+        /// Название записи
+        entry: String,
+        /// Файл; "-" - прочитать из stdin, тогда нужен --name
+        file: PathBuf,
+        /// Имя вложения, по умолчанию - имя файла
+        #[arg(long)]
+        name: Option<String>,
+        /// MIME-тип, по умолчанию - по расширению имени
+        #[arg(long)]
+        mime: Option<String>,
+    },
+    /// Вложения записи, без названия - всех записей: id, id записи, размер, тип, имя
+    List { entry: Option<String> },
+    /// Извлечь вложение в новый файл с правами 0600 или в каталог под сохраненным именем;
+    /// "-" - в stdout. Существующий файл не перезаписывается
+    Extract { id: i64, path: PathBuf },
+    /// Удалить вложение
+    Rm { id: i64 },
+}
+
+#[derive(clap::Args)]
+pub struct GenerateArgs {
+    #[arg(long, default_value_t = PasswordPolicy::default().length)]
+    length: usize,
+    /// Без строчных букв
+    #[arg(long)]
+    no_lower: bool,
+    /// Без заглавных букв
+    #[arg(long)]
+    no_upper: bool,
+    /// Без цифр
+    #[arg(long)]
+    no_digits: bool,
+    /// Без спецсимволов
+    #[arg(long)]
+    no_symbols: bool,
+    /// Исключить похожие символы: I, l, 1, O, 0, o, |, кавычки
+    #[arg(long)]
+    exclude_ambiguous: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Lang {
+    En,
+    Ru,
+}
+
+#[derive(clap::Args)]
+pub struct PassphraseArgs {
+    /// Число слов; по умолчанию - сколько нужно словарю для энтропии 6 слов EFF (77,5 бит)
+    #[arg(long)]
+    words: Option<usize>,
+    /// Встроенный словарь
+    #[arg(long, value_enum, default_value_t = Lang::En)]
+    lang: Lang,
+    /// Свой словарь: по слову в строке или в формате EFF (номер, табуляция, слово)
+    #[arg(long, value_name = "ФАЙЛ")]
+    wordlist: Option<PathBuf>,
+    #[arg(long, default_value_t = PassphrasePolicy::default().separator)]
+    separator: String,
+    /// Каждое слово с заглавной буквы
+    #[arg(long)]
+    capitalize: bool,
+    /// Добавить цифру в конце
+    #[arg(long)]
+    digit: bool,
+    /// Добавить спецсимвол в конце
+    #[arg(long)]
+    symbol: bool,
+}
+
+enum Failure {
+    Vault(PassHiveError),
+    NotFound(String),
+    Ambiguous(String, usize),
+    Breached(usize),
+}
+
+impl From<PassHiveError> for Failure {
+    fn from(e: PassHiveError) -> Self {
+        Failure::Vault(e)
+    }
+}
+
+// Выполняет команду без баннера, спиннеров и сырого режима.
+// Результат - в stdout, ошибки - в stderr, итог - код выхода.
+pub fn run(command: Command, args: &Args) -> i32 {
+    match execute(command, args) {
+        Ok(()) => EXIT_OK,
+        Err(Failure::Vault(e)) => {
+            eprintln!("passhive: {}", e);
+            match e {
+                PassHiveError::WrongPassword => EXIT_AUTH,
+                PassHiveError::NoSuchEntry(_) | PassHiveError::NoSuchAttachment(_) => {
+                    EXIT_NOT_FOUND
+                }
+                _ => EXIT_ERROR,
+            }
+        }
+        Err(Failure::NotFound(name)) => {
+            eprintln!("passhive: нет записи с названием {}", name);
+            EXIT_NOT_FOUND
+        }
+        Err(Failure::Ambiguous(name, count)) => {
+            eprintln!(
+                "passhive: под названием {} найдено записей: {}, используйте id",
+                name, count
+            );
+            EXIT_AMBIGUOUS
+        }
+        Err(Failure::Breached(count)) => {
+            eprintln!("passhive: паролей из утечек: {}", count);
+            EXIT_BREACHED
+        }
+    }
+}
+
+fn execute(command: Command, args: &Args) -> Result<(), Failure> {
+    let format = args.format;
+
+    if let Command::Generate(args) = command {
+        let password = generate_password(&PasswordPolicy {
+            length: args.length,
+            lowercase: !args.no_lower,
+            uppercase: !args.no_upper,
+            digits: !args.no_digits,
+            symbols: !args.no_symbols,
+            exclude_ambiguous: args.exclude_ambiguous,
+        })?;
+        println!("{}", password.as_str());
+        return Ok(());
+    }
+    if let Command::Passphrase(args) = command {
+        return passphrase(args);
+    }
+
+    let vault = open_vault(args.login.clone())?;
+
+    match command {
+        Command::List {
+            folder,
+            tag,
+            favorite,
+        } => {
+            let mut entries = vault.list()?;
+            entries.retain(|entry| {
+                (!favorite || entry.labels.favorite)
+                    && tag
+                        .iter()
+                        .all(|wanted| tag::has(&entry.labels.tags, wanted))
+            });
+            if let Some(path) = folder {
+                let folders = vault.folders()?;
+                let root = find_folder(&folders, &path)?;
+                let inside = folder::subtree(&folders, root);
+                entries.retain(|entry| entry.folder.is_some_and(|id| inside.contains(&id)));
+            }
+            print_list(&entries, &vault.folders()?, format)?
+        }
+        Command::Get { name, field } => {
+            let entry = find_by_name(&vault, &name)?;
+            match field {
+                Some(field) => print_field(&entry, &field, format)?,
+                None => print_entry(&entry, &vault.folders()?, format)?,
+            }
+        }
+        Command::Add {
+            kind,
+            name,
+            notice,
+            set,
+            stdin,
+            custom,
+            hidden,
+            folder,
+            tag,
+            favorite,
+        } => {
+            // Встроенные типы важнее шаблонов: их названия шаблонам запрещены
+            let data = match DataType::from_formal_name(&kind) {
+                Some(DataType::Templated { .. }) => {
+                    return Err(PassHiveError::InvalidInput(
+                        "укажите вместо template название шаблона".to_string(),
+                    )
+                    .into());
+                }
+                Some(data) => data,
+                None => match template::find(&vault.templates()?, &kind) {
+                    Some(template) => template.new_entry(),
+                    None => {
+                        return Err(PassHiveError::InvalidInput(format!(
+                            "неизвестный тип {}: доступны password, card, document, token, wificonfig, passport, otp, sshkey и названия шаблонов",
+                            kind
+                        ))
+                        .into());
+                    }
+                },
+            };
+            let mut entry = DataAndMeta::new(data, name, notice);
+            apply_fields(&mut entry.data, &set, stdin)?;
+            apply_custom_fields(&mut entry.custom_fields, &custom, &hidden)?;
+            if let Some(path) = folder {
+                entry.folder = resolve_folder(&vault, &path)?;
+            }
+            for wanted in &tag {
+                tag::add(&mut entry.labels.tags, wanted)?;
+            }
+            entry.labels.favorite = favorite;
+            println!("{}", vault.add(&entry)?);
+        }
+        Command::Rm { id } => vault.delete(id)?,
+        Command::Otp { name } => {
+            let mut entry = find_by_name(&vault, &name)?;
+            let code = otp::code_for(&entry.data, otp::unix_now())?;
+            // Выданный код HOTP больше не годится - следующий вызов должен дать новый
+            if otp::advance_counter(&mut entry.data)? {
+                vault.update(&entry)?;
+            }
+            println!("{}", code.code.as_str());
+            if let Some(remaining) = code.remaining {
+                eprintln!("действует еще {} с", remaining);
+            }
+        }
+        Command::Mrz { name } => {
+            let entry = find_by_name(&vault, &name)?;
+            println!("{}", passport::generate_mrz(&entry.data)?);
+        }
+        Command::SshAgent { socket } => run_ssh_agent(&vault, socket)?,
+        Command::Template { action } => match action {
+            TemplateAction::List => print_templates(&vault.templates()?, format)?,
+            TemplateAction::Add { name, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|spec| Template::parse_field(spec))
+                    .collect::<Result<Vec<TemplateField>, PassHiveError>>()?;
+                vault.add_template(&Template::new(&name, fields)?)?;
+            }
+            TemplateAction::Rm { name } => match template::find(&vault.templates()?, &name) {
+                Some(template) => vault.delete_template(template.id)?,
+                None => {
+                    return Err(PassHiveError::InvalidInput(format!("нет шаблона {}", name)).into());
+                }
+            },
+        },
+        Command::Tags => {
+            let entries = vault.list()?;
+            let counted = tag::count(entries.iter().map(|entry| entry.labels.tags.as_slice()));
+            print_tags(&counted, format)?
+        }
+        Command::Folder { action } => match action {
+            FolderAction::List => {
+                print_folders(&vault.folders()?, &vault.folder_counts()?, format)?
+            }
+            FolderAction::Add { path } => {
+                vault.create_folder(&path)?;
+            }
+            FolderAction::Rm { path } => {
+                let folders = vault.folders()?;
+                vault.delete_folder(find_folder(&folders, &path)?.id)?;
+            }
+        },
+        Command::Attachment { action } => run_attachment(&vault, action, format)?,
+        Command::Breach => {
+            let index = match &args.hibp {
+                Some(path) => BreachIndex::open(path)?,
+                None => {
+                    return Err(PassHiveError::InvalidInput(
+                        "укажите выгрузку HIBP через --hibp или PASSHIVE_HIBP".to_string(),
+                    )
+                    .into());
+                }
+            };
+            let entries = vault.list()?;
+            let breached = check_entries(&entries, &index)?;
+            print_breached(&entries, &breached, format)?;
+            if !breached.is_empty() {
+                return Err(Failure::Breached(breached.len()));
+            }
+        }
+        Command::Edit {
+            id,
+            name,
+            notice,
+            set,
+            stdin,
+            custom,
+            hidden,
+            folder,
+            tag,
+            untag,
+            favorite,
+            no_favorite,
+        } => {
+            let mut entry = vault.get(id)?;
+            if let Some(name) = name {
+                entry.name = name;
+            }
+            if let Some(notice) = notice {
+                entry.notice = notice;
+            }
+            apply_fields(&mut entry.data, &set, stdin)?;
+            apply_custom_fields(&mut entry.custom_fields, &custom, &hidden)?;
+            for unwanted in &untag {
+                tag::remove(&mut entry.labels.tags, unwanted);
+            }
+            for wanted in &tag {
+                tag::add(&mut entry.labels.tags, wanted)?;
+            }
+            if favorite || no_favorite {
+                entry.labels.favorite = favorite;
+            }
+            match folder {
+                Some(path) => vault.update_and_move(&entry, resolve_folder(&vault, &path)?)?,
+                None => vault.update(&entry)?,
+            }
+        }
+        Command::Generate(_) | Command::Passphrase(_) => {
+            unreachable!("генераторы выполняются без хранилища")
+        }
+    }
+
+    Ok(())
+}
+
+fn passphrase(args: PassphraseArgs) -> Result<(), Failure> {
+    let words: Vec<String> = match args.wordlist {
+        Some(path) => load_wordlist(&path)?,
+        None => {
+            let wordlist = match args.lang {
+                Lang::En => Wordlist::English,
+                Lang::Ru => Wordlist::Russian,
+            };
+            wordlist
+                .words()
+                .iter()
+                .map(|word| word.to_string())
+                .collect()
+        }
+    };
+    let policy = PassphrasePolicy {
+        words: args
+            .words
+            .unwrap_or_else(|| PassphrasePolicy::words_for(words.len())),
+        separator: args.separator,
+        capitalize: args.capitalize,
+        append_digit: args.digit,
+        append_symbol: args.symbol,
+    };
+
+    let passphrase = generate_passphrase(&words, &policy)?;
+
+    println!("{}", passphrase.phrase.as_str());
+    eprintln!("энтропия: {:.1} бит", passphrase.entropy_bits);
+    Ok(())
+}
+
+fn open_vault(login: Option<String>) -> Result<Vault, PassHiveError> {
+    let login = match login {
+        Some(login) => login,
+        None => {
+            return Err(PassHiveError::InvalidInput(
+                "укажите логин через --login или PASSHIVE_LOGIN".to_string(),
+            ));
+        }
+    };
+
+    let (exists, path, _) = os_work::verify_data();
+    if !exists {
+        os_work::init_dir(&path)?;
+    }
+
+    let mut vault = Vault::open(&path)?;
+    if let Some(backup) = vault.migration_backup() {
+        eprintln!(
+            "passhive: схема БД обновлена, копия до обновления: {}",
+            backup.display()
+        );
+    }
+
+    let password = match std::env::var("PASSHIVE_PASSWORD") {
+        Ok(password) => Zeroizing::new(password),
+        Err(_) => Zeroizing::new(rpassword::prompt_password("Пароль: ")?),
+    };
+
+    vault.unlock(&login, &password)?;
+    Ok(vault)
+}
+
+fn find_by_name(vault: &Vault, name: &str) -> Result<ShowableData, Failure> {
+    let mut found: Vec<ShowableData> = vault
+        .list()?
+        .into_iter()
+        .filter(|entry| entry.name == name)
+        .collect();
+
+    match found.len() {
+        0 => Err(Failure::NotFound(name.to_string())),
+        1 => Ok(found.remove(0)),
+        count => Err(Failure::Ambiguous(name.to_string(), count)),
+    }
+}
+
+// --set поле=значение, затем секрет из stdin, если он запрошен
+fn apply_fields(
+    data: &mut DataType,
+    set: &[String],
+    from_stdin: bool,
+) -> Result<(), PassHiveError> {
+    // Паспорт из stdin читается как MRZ, --set дополняет его полями не из MRZ
+    let mrz = from_stdin && matches!(data, DataType::Passport { .. });
+    if mrz {
+        let mut text = Zeroizing::new(String::new());
+        stdin().read_to_string(&mut text)?;
+        let mut parsed = passport::parse_mrz(&text)?;
+        if let (
+            DataType::Passport {
+                authority,
+                issue_date,
+                ..
+            },
+            DataType::Passport {
+                authority: old_authority,
+                issue_date: old_issue_date,
+                ..
+            },
+        ) = (&mut parsed, &mut *data)
+        {
+            *authority = std::mem::take(old_authority);
+            *issue_date = std::mem::take(old_issue_date);
+        }
+        *data = parsed;
+    }
+
+    for pair in set {
+        match pair.split_once('=') {
+            Some((field, value)) => data.set_field(field, value)?,
+            None => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "ожидается ПОЛЕ=ЗНАЧЕНИЕ, получено {}",
+                    pair
+                )));
+            }
+        }
+    }
+
+    if from_stdin && !mrz {
+        let mut secret = Zeroizing::new(String::new());
+        stdin().read_to_string(&mut secret)?;
+        let trimmed = secret.trim_end_matches('\n').trim_end_matches('\r');
+        data.set_field(&data.secret_field(), trimmed)?;
+    }
+
+    // Секрет otp можно передать otpauth:// ссылкой - она задает все параметры
+    if let DataType::Otp { secret, .. } = data
+        && secret.starts_with("otpauth://")
+    {
+        *data = otp::parse_otpauth(secret)?;
+    }
+
+    // Номер карты можно передать с пробелами, как он напечатан на карте
+    if let DataType::Card { num, .. } = data {
+        *num = card::normalize_number(num);
+    }
+    card::validate(data)?;
+    passport::validate(data)?;
+    if let DataType::SshKey { .. } = data {
+        *data = ssh::normalize(data)?;
+    }
+
+    Ok(())
+}
+
+fn find_folder<'a>(folders: &'a [Folder], path: &str) -> Result<&'a Folder, PassHiveError> {
+    folder::find(folders, path).ok_or_else(|| {
+        PassHiveError::InvalidInput(format!(
+            "нет папки {}; создайте ее: passhive folder add {}",
+            path, path
+        ))
+    })
+}
+
+// "/" и пустой путь - вне папок
+fn resolve_folder(vault: &Vault, path: &str) -> Result<Option<i64>, PassHiveError> {
+    if path
+        .trim()
+        .trim_matches(folder::SEPARATOR)
+        .trim()
+        .is_empty()
+    {
+        return Ok(None);
+    }
+    Ok(Some(find_folder(&vault.folders()?, path)?.id))
+}
+
+fn run_attachment(vault: &Vault, action: AttachmentAction, format: Format) -> Result<(), Failure> {
+    match action {
+        AttachmentAction::Add {
+            entry,
+            file,
+            name,
+            mime,
+        } => {
+            let entry = find_by_name(vault, &entry)?;
+            let id = match file.to_str() {
+                Some("-") => {
+                    let name = name.ok_or_else(|| {
+                        PassHiveError::InvalidInput(
+                            "для вложения из stdin укажите --name".to_string(),
+                        )
+                    })?;
+                    vault.attach(entry.id, &name, mime.as_deref(), stdin().lock())?
+                }
+                _ => {
+                    let name = match name {
+                        Some(name) => name,
+                        None => file
+                            .file_name()
+                            .map(|name| name.to_string_lossy().to_string())
+                            .unwrap_or_default(),
+                    };
+                    let reader = BufReader::new(File::open(&file).map_err(PassHiveError::from)?);
+                    vault.attach(entry.id, &name, mime.as_deref(), reader)?
+                }
+            };
+            println!("{}", id);
+        }
+        AttachmentAction::List { entry } => {
+            let entry = match entry {
+                Some(name) => Some(find_by_name(vault, &name)?.id),
+                None => None,
+            };
+            print_attachments(&vault.attachments(entry)?, format)?;
+        }
+        AttachmentAction::Extract { id, path } => {
+            let found = vault
+                .attachments(None)?
+                .into_iter()
+                .find(|attachment| attachment.id == id)
+                .ok_or(PassHiveError::NoSuchAttachment(id))?;
+
+            if path.to_str() == Some("-") {
+                vault.extract_attachment(id, stdout().lock())?;
+                return Ok(());
+            }
+
+            let target = attachment::target_path(&path, &found.filename);
+            let file = attachment::create_private_file(&target)?;
+            // Недописанный файл не оставляем: его можно принять за целое вложение
+            if let Err(e) = vault.extract_attachment(id, BufWriter::new(file)) {
+                let _ = fs::remove_file(&target);
+                return Err(e.into());
+            }
+            println!("{}", target.display());
+        }
+        AttachmentAction::Rm { id } => vault.delete_attachment(id)?,
+    }
+    Ok(())
+}
+
+// --custom и --hidden ИМЯ=ЗНАЧЕНИЕ: поле с тем же именем заменяется, пустое значение удаляет его
+fn apply_custom_fields(
+    fields: &mut Vec<CustomField>,
+    custom: &[String],
+    hidden: &[String],
+) -> Result<(), PassHiveError> {
+    let pairs = custom
+        .iter()
+        .map(|pair| (pair, false))
+        .chain(hidden.iter().map(|pair| (pair, true)));
+
+    for (pair, hidden) in pairs {
+        match pair.split_once('=') {
+            Some((name, value)) => {
+                custom_fields::validate_name(name.trim())?;
+                custom_fields::set(fields, name, value, hidden);
+            }
+            None => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "ожидается ИМЯ=ЗНАЧЕНИЕ, получено {}",
+                    pair
+                )));
+            }
+        }
+    }
+
+    custom_fields::validate(fields)
+}
+
+#[cfg(unix)]
+fn run_ssh_agent(vault: &Vault, socket: Option<PathBuf>) -> Result<(), PassHiveError> {
+    let socket = match socket {
+        Some(socket) => socket,
+        None => ssh_agent::default_socket_path()?,
+    };
+    let keys = ssh::collect_keys(&vault.list()?);
+    if keys.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "в хранилище нет ключей SSH".to_string(),
+        ));
+    }
+
+    let signals = os_work::block_termination_signals();
+    let agent = SshAgent::start(&socket, keys)?;
+    // Тот же вывод, что у ssh-agent -s: eval "$(passhive ssh-agent)"
+    println!(
+        "SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;",
+        agent.socket().display()
+    );
+    eprintln!(
+        "агент SSH запущен, ключей: {}. Остановка - Ctrl+C",
+        agent.key_count()
+    );
+
+    os_work::wait_for_signal(&signals);
+    drop(agent);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn run_ssh_agent(_vault: &Vault, _socket: Option<PathBuf>) -> Result<(), PassHiveError> {
+    Err(PassHiveError::InvalidInput(
+        "агент SSH работает только в Unix".to_string(),
+    ))
+}
+
+// Для json и yaml: записи с путями их папок
+fn with_folders<'a>(
+    entries: &'a [ShowableData],
+    folders: &'a [Folder],
+) -> Vec<EntryWithFolder<'a>> {
+    entries
+        .iter()
+        .map(|entry| EntryWithFolder {
+            entry,
+            folder: folder_path(folders, entry),
+        })
+        .collect()
+}
+
+fn folder_path<'a>(folders: &'a [Folder], entry: &ShowableData) -> Option<&'a str> {
+    folder::by_id(folders, entry.folder?).map(|folder| folder.path.as_str())
+}
+
+fn print_list(
+    entries: &[ShowableData],
+    folders: &[Folder],
+    format: Format,
+) -> Result<(), PassHiveError> {
+    match format {
+        Format::Text => {
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}",
+                    entry.id,
+                    entry.data.formal_name(),
+                    escape_tsv(&entry.name).as_str()
+                );
+            }
+        }
+        Format::Json => {
+            let entries = with_folders(entries, folders);
+            println!("{}", Zeroizing::new(to_json(&entries)?).as_str())
+        }
+        Format::Yaml => {
+            let entries = with_folders(entries, folders);
+            print!("{}", Zeroizing::new(to_yaml(&entries)?).as_str())
+        }
+        Format::Tsv => {
+            println!("id\ttype\tname\tnotice");
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.id,
+                    entry.data.formal_name(),
+                    escape_tsv(&entry.name).as_str(),
+                    escape_tsv(&entry.notice).as_str()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_entry(
+    entry: &ShowableData,
+    folders: &[Folder],
+    format: Format,
+) -> Result<(), PassHiveError> {
+    let folder = folder_path(folders, entry);
+    match format {
+        Format::Text => {
+            println!("id: {}", entry.id);
+            println!("type: {}", entry.data.formal_name());
+            println!("name: {}", entry.name);
+            println!("notice: {}", entry.notice);
+            println!("tags: {}", entry.labels.tags.join(","));
+            println!("favorite: {}", entry.labels.favorite);
+            println!("folder: {}", folder.unwrap_or(""));
+            for (field, value) in entry.data.fields()? {
+                let value = Zeroizing::new(value);
+                println!("{}: {}", field, value.as_str());
+            }
+            for field in &entry.custom_fields {
+                println!("{}{}: {}", custom_fields::PREFIX, field.name, field.value);
+            }
+        }
+        Format::Json => {
+            let entry = EntryWithFolder { entry, folder };
+            println!("{}", Zeroizing::new(to_json(&entry)?).as_str())
+        }
+        Format::Yaml => {
+            let entry = EntryWithFolder { entry, folder };
+            print!("{}", Zeroizing::new(to_yaml(&entry)?).as_str())
+        }
+        Format::Tsv => {
+            println!("id\t{}", entry.id);
+            println!("type\t{}", entry.data.formal_name());
+            println!("name\t{}", escape_tsv(&entry.name).as_str());
+            println!("notice\t{}", escape_tsv(&entry.notice).as_str());
+            println!(
+                "tags\t{}",
+                escape_tsv(&entry.labels.tags.join(",")).as_str()
+            );
+            println!("favorite\t{}", entry.labels.favorite);
+            println!("folder\t{}", escape_tsv(folder.unwrap_or("")).as_str());
+            for (field, value) in entry.data.fields()? {
+                let value = Zeroizing::new(value);
+                println!("{}\t{}", field, escape_tsv(&value).as_str());
+            }
+            for field in &entry.custom_fields {
+                println!(
+                    "{}{}\t{}",
+                    custom_fields::PREFIX,
+                    escape_tsv(&field.name).as_str(),
+                    escape_tsv(&field.value).as_str()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_field(entry: &ShowableData, field: &str, format: Format) -> Result<(), PassHiveError> {
+    let value = match field.strip_prefix(custom_fields::PREFIX) {
+        Some(name) => match entry
+            .custom_fields
+            .iter()
+            .find(|custom| custom.name == name)
+        {
+            Some(custom) => serde_json::Value::String(custom.value.clone()),
+            None => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "у записи {} нет дополнительного поля {}",
+                    entry.name, name
+                )));
+            }
+        },
+        None => match entry.data.field_map()?.remove(field) {
+            Some(value) => value,
+            None => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "у типа {} нет поля {}",
+                    entry.data.formal_name(),
+                    field
+                )));
+            }
+        },
+    };
+
+    match format {
+        Format::Json => println!("{}", Zeroizing::new(to_json(&value)?).as_str()),
+        Format::Yaml => print!("{}", Zeroizing::new(to_yaml(&value)?).as_str()),
+        Format::Text | Format::Tsv => {
+            let text = Zeroizing::new(match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            });
+            match format {
+                Format::Tsv => println!("{}", escape_tsv(&text).as_str()),
+                _ => println!("{}", text.as_str()),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_templates(templates: &[Template], format: Format) -> Result<(), PassHiveError> {
+    let fields = |template: &Template| {
+        template
+            .fields
+            .iter()
+            .map(|field| format!("{}:{}", field.name, field.kind.formal_name()))
+            .collect::<Vec<String>>()
+            .join(",")
+    };
+
+    match format {
+        Format::Text => {
+            for template in templates {
+                println!(
+                    "{}\t{}",
+                    escape_tsv(&template.name).as_str(),
+                    fields(template)
+                );
+            }
+        }
+        Format::Json => println!("{}", to_json(templates)?),
+        Format::Yaml => print!("{}", to_yaml(templates)?),
+        Format::Tsv => {
+            println!("name\tfields");
+            for template in templates {
+                println!(
+                    "{}\t{}",
+                    escape_tsv(&template.name).as_str(),
+                    escape_tsv(&fields(template)).as_str()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_attachments(attachments: &[Attachment], format: Format) -> Result<(), PassHiveError> {
+    match format {
+        Format::Text => {
+            for attachment in attachments {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    attachment.id,
+                    attachment.entry,
+                    attachment.size,
+                    escape_tsv(&attachment.mime).as_str(),
+                    escape_tsv(&attachment.filename).as_str()
+                );
+            }
+        }
+        Format::Json => println!("{}", to_json(attachments)?),
+        Format::Yaml => print!("{}", to_yaml(attachments)?),
+        Format::Tsv => {
+            println!("id\tentry\tsize\tmime\tfilename\tcreated_at");
+            for attachment in attachments {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    attachment.id,
+                    attachment.entry,
+                    attachment.size,
+                    escape_tsv(&attachment.mime).as_str(),
+                    escape_tsv(&attachment.filename).as_str(),
+                    attachment.created_at
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct CountedTag<'a> {
+    tag: &'a str,
+    entries: usize,
+}
+
+fn print_tags(counted: &[(String, usize)], format: Format) -> Result<(), PassHiveError> {
+    let listed: Vec<CountedTag> = counted
+        .iter()
+        .map(|(tag, entries)| CountedTag {
+            tag,
+            entries: *entries,
+        })
+        .collect();
+
+    match format {
+        Format::Text => {
+            for tag in &listed {
+                println!("{}\t{}", escape_tsv(tag.tag).as_str(), tag.entries);
+            }
+        }
+        Format::Json => println!("{}", to_json(&listed)?),
+        Format::Yaml => print!("{}", to_yaml(&listed)?),
+        Format::Tsv => {
+            println!("tag\tentries");
+            for tag in &listed {
+                println!("{}\t{}", escape_tsv(tag.tag).as_str(), tag.entries);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ListedFolder<'a> {
+    id: i64,
+    path: &'a str,
+    entries: i64,
+}
+
+fn print_folders(
+    folders: &[Folder],
+    counts: &HashMap<i64, i64>,
+    format: Format,
+) -> Result<(), PassHiveError> {
+    let listed: Vec<ListedFolder> = folders
+        .iter()
+        .map(|folder| ListedFolder {
+            id: folder.id,
+            path: &folder.path,
+            entries: counts.get(&folder.id).copied().unwrap_or(0),
+        })
+        .collect();
+
+    match format {
+        Format::Text => {
+            for folder in &listed {
+                println!("{}\t{}", escape_tsv(folder.path).as_str(), folder.entries);
+            }
+        }
+        Format::Json => println!("{}", to_json(&listed)?),
+        Format::Yaml => print!("{}", to_yaml(&listed)?),
+        Format::Tsv => {
+            println!("id\tpath\tentries");
+            for folder in &listed {
+                println!(
+                    "{}\t{}\t{}",
+                    folder.id,
+                    escape_tsv(folder.path).as_str(),
+                    folder.entries
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct BreachedEntry<'a> {
+    id: i64,
+    #[serde(rename = "type")]
+    kind: String,
+    name: &'a str,
+    count: u64,
+}
+
+fn print_breached(
+    entries: &[ShowableData],
+    breached: &HashMap<i64, u64>,
+    format: Format,
+) -> Result<(), PassHiveError> {
+    let found: Vec<BreachedEntry> = entries
+        .iter()
+        .filter_map(|entry| {
+            breached.get(&entry.id).map(|count| BreachedEntry {
+                id: entry.id,
+                kind: entry.data.formal_name(),
+                name: &entry.name,
+                count: *count,
+            })
+        })
+        .collect();
+
+    match format {
+        Format::Text => {
+            for entry in found.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.id,
+                    entry.kind,
+                    escape_tsv(entry.name).as_str(),
+                    entry.count
+                );
+            }
+        }
+        Format::Json => println!("{}", to_json(&found)?),
+        Format::Yaml => print!("{}", to_yaml(&found)?),
+        Format::Tsv => {
+            println!("id\ttype\tname\tcount");
+            for entry in found.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.id,
+                    entry.kind,
+                    escape_tsv(entry.name).as_str(),
+                    entry.count
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, PassHiveError> {
+    serde_json::to_string_pretty(value)
+        .map_err(|e| PassHiveError::InvalidInput(format!("ошибка сериализации JSON: {}", e)))
+}
+
+fn to_yaml<T: serde::Serialize + ?Sized>(value: &T) -> Result<String, PassHiveError> {
+    serde_yaml::to_string(value)
+        .map_err(|e| PassHiveError::InvalidInput(format!("ошибка сериализации YAML: {}", e)))
+}
+
+fn escape_tsv(value: &str) -> Zeroizing<String> {
+    Zeroizing::new(
+        value
+            .replace('\\', "\\\\")
+            .replace('\t', "\\t")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r"),
+    )
+}
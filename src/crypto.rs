@@ -1,62 +1,74 @@
-use crate::db::models::DataType;
+use crate::db::models::{
+    CustomField, DataType, EncryptedFields, KdfAlgorithm, KdfParams, Labels, WrappedKey,
+};
+use crate::error::PassHiveError;
+use crate::secret::SecretKey;
 use argon2::{
     Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version,
     password_hash::{PasswordHasher, SaltString},
 };
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
 use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce, aead::AeadMut};
-use indicatif::*;
 use rand::TryRngCore;
 use rand_core::OsRng;
 
-use std::time::Duration;
+use std::io::{ErrorKind, Read};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
 
-fn vec_to_str(vec: &Vec<u8>) -> String {
-    String::from_utf8(vec.clone()).unwrap()
+// Без копирования: расшифрованный буфер становится строкой, а не остается в памяти рядом с ней
+fn vec_to_str(vec: Vec<u8>) -> Result<String, PassHiveError> {
+    String::from_utf8(vec).map_err(|e| {
+        // Невалидный UTF-8 тоже секрет - затираем его перед возвратом ошибки
+        let _ = Zeroizing::new(e.into_bytes());
+        PassHiveError::Crypto("расшифрованный текст не является UTF-8".to_string())
+    })
 }
 
-pub fn encode(password: String) -> Result<(String, String), String> {
-    let spinner = ProgressBar::new_spinner();
+pub fn create_salt() -> Result<String, PassHiveError> {
     let mut raw_salt: [u8; 32] = [0; 32];
 
-    spinner.set_style(
-        ProgressStyle::default_spinner()
-            .tick_strings(&[
-                "[    ]", "[=   ]", "[==  ]", "[=== ]", "[ ===]", "[  ==]", "[   =]", "[    ]",
-            ])
-            .template("{spinner} {msg}")
-            .expect("Ошибка шаблона прогрессбара"),
-    );
-    spinner.set_message(
-        "🐝Генерация хэша пароля... Не переживайте, обычно это занимает до 10 секунд!🐝",
-    );
-    spinner.enable_steady_tick(Duration::from_millis(120));
-
     if let Err(_) = OsRng.try_fill_bytes(&mut raw_salt) {
-        return Err("Ошибка при генерации соли в битовом виде".to_string());
+        return Err(PassHiveError::Crypto(
+            "Ошибка при генерации соли в битовом виде".to_string(),
+        ));
     }
 
-    let salt = SaltString::encode_b64(&raw_salt);
-
-    if let Err(_) = salt {
-        return Err("Ошибка генерации соли".to_string());
+    match SaltString::encode_b64(&raw_salt) {
+        Ok(salt) => Ok(salt.to_string()),
+        Err(_) => Err(PassHiveError::Crypto("Ошибка генерации соли".to_string())),
     }
+}
 
-    let salt = salt.unwrap();
+fn argon2_with(params: &KdfParams, output_len: usize) -> Result<Argon2<'static>, PassHiveError> {
+    let config = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        Some(output_len),
+    )
+    .map_err(|e| PassHiveError::Crypto(format!("Ошибка конфигуратора Argon2: {}", e)))?;
 
-    let config = Params::new(65536, 10, 3, Some(64));
+    let algorithm = match params.algorithm {
+        KdfAlgorithm::Argon2id => Algorithm::Argon2id,
+        KdfAlgorithm::Argon2i => Algorithm::Argon2i,
+        KdfAlgorithm::Argon2d => Algorithm::Argon2d,
+    };
 
-    if let Err(_) = config {
-        return Err("Ошибка конфигуратора Argon2".to_string());
-    }
+    Ok(Argon2::new(algorithm, Version::V0x13, config))
+}
 
-    let config = config.unwrap();
+pub fn encode(password: &str, params: &KdfParams) -> Result<(String, String), PassHiveError> {
+    let salt = SaltString::from_b64(create_salt()?.as_str())
+        .map_err(|e| PassHiveError::Crypto(e.to_string()))?;
 
-    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, config);
+    let argon2 = argon2_with(params, 64)?;
 
     let hash = argon2.hash_password(password.as_bytes(), &salt);
 
     if let Err(_) = hash {
-        return Err("Ошибка хэширования".to_string());
+        return Err(PassHiveError::Crypto("Ошибка хэширования".to_string()));
     }
 
     let hash = hash.unwrap();
@@ -64,111 +76,514 @@ pub fn encode(password: String) -> Result<(String, String), String> {
     Ok((hash.to_string(), salt.to_string()))
 }
 
-pub fn check_password(hash: &String, pass: &String) -> Result<bool, String> {
+pub fn check_password(hash: &str, pass: &str) -> Result<bool, PassHiveError> {
     match PasswordHash::new(hash) {
         Ok(parsed) => {
-            let spinner = ProgressBar::new_spinner();
-            spinner.set_style(
-                ProgressStyle::default_spinner()
-                    .tick_strings(&[
-                        "[    ]", "[=   ]", "[==  ]", "[=== ]", "[ ===]", "[  ==]", "[   =]",
-                        "[    ]",
-                    ])
-                    .template("{spinner} {msg}")
-                    .expect("Ошибка шаблона прогрессбара"),
-            );
-            spinner.set_message(
-                "🐝Сверяем пароль с хэшем... Не переживайте, обычно это занимает не более 10 секунд!🐝",
-            );
-            spinner.enable_steady_tick(Duration::from_millis(120));
             let argon2 = Argon2::default();
-            return Ok(argon2.verify_password(pass.as_bytes(), &parsed).is_ok());
+            Ok(argon2.verify_password(pass.as_bytes(), &parsed).is_ok())
         }
-        Err(e) => return Err(e.to_string()),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
     }
+}
+
+// Размер части вложения до шифрования: в памяти одновременно не больше двух частей
+pub const CHUNK_SIZE: usize = 64 * 1024;
 
-    Err("Ошибка выхода из match в check_password!".to_string())
+// Префикс nonce потока STREAM: 24 байта nonce минус 4 байта счетчика и 1 байт признака последней части
+pub type StreamNonce = [u8; 19];
+
+pub fn create_stream_nonce() -> Result<StreamNonce, PassHiveError> {
+    let mut total = [0u8; 19];
+    match OsRng.try_fill_bytes(&mut total) {
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
+        Ok(()) => Ok(total),
+    }
 }
 
-pub fn create_nonce() -> Result<[u8; 24], String> {
+pub fn create_nonce() -> Result<[u8; 24], PassHiveError> {
     let mut total = [0u8; 24];
     match OsRng.try_fill_bytes(&mut total) {
-        Err(e) => return Err(e.to_string()),
-        Ok(()) => return Ok(total),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
+        Ok(()) => Ok(total),
     }
 }
 
-pub fn create_crypto_key(salt: &str, password: &str) -> Result<[u8; 32], String> {
-    let argon2 = Argon2::default();
-    let mut out = [0u8; 32];
-    match argon2.hash_password_into(password.as_bytes(), salt.as_bytes(), &mut out) {
-        Ok(()) => return Ok(out),
-        Err(e) => return Err(e.to_string()),
+pub fn create_crypto_key(
+    salt: &str,
+    password: &str,
+    params: &KdfParams,
+) -> Result<SecretKey, PassHiveError> {
+    let argon2 = argon2_with(params, 32)?;
+    let mut out = SecretKey::zeroed();
+    match argon2.hash_password_into(password.as_bytes(), salt.as_bytes(), out.as_mut_bytes()) {
+        Ok(()) => Ok(out),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
     }
 }
 
-pub fn encrypt_str_with_nonce(
-    string: &str,
-    key: &[u8; 32],
-    nonce: &[u8; 24],
-) -> Result<Vec<u8>, String> {
-    let mut cipher = XChaCha20Poly1305::new(Key::from_slice(key));
-    let xnonce = XNonce::from_slice(nonce);
+// Подбирает число итераций так, чтобы вход занимал около target.
+// При входе Argon2 считается дважды (проверка хэша и ключ из пароля),
+// поэтому на одно вычисление отводится половина времени.
+pub fn calibrate_kdf(
+    target: Duration,
+    algorithm: KdfAlgorithm,
+    memory_kib: u32,
+    parallelism: u32,
+) -> Result<KdfParams, PassHiveError> {
+    let probe = KdfParams {
+        algorithm,
+        memory_kib,
+        iterations: 1,
+        parallelism,
+    };
+    let salt = create_salt()?;
 
-    match cipher.encrypt(xnonce, string.as_bytes()) {
-        Ok(enc) => return Ok(enc),
-        Err(e) => Err(e.to_string()),
+    let start = Instant::now();
+    create_crypto_key(&salt, "calibration", &probe)?;
+    let one_iteration = start.elapsed().as_secs_f64();
+
+    let per_derivation = target.as_secs_f64() / 2.0;
+    let iterations = (per_derivation / one_iteration.max(f64::EPSILON)).floor() as u32;
+
+    Ok(KdfParams {
+        iterations: iterations.max(1),
+        ..probe
+    })
+}
+
+// Ключ хранилища: случайный, шифрует записи в users.
+// В meta лежит только его копия, зашифрованная ключом из пароля (KEK).
+pub fn create_vault_key() -> Result<SecretKey, PassHiveError> {
+    let mut key = SecretKey::zeroed();
+    match OsRng.try_fill_bytes(key.as_mut_bytes()) {
+        Err(e) => Err(PassHiveError::Crypto(format!(
+            "Ошибка генерации ключа хранилища: {}",
+            e
+        ))),
+        Ok(()) => Ok(key),
     }
 }
 
-pub fn encrypt_str(string: &str, key: &[u8; 32]) -> Result<(Vec<u8>, [u8; 24]), String> {
-    let new_nonce;
-    match create_nonce() {
-        Ok(n) => new_nonce = n,
-        Err(e) => return Err(e.to_string()),
+pub fn wrap_key(vault_key: &[u8; 32], kek: &[u8; 32]) -> Result<WrappedKey, PassHiveError> {
+    let nonce = create_nonce()?;
+    let mut cipher = XChaCha20Poly1305::new(Key::from_slice(kek));
+
+    match cipher.encrypt(XNonce::from_slice(&nonce), vault_key.as_ref()) {
+        Ok(wrapped) => Ok((wrapped, nonce)),
+        Err(e) => Err(PassHiveError::Crypto(format!(
+            "Ошибка шифрования ключа хранилища: {}",
+            e
+        ))),
+    }
+}
+
+// Ключ из неверного пароля не проходит проверку тега AEAD - это WrongPassword
+pub fn unwrap_key(
+    wrapped: &[u8],
+    nonce: &[u8; 24],
+    kek: &[u8; 32],
+) -> Result<SecretKey, PassHiveError> {
+    let mut cipher = XChaCha20Poly1305::new(Key::from_slice(kek));
+
+    let raw = Zeroizing::new(
+        cipher
+            .decrypt(XNonce::from_slice(nonce), wrapped)
+            .map_err(|_| PassHiveError::WrongPassword)?,
+    );
+
+    if raw.len() != 32 {
+        return Err(PassHiveError::Crypto(
+            "Неверная длина ключа хранилища".to_string(),
+        ));
     }
 
+    let mut key = SecretKey::zeroed();
+    key.as_mut_bytes().copy_from_slice(&raw);
+    Ok(key)
+}
+
+pub fn encrypt_str(string: &str, key: &[u8; 32]) -> Result<(Vec<u8>, [u8; 24]), PassHiveError> {
+    let new_nonce = create_nonce()?;
+
     let mut cipher = XChaCha20Poly1305::new(Key::from_slice(key));
     let xnonce = XNonce::from_slice(&new_nonce);
 
     match cipher.encrypt(xnonce, string.as_bytes()) {
-        Ok(enc) => return Ok((enc, new_nonce)),
-        Err(e) => Err(e.to_string()),
+        Ok(enc) => Ok((enc, new_nonce)),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
     }
 }
 
-pub fn encrypt_data(data: &DataType, key: &[u8; 32]) -> Result<(Vec<u8>, [u8; 24]), String> {
-    let serialised;
-    match serde_json::to_string(data) {
-        Ok(ser) => serialised = ser,
-        Err(e) => return Err(e.to_string()),
-    }
+pub fn encrypt_data(data: &DataType, key: &[u8; 32]) -> Result<(Vec<u8>, [u8; 24]), PassHiveError> {
+    let serialised = match serde_json::to_string(data) {
+        Ok(ser) => Zeroizing::new(ser),
+        Err(e) => return Err(PassHiveError::Crypto(e.to_string())),
+    };
 
-    match encrypt_str(serialised.as_str(), key) {
-        Ok((enc, nonce)) => return Ok((enc, nonce)),
-        Err(e) => return Err(e),
-    }
+    encrypt_str(serialised.as_str(), key)
+}
+
+pub fn encrypt_custom_fields(
+    fields: &[CustomField],
+    key: &[u8; 32],
+) -> Result<(Vec<u8>, [u8; 24]), PassHiveError> {
+    let serialised = Zeroizing::new(
+        serde_json::to_string(fields).map_err(|e| PassHiveError::Crypto(e.to_string()))?,
+    );
+    encrypt_str(&serialised, key)
+}
+
+pub fn encrypt_labels(
+    labels: &Labels,
+    key: &[u8; 32],
+) -> Result<(Vec<u8>, [u8; 24]), PassHiveError> {
+    let serialised = Zeroizing::new(
+        serde_json::to_string(labels).map_err(|e| PassHiveError::Crypto(e.to_string()))?,
+    );
+    encrypt_str(&serialised, key)
+}
+
+pub fn encrypt_row(
+    data: &DataType,
+    name: &str,
+    notice: &str,
+    custom_fields: &[CustomField],
+    labels: &Labels,
+    key: &[u8; 32],
+) -> Result<EncryptedFields, PassHiveError> {
+    let (data, nonce) = encrypt_data(data, key)?;
+    let (name, name_nonce) = encrypt_str(name, key)?;
+    let (notice, notice_nonce) = encrypt_str(notice, key)?;
+    let (custom_fields, custom_fields_nonce) = encrypt_custom_fields(custom_fields, key)?;
+    let (labels, labels_nonce) = encrypt_labels(labels, key)?;
+
+    Ok(EncryptedFields {
+        data,
+        nonce,
+        name,
+        name_nonce,
+        notice,
+        notice_nonce,
+        custom_fields,
+        custom_fields_nonce,
+        labels,
+        labels_nonce,
+    })
 }
 
-pub fn decrypt_str(string: &Vec<u8>, nonce: &[u8; 24], key: &[u8; 32]) -> Result<String, String> {
+pub fn decrypt_str(
+    string: &Vec<u8>,
+    nonce: &[u8; 24],
+    key: &[u8; 32],
+) -> Result<String, PassHiveError> {
     let mut cipher = XChaCha20Poly1305::new(Key::from_slice(key));
     let xnonce = XNonce::from_slice(nonce);
 
     match cipher.decrypt(xnonce, string.as_ref()) {
-        Ok(dec) => return Ok(vec_to_str(&dec)),
-        Err(e) => return Err(e.to_string()),
+        Ok(dec) => vec_to_str(dec),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
     }
 }
 
-pub fn decrypt_data(data: &Vec<u8>, nonce: &[u8; 24], key: &[u8; 32]) -> Result<DataType, String> {
+pub fn decrypt_data(
+    data: &Vec<u8>,
+    nonce: &[u8; 24],
+    key: &[u8; 32],
+) -> Result<DataType, PassHiveError> {
     let mut cipher = XChaCha20Poly1305::new(Key::from_slice(key));
     let xnonce = XNonce::from_slice(nonce);
 
     match cipher.decrypt(xnonce, data.as_ref()) {
-        Err(e) => return Err(e.to_string()),
+        Err(e) => Err(PassHiveError::Crypto(e.to_string())),
         Ok(json_text) => {
-            let json_string = vec_to_str(&json_text);
-            return Ok(serde_json::from_str(json_string.as_str()).unwrap());
+            let json_text = Zeroizing::new(json_text);
+            serde_json::from_slice(&json_text)
+                .map_err(|e| PassHiveError::Crypto(format!("неверный формат данных: {}", e)))
         }
     }
 }
+
+pub fn decrypt_custom_fields(
+    data: &Vec<u8>,
+    nonce: &[u8; 24],
+    key: &[u8; 32],
+) -> Result<Vec<CustomField>, PassHiveError> {
+    let json_text = Zeroizing::new(decrypt_str(data, nonce, key)?);
+    serde_json::from_str(&json_text)
+        .map_err(|e| PassHiveError::Crypto(format!("неверный формат дополнительных полей: {}", e)))
+}
+
+pub fn decrypt_labels(
+    data: &Vec<u8>,
+    nonce: &[u8; 24],
+    key: &[u8; 32],
+) -> Result<Labels, PassHiveError> {
+    let json_text = Zeroizing::new(decrypt_str(data, nonce, key)?);
+    serde_json::from_str(&json_text)
+        .map_err(|e| PassHiveError::Crypto(format!("неверный формат тегов: {}", e)))
+}
+
+// Шифрует поток частями по CHUNK_SIZE (конструкция STREAM): у каждой части свой номер,
+// последняя помечена, поэтому переставленные, чужие или отрезанные части не расшифруются.
+// Итератор выдает зашифрованные части; ошибка чтения завершает его
+pub struct StreamEncryptor<R: Read> {
+    reader: R,
+    // None - последняя часть уже выдана
+    encryptor: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    // Часть, прочитанная наперед: последней можно пометить только ее
+    pending: Option<Zeroizing<Vec<u8>>>,
+}
+
+impl<R: Read> StreamEncryptor<R> {
+    pub fn new(reader: R, nonce: &StreamNonce, key: &[u8; 32]) -> Self {
+        StreamEncryptor {
+            reader,
+            encryptor: Some(EncryptorBE32::new(
+                Key::from_slice(key),
+                GenericArray::from_slice(nonce),
+            )),
+            pending: None,
+        }
+    }
+
+    // Read может вернуть меньше запрошенного - дочитываем до CHUNK_SIZE или конца потока
+    fn read_chunk(&mut self) -> Result<Zeroizing<Vec<u8>>, PassHiveError> {
+        let mut chunk = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+        let mut filled = 0;
+        while filled < CHUNK_SIZE {
+            match self.reader.read(&mut chunk[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        chunk.truncate(filled);
+        Ok(chunk)
+    }
+
+    fn next_chunk(&mut self) -> Result<Vec<u8>, PassHiveError> {
+        let current = match self.pending.take() {
+            Some(chunk) => chunk,
+            None => self.read_chunk()?,
+        };
+        let next = self.read_chunk()?;
+
+        let encrypted = match next.is_empty() {
+            true => match self.encryptor.take() {
+                Some(encryptor) => encryptor.encrypt_last(current.as_slice()),
+                None => return Err(PassHiveError::Crypto("поток уже завершен".to_string())),
+            },
+            false => {
+                self.pending = Some(next);
+                match self.encryptor.as_mut() {
+                    Some(encryptor) => encryptor.encrypt_next(current.as_slice()),
+                    None => return Err(PassHiveError::Crypto("поток уже завершен".to_string())),
+                }
+            }
+        };
+        encrypted.map_err(|e| PassHiveError::Crypto(e.to_string()))
+    }
+}
+
+impl<R: Read> Iterator for StreamEncryptor<R> {
+    type Item = Result<Vec<u8>, PassHiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.encryptor.as_ref()?;
+        let chunk = self.next_chunk();
+        if chunk.is_err() {
+            self.encryptor = None;
+        }
+        Some(chunk)
+    }
+}
+
+pub struct StreamDecryptor {
+    // None - последняя часть уже расшифрована
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+}
+
+impl StreamDecryptor {
+    pub fn new(nonce: &StreamNonce, key: &[u8; 32]) -> Self {
+        StreamDecryptor {
+            decryptor: Some(DecryptorBE32::new(
+                Key::from_slice(key),
+                GenericArray::from_slice(nonce),
+            )),
+        }
+    }
+
+    // last - часть последняя из сохраненных; если она не была последней при шифровании,
+    // расшифровка не пройдет - так обнаруживается отрезанный конец
+    pub fn decrypt_chunk(
+        &mut self,
+        chunk: &[u8],
+        last: bool,
+    ) -> Result<Zeroizing<Vec<u8>>, PassHiveError> {
+        let decrypted = match (last, self.decryptor.take()) {
+            (true, Some(decryptor)) => decryptor.decrypt_last(chunk),
+            (false, Some(mut decryptor)) => {
+                let decrypted = decryptor.decrypt_next(chunk);
+                self.decryptor = Some(decryptor);
+                decrypted
+            }
+            (_, None) => return Err(PassHiveError::Crypto("поток уже завершен".to_string())),
+        };
+        decrypted
+            .map(Zeroizing::new)
+            .map_err(|e| PassHiveError::Crypto(e.to_string()))
+    }
+
+    // Поток без последней части неполон
+    pub fn finish(self) -> Result<(), PassHiveError> {
+        match self.decryptor {
+            None => Ok(()),
+            Some(_) => Err(PassHiveError::Crypto("поток оборван".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrapped_key_unwraps_only_with_its_kek() {
+        let vault_key = create_vault_key().unwrap();
+        let kek = create_vault_key().unwrap();
+        let (wrapped, nonce) = wrap_key(&vault_key, &kek).unwrap();
+
+        assert_ne!(wrapped[..32], vault_key[..]);
+        assert_eq!(*unwrap_key(&wrapped, &nonce, &kek).unwrap(), *vault_key);
+
+        let other = create_vault_key().unwrap();
+        assert!(matches!(
+            unwrap_key(&wrapped, &nonce, &other),
+            Err(PassHiveError::WrongPassword)
+        ));
+    }
+
+    #[test]
+    fn kek_depends_on_password_and_salt() {
+        let params = KdfParams {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let salt = create_salt().unwrap();
+        let key = create_crypto_key(&salt, "pw1", &params).unwrap();
+
+        assert_eq!(*create_crypto_key(&salt, "pw1", &params).unwrap(), *key);
+        assert_ne!(*create_crypto_key(&salt, "pw2", &params).unwrap(), *key);
+        let other_salt = create_salt().unwrap();
+        assert_ne!(
+            *create_crypto_key(&other_salt, "pw1", &params).unwrap(),
+            *key
+        );
+    }
+
+    #[test]
+    fn vault_key_decrypts_rows_it_encrypted() {
+        let key = create_vault_key().unwrap();
+        let (data, nonce) = encrypt_str("секрет", &key).unwrap();
+
+        assert_eq!(decrypt_str(&data, &nonce, &key).unwrap(), "секрет");
+        let other = create_vault_key().unwrap();
+        assert!(decrypt_str(&data, &nonce, &other).is_err());
+    }
+
+    #[test]
+    fn new_rows_never_share_a_nonce() {
+        let key = [1u8; 32];
+        let data = DataType::Password {
+            password: "hunter2".to_string(),
+            username: String::new(),
+            urls: Vec::new(),
+            totp: None,
+        };
+        let first = encrypt_row(&data, "github", "", &[], &Labels::default(), &key).unwrap();
+        let second = encrypt_row(&data, "github", "", &[], &Labels::default(), &key).unwrap();
+
+        let nonces = [
+            first.nonce,
+            first.name_nonce,
+            first.notice_nonce,
+            first.custom_fields_nonce,
+            first.labels_nonce,
+            second.nonce,
+            second.name_nonce,
+        ];
+        for (i, nonce) in nonces.iter().enumerate() {
+            assert!(!nonces[..i].contains(nonce));
+        }
+        assert_ne!(first.name, second.name);
+    }
+
+    fn encrypt_stream(plain: &[u8], nonce: &StreamNonce, key: &[u8; 32]) -> Vec<Vec<u8>> {
+        StreamEncryptor::new(plain, nonce, key)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn decrypt_stream(
+        chunks: &[Vec<u8>],
+        nonce: &StreamNonce,
+        key: &[u8; 32],
+    ) -> Result<Vec<u8>, PassHiveError> {
+        let mut decryptor = StreamDecryptor::new(nonce, key);
+        let mut plain = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            plain.extend_from_slice(&decryptor.decrypt_chunk(chunk, i + 1 == chunks.len())?);
+        }
+        decryptor.finish()?;
+        Ok(plain)
+    }
+
+    #[test]
+    fn stream_round_trips_on_chunk_boundaries() {
+        let key = create_vault_key().unwrap();
+        let nonce = create_stream_nonce().unwrap();
+
+        // Пустой поток - одна пустая последняя часть; ровно CHUNK_SIZE - одна часть
+        for (len, parts) in [
+            (0, 1),
+            (1, 1),
+            (CHUNK_SIZE, 1),
+            (CHUNK_SIZE + 1, 2),
+            (2 * CHUNK_SIZE + 100, 3),
+        ] {
+            let plain: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let chunks = encrypt_stream(&plain, &nonce, &key);
+            assert_eq!(chunks.len(), parts, "длина {}", len);
+            assert_eq!(decrypt_stream(&chunks, &nonce, &key).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn stream_detects_tampering_reordering_and_truncation() {
+        let key = create_vault_key().unwrap();
+        let nonce = create_stream_nonce().unwrap();
+        let plain = vec![7u8; 2 * CHUNK_SIZE + 10];
+        let chunks = encrypt_stream(&plain, &nonce, &key);
+
+        let mut tampered = chunks.clone();
+        tampered[1][5] ^= 1;
+        assert!(decrypt_stream(&tampered, &nonce, &key).is_err());
+
+        let mut reordered = chunks.clone();
+        reordered.swap(0, 1);
+        assert!(decrypt_stream(&reordered, &nonce, &key).is_err());
+
+        // Без последней части предпоследняя не сойдет за последнюю
+        assert!(decrypt_stream(&chunks[..2], &nonce, &key).is_err());
+
+        let mut decryptor = StreamDecryptor::new(&nonce, &key);
+        decryptor.decrypt_chunk(&chunks[0], false).unwrap();
+        assert!(decryptor.finish().is_err());
+
+        let other = create_vault_key().unwrap();
+        assert!(decrypt_stream(&chunks, &nonce, &other).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::db::models::CustomField;
+use crate::error::PassHiveError;
+use zeroize::Zeroize;
+
+// Дополнительные поля записи. Порядок полей задает пользователь и сохраняется,
+// имена уникальны внутри записи
+
+// В get поле выводится как custom.ИМЯ, чтобы не совпасть с полями типа
+pub const PREFIX: &str = "custom.";
+
+// Значение в таблице вместо скрытого
+pub const MASK: &str = "********";
+
+pub fn validate_name(name: &str) -> Result<(), PassHiveError> {
+    if name.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "у дополнительного поля должно быть имя".to_string(),
+        ));
+    }
+    // --custom ИМЯ=ЗНАЧЕНИЕ делится по первому =
+    if name.contains(['=', '\n']) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в имени поля {} не может быть символа = и перевода строки",
+            name
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate(fields: &[CustomField]) -> Result<(), PassHiveError> {
+    for (idx, field) in fields.iter().enumerate() {
+        validate_name(&field.name)?;
+        if field.value.contains('\n') {
+            return Err(PassHiveError::InvalidInput(format!(
+                "значение поля {} должно быть одной строкой",
+                field.name
+            )));
+        }
+        if fields[..idx].iter().any(|other| other.name == field.name) {
+            return Err(PassHiveError::InvalidInput(format!(
+                "поле {} повторяется",
+                field.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Существующее поле меняется на своем месте, новое добавляется в конец.
+// Пустое значение удаляет поле
+pub fn set(fields: &mut Vec<CustomField>, name: &str, value: &str, hidden: bool) {
+    let name = name.trim();
+    let position = fields.iter().position(|field| field.name == name);
+
+    match (position, value.is_empty()) {
+        (Some(idx), true) => fields.remove(idx).zeroize(),
+        (Some(idx), false) => {
+            fields[idx].value.zeroize();
+            fields[idx].value = value.to_string();
+            fields[idx].hidden = hidden;
+        }
+        (None, true) => {}
+        (None, false) => fields.push(CustomField {
+            name: name.to_string(),
+            value: value.to_string(),
+            hidden,
+        }),
+    }
+}
+
+// Строка для таблицы: скрытое значение заменяется маской
+pub fn display(field: &CustomField) -> String {
+    match field.hidden {
+        true => format!("{}: {} (скрыто)", field.name, MASK),
+        false => format!("{}: {}", field.name, field.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(fields: &[CustomField]) -> Vec<&str> {
+        fields.iter().map(|field| field.name.as_str()).collect()
+    }
+
+    #[test]
+    fn set_keeps_order_and_removes_empty_values() {
+        let mut fields = Vec::new();
+        set(&mut fields, "PIN", "1234", true);
+        set(&mut fields, " Секретный вопрос ", "Кличка кота", false);
+        set(&mut fields, "Код", "42", false);
+        assert_eq!(names(&fields), ["PIN", "Секретный вопрос", "Код"]);
+
+        set(&mut fields, "PIN", "0000", false);
+        assert_eq!(names(&fields), ["PIN", "Секретный вопрос", "Код"]);
+        assert_eq!(fields[0].value, "0000");
+        assert!(!fields[0].hidden);
+
+        set(&mut fields, "Секретный вопрос", "", false);
+        set(&mut fields, "Нет такого", "", false);
+        assert_eq!(names(&fields), ["PIN", "Код"]);
+    }
+
+    #[test]
+    fn duplicate_and_multiline_fields_are_rejected() {
+        let field = |name: &str, value: &str| CustomField {
+            name: name.to_string(),
+            value: value.to_string(),
+            hidden: false,
+        };
+
+        assert!(validate(&[field("PIN", "1"), field("Код", "2")]).is_ok());
+        assert!(validate(&[field("PIN", "1"), field("PIN", "2")]).is_err());
+        assert!(validate(&[field("PIN", "1\n2")]).is_err());
+        assert!(validate(&[field("a=b", "1")]).is_err());
+        assert!(validate(&[field("", "1")]).is_err());
+    }
+
+    #[test]
+    fn hidden_values_are_masked() {
+        let mut fields = Vec::new();
+        set(&mut fields, "PIN", "1234", true);
+        set(&mut fields, "Код", "42", false);
+
+        assert_eq!(display(&fields[0]), "PIN: ******** (скрыто)");
+        assert_eq!(display(&fields[1]), "Код: 42");
+    }
+}
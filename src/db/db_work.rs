@@ -1,74 +1,27 @@
 use super::models::*;
+use crate::error::PassHiveError;
 use rusqlite::{Connection, params};
-use std::path::{self, PathBuf};
-
-fn connect_to_db(path: &PathBuf) -> Result<Connection, String> {
-    Connection::open(path.join("passhive.db"))
-        .map_err(|e| format!("Ошибка открытия базы данных: {}", e))
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// SQLite проверяет REFERENCES только при включенном foreign_keys, а он действует
+// лишь на свое соединение - поэтому включается здесь, на каждом
+pub(crate) fn connect_to_db(path: &PathBuf) -> Result<Connection, PassHiveError> {
+    let connection = Connection::open(path.join("passhive.db"))?;
+    connection.pragma_update(None, "foreign_keys", true)?;
+    Ok(connection)
 }
 
-pub fn init_db(path: &PathBuf) -> Result<(), String> {
-    let connection;
-
-    match connect_to_db(path) {
-        Ok(conn) => connection = conn,
-        Err(e) => return Err(e),
-    }
-
-    let sql_meta = "
-        CREATE TABLE IF NOT EXISTS meta (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_login TEXT NOT NULL,
-            user_create_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-            user_passwd TEXT NOT NULL,
-            salt TEXT NOT NULL
-        )";
-    connection
-        .execute(sql_meta, [])
-        .map_err(|e| format!("Ошибка создания таблицы meta: {}", e))?;
-
-    let sql_users = "
-        CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            owner INTEGER NOT NULL,
-            data_type TEXT NOT NULL,
-            data BLOB NOT NULL,
-            name BLOB,
-            notice BLOB,
-            nonce BLOB NOT NULL
-        )";
-    connection
-        .execute(sql_users, [])
-        .map_err(|e| format!("Ошибка создания таблицы users: {}", e))?;
-
-    let sql_keys = "
-        CREATE TABLE IF NOT EXISTS keys (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        owner INTEGER NOT NULL,
-        pubkey BLOB NOT NULL
-        )";
-    connection
-        .execute(sql_keys, [])
-        .map_err(|e| format!("Ошибка создания таблицы keys: {}", e))?;
-
-    Ok(())
-}
-
-pub fn users_empty(path: &PathBuf) -> bool {
-    let connection;
-
-    match connect_to_db(path) {
-        Ok(conn) => connection = conn,
-        Err(_) => unreachable!("БД ПРОПАЛА ПО ХОДУ РАБОТЫ!"),
-    }
+pub fn users_empty(path: &PathBuf) -> Result<bool, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
     let sql = "SELECT COUNT(*) FROM meta";
-    let count: i64 = connection.query_row(sql, [], |row| row.get(0)).unwrap_or(0);
+    let count: i64 = connection.query_row(sql, [], |row| row.get(0))?;
 
-    count == 0
+    Ok(count == 0)
 }
 
-pub fn user_exists(path: &PathBuf, login: &str) -> Result<bool, String> {
+pub fn user_exists(path: &PathBuf, login: &str) -> Result<bool, PassHiveError> {
     let conn;
 
     match connect_to_db(path) {
@@ -76,18 +29,18 @@ pub fn user_exists(path: &PathBuf, login: &str) -> Result<bool, String> {
         Err(e) => return Err(e),
     }
 
-    let mut stmt = conn
-        .prepare("SELECT COUNT(*) FROM meta WHERE user_login = ?1")
-        .map_err(|e| e.to_string())?;
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM meta WHERE user_login = ?1")?;
 
-    let count: i64 = stmt
-        .query_row(params![login], |row| row.get(0))
-        .map_err(|e| e.to_string())?;
+    let count: i64 = stmt.query_row(params![login], |row| row.get(0))?;
 
     Ok(count > 0)
 }
 
-pub fn reg_new_user(path: &PathBuf, login: &str, hash: &str, salt: &str) -> Result<i64, String> {
+pub fn reg_new_user(
+    path: &PathBuf,
+    login: &str,
+    creds: &Credentials,
+) -> Result<i64, PassHiveError> {
     let connection;
 
     match connect_to_db(path) {
@@ -95,20 +48,32 @@ pub fn reg_new_user(path: &PathBuf, login: &str, hash: &str, salt: &str) -> Resu
         Err(e) => return Err(e),
     }
 
-    let sql = "INSERT INTO meta (user_login, user_passwd, salt) VALUES (?1, ?2, ?3)";
-    connection
-        .execute(sql, params![login, hash, salt])
-        .map_err(|e| format!("Ошибка регистрации нового пользователя в БД: {}", e))?;
+    let kdf = kdf_to_json(&creds.kdf)?;
+
+    let sql = "
+        INSERT INTO meta (user_login, user_passwd, salt, vault_key, vault_key_nonce, kdf_params)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+    connection.execute(
+        sql,
+        params![
+            login,
+            creds.hash,
+            creds.salt,
+            creds.wrapped_key,
+            creds.key_nonce,
+            kdf
+        ],
+    )?;
 
     Ok(connection.last_insert_rowid())
 }
 
-pub fn find_by_login(path: &PathBuf, login: &String) -> Result<Option<(i64, String)>, String> {
-    let connection = connect_to_db(path).map_err(|e| e.to_string())?;
+pub fn find_by_login(path: &PathBuf, login: &str) -> Result<Option<(i64, String)>, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
     let sql = "SELECT id, user_passwd FROM meta WHERE user_login = ?1";
 
-    let mut stmt = connection.prepare(sql).map_err(|e| e.to_string())?;
+    let mut stmt = connection.prepare(sql)?;
 
     let result = stmt.query_row(params![login], |row| {
         let id: i64 = row.get(0)?;
@@ -119,103 +84,626 @@ pub fn find_by_login(path: &PathBuf, login: &String) -> Result<Option<(i64, Stri
     match result {
         Ok(data) => Ok(Some(data)),
         Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn get_all_user_data(path: &PathBuf, user_id: i64) -> Result<Vec<UserData>, rusqlite::Error> {
-    let connection = connect_to_db(path).map_err(|e| rusqlite::Error::InvalidQuery)?;
+const SELECT_ROWS_SQL: &str = "
+    SELECT id, data_type, data, name, notice, nonce, name_nonce, notice_nonce,
+        custom_fields, custom_fields_nonce, folder, labels, labels_nonce
+    FROM users";
+
+fn row_to_user_data(row: &rusqlite::Row) -> rusqlite::Result<RawUserRow> {
+    Ok(RawUserRow {
+        id: row.get(0)?,
+        data_type: row.get(1)?,
+        data: row.get(2)?,
+        name: row.get(3)?,
+        notice: row.get(4)?,
+        nonce: row.get(5)?,
+        name_nonce: row.get(6)?,
+        notice_nonce: row.get(7)?,
+        custom_fields: row.get(8)?,
+        custom_fields_nonce: row.get(9)?,
+        folder: row.get(10)?,
+        labels: row.get(11)?,
+        labels_nonce: row.get(12)?,
+    })
+}
 
-    let mut stmt = connection
-        .prepare("SELECT id, data_type, data, name, notice, nonce FROM users WHERE owner = ?1")?;
+pub fn get_all_user_data(path: &PathBuf, user_id: i64) -> Result<Vec<UserData>, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
-    let user_iter = stmt.query_map(params![user_id], |row| {
-        let id = row.get::<_, i64>(0)?;
-        let data_type = row.get::<_, String>(1)?;
-        let data = row.get::<_, Vec<u8>>(2)?;
-        let name = row.get::<_, Vec<u8>>(3)?;
-        let notice = row.get::<_, Vec<u8>>(4)?;
-        let nonce = row.get::<_, [u8; 24]>(5)?;
-        Ok(UserData::new(id, data, data_type, nonce, notice, name))
-    })?;
+    let mut stmt = connection.prepare(&format!("{} WHERE owner = ?1", SELECT_ROWS_SQL))?;
+    let user_iter = stmt.query_map(params![user_id], row_to_user_data)?;
+
+    let mut total = Vec::new();
+    for row in user_iter {
+        total.push(UserData::from_raw(row?)?);
+    }
+
+    Ok(total)
+}
+
+pub fn get_row(path: &PathBuf, owner: i64, id: i64) -> Result<UserData, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
-    user_iter.collect()
+    let sql = format!("{} WHERE owner = ?1 AND id = ?2", SELECT_ROWS_SQL);
+    match connection.query_row(&sql, params![owner, id], row_to_user_data) {
+        Ok(raw) => UserData::from_raw(raw),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(PassHiveError::NoSuchEntry(id)),
+        Err(e) => Err(e.into()),
+    }
 }
 
 pub fn insert_row(
     path: &PathBuf,
     owner: i64,
     data_type: &String,
-    data: &Vec<u8>,
-    name: &Vec<u8>,
-    notice: &Vec<u8>,
-    nonce: &[u8; 24],
-) -> Result<i64, String> {
-    let connection = connect_to_db(path).map_err(|e| format!("Ошибка подключения к БД: {}", e))?;
-
-    let sql = "INSERT INTO users (owner, data_type, data, name, notice, nonce) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+    fields: &EncryptedFields,
+    folder: Option<i64>,
+) -> Result<i64, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
-    match connection.execute(sql, params![owner, data_type, data, name, notice, nonce]) {
+    let sql = "
+        INSERT INTO users (owner, data_type, data, nonce, name, name_nonce, notice, notice_nonce,
+            custom_fields, custom_fields_nonce, folder, labels, labels_nonce, secret_updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13,
+            CAST(strftime('%s', 'now') AS INTEGER))";
+
+    match connection.execute(
+        sql,
+        params![
+            owner,
+            data_type,
+            fields.data,
+            fields.nonce,
+            fields.name,
+            fields.name_nonce,
+            fields.notice,
+            fields.notice_nonce,
+            fields.custom_fields,
+            fields.custom_fields_nonce,
+            folder,
+            fields.labels,
+            fields.labels_nonce
+        ],
+    ) {
         Ok(_) => {
             let id = connection.last_insert_rowid();
             Ok(id)
         }
-        Err(e) => Err(format!("Ошибка вставки данных: {}", e)),
+        Err(e) => Err(e.into()),
     }
 }
 
-pub fn get_salt_by_id(path: &PathBuf, id: i64) -> Result<String, String> {
-    let connection = connect_to_db(path).map_err(|e| format!("Ошибка подключения к БД: {}", e))?;
+pub fn get_salt_by_id(path: &PathBuf, id: i64) -> Result<String, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
     let sql = "SELECT salt FROM meta WHERE id = ?1";
-    let mut stmt = connection
-        .prepare(sql)
-        .map_err(|e| format!("Ошибка подготовки запроса: {}", e))?;
+    let mut stmt = connection.prepare(sql)?;
 
-    stmt.query_row(params![id], |row| row.get::<_, String>(0))
-        .map_err(|_| {
-            "Ошибка: нет пользователя с таким ID или проблемы с чтением строки".to_string()
-        })
+    Ok(stmt.query_row(params![id], |row| row.get::<_, String>(0))?)
 }
 
-pub fn delete_row(path: &PathBuf, id: i64) -> Result<(), String> {
-    let connection = connect_to_db(path).map_err(|e| format!("Ошибка подключения к БД: {}", e))?;
+// Зашифрованный ключ хранилища. None - пользователь создан до перехода на ключ хранилища
+pub fn get_wrapped_key(path: &PathBuf, id: i64) -> Result<Option<WrappedKey>, PassHiveError> {
+    let connection = connect_to_db(path)?;
 
-    let sql = "DELETE FROM users WHERE id=?1";
+    let sql = "SELECT vault_key, vault_key_nonce FROM meta WHERE id = ?1";
+    let (wrapped, nonce) = connection.query_row(sql, params![id], |row| {
+        Ok((
+            row.get::<_, Option<Vec<u8>>>(0)?,
+            row.get::<_, Option<[u8; 24]>>(1)?,
+        ))
+    })?;
 
-    match connection.execute(sql, params![id]) {
-        Err(e) => return Err(e.to_string()),
-        Ok(_) => return Ok(()),
+    match (wrapped, nonce) {
+        (Some(wrapped), Some(nonce)) => Ok(Some((wrapped, nonce))),
+        _ => Ok(None),
     }
 }
 
+fn kdf_to_json(kdf: &KdfParams) -> Result<String, PassHiveError> {
+    serde_json::to_string(kdf)
+        .map_err(|e| PassHiveError::SchemaMismatch(format!("meta.kdf_params: {}", e)))
+}
+
+fn parse_kdf(raw: Option<String>) -> Result<Option<KdfParams>, PassHiveError> {
+    match raw {
+        None => Ok(None),
+        Some(json) => serde_json::from_str(&json)
+            .map(Some)
+            .map_err(|e| PassHiveError::SchemaMismatch(format!("meta.kdf_params: {}", e))),
+    }
+}
+
+// (текущие параметры, параметры, которые применятся при следующем входе).
+// Текущие None - пользователь создан до появления meta.kdf_params
+pub fn get_kdf_params(
+    path: &PathBuf,
+    id: i64,
+) -> Result<(Option<KdfParams>, Option<KdfParams>), PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT kdf_params, kdf_pending FROM meta WHERE id = ?1";
+    let (current, pending) = connection.query_row(sql, params![id], |row| {
+        Ok((
+            row.get::<_, Option<String>>(0)?,
+            row.get::<_, Option<String>>(1)?,
+        ))
+    })?;
+
+    Ok((parse_kdf(current)?, parse_kdf(pending)?))
+}
+
+pub fn set_pending_kdf(path: &PathBuf, id: i64, kdf: &KdfParams) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let kdf = kdf_to_json(kdf)?;
+    connection.execute(
+        "UPDATE meta SET kdf_pending = ?1 WHERE id = ?2",
+        params![kdf, id],
+    )?;
+
+    Ok(())
+}
+
+pub fn get_hash_by_id(path: &PathBuf, id: i64) -> Result<String, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT user_passwd FROM meta WHERE id = ?1";
+    connection
+        .query_row(sql, params![id], |row| row.get::<_, String>(0))
+        .map_err(PassHiveError::from)
+}
+
+// Новый хэш, соль, параметры KDF и перешифрованный ключ хранилища записываются
+// одной транзакцией, поэтому после сбоя остается либо старый пароль, либо новый целиком
+pub fn update_credentials(
+    path: &PathBuf,
+    id: i64,
+    creds: &Credentials,
+) -> Result<(), PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+    write_credentials(&tx, id, creds)?;
+
+    Ok(tx.commit()?)
+}
+
+fn write_credentials(
+    connection: &Connection,
+    id: i64,
+    creds: &Credentials,
+) -> Result<(), PassHiveError> {
+    let kdf = kdf_to_json(&creds.kdf)?;
+
+    let sql = "
+        UPDATE meta
+        SET user_passwd = ?1, salt = ?2, vault_key = ?3, vault_key_nonce = ?4,
+            kdf_params = ?5, kdf_pending = NULL
+        WHERE id = ?6";
+    let changed = connection.execute(
+        sql,
+        params![
+            creds.hash,
+            creds.salt,
+            creds.wrapped_key,
+            creds.key_nonce,
+            kdf,
+            id
+        ],
+    )?;
+
+    if changed != 1 {
+        return Err(PassHiveError::Db(rusqlite::Error::QueryReturnedNoRows));
+    }
+
+    Ok(())
+}
+
+// Вложения записи удаляются вместе с ней, в той же транзакции.
+// Внешние ключи включены в connect_to_db - сначала части, потом вложения, потом сама запись
+pub fn delete_row(path: &PathBuf, owner: i64, id: i64) -> Result<(), PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+
+    tx.execute(
+        "DELETE FROM attachment_chunks WHERE attachment IN
+            (SELECT id FROM attachments WHERE entry = ?1 AND owner = ?2)",
+        params![id, owner],
+    )?;
+    tx.execute(
+        "DELETE FROM attachments WHERE entry = ?1 AND owner = ?2",
+        params![id, owner],
+    )?;
+
+    let sql = "DELETE FROM users WHERE id = ?1 AND owner = ?2";
+    if tx.execute(sql, params![id, owner])? == 0 {
+        return Err(PassHiveError::NoSuchEntry(id));
+    }
+
+    Ok(tx.commit()?)
+}
+
+const UPDATE_ROW_SQL: &str = "
+    UPDATE users
+    SET data = ?1, nonce = ?2, name = ?3, name_nonce = ?4, notice = ?5, notice_nonce = ?6,
+        custom_fields = ?7, custom_fields_nonce = ?8, labels = ?9, labels_nonce = ?10
+    WHERE id = ?11 AND owner = ?12";
+
+// Правка пользователем. secret_changed - сдвинуть secret_updated_at;
+// перешифровка в update_rows его не трогает. folder: Some - перенести запись
+// в папку (None внутри - вне папок) той же транзакцией
 pub fn update_row(
     path: &PathBuf,
-    enc_data: Vec<u8>,
-    nonce: [u8; 24],
-    name: Vec<u8>,
-    notice: Vec<u8>,
+    owner: i64,
+    id: i64,
+    fields: &EncryptedFields,
+    secret_changed: bool,
+    folder: Option<Option<i64>>,
+) -> Result<(), PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+
+    let sql = match secret_changed {
+        false => UPDATE_ROW_SQL,
+        true => {
+            "
+            UPDATE users
+            SET data = ?1, nonce = ?2, name = ?3, name_nonce = ?4, notice = ?5, notice_nonce = ?6,
+                custom_fields = ?7, custom_fields_nonce = ?8, labels = ?9, labels_nonce = ?10,
+                secret_updated_at = CAST(strftime('%s', 'now') AS INTEGER)
+            WHERE id = ?11 AND owner = ?12"
+        }
+    };
+    let updated = tx.execute(
+        sql,
+        params![
+            fields.data,
+            fields.nonce,
+            fields.name,
+            fields.name_nonce,
+            fields.notice,
+            fields.notice_nonce,
+            fields.custom_fields,
+            fields.custom_fields_nonce,
+            fields.labels,
+            fields.labels_nonce,
+            id,
+            owner
+        ],
+    )?;
+    if updated == 0 {
+        return Err(PassHiveError::NoSuchEntry(id));
+    }
+    if let Some(folder) = folder {
+        tx.execute(SET_FOLDER_SQL, params![folder, id, owner])?;
+    }
+
+    Ok(tx.commit()?)
+}
+
+// Перезаписывает несколько строк в одной транзакции: либо все, либо ни одной.
+// creds - записать той же транзакцией новый ключ хранилища, которым зашифрованы строки
+pub fn update_rows(
+    path: &PathBuf,
+    owner: i64,
+    rows: &[(i64, EncryptedFields)],
+    creds: Option<&Credentials>,
+) -> Result<(), PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+
+    for (id, fields) in rows {
+        tx.execute(
+            UPDATE_ROW_SQL,
+            params![
+                fields.data,
+                fields.nonce,
+                fields.name,
+                fields.name_nonce,
+                fields.notice,
+                fields.notice_nonce,
+                fields.custom_fields,
+                fields.custom_fields_nonce,
+                fields.labels,
+                fields.labels_nonce,
+                id,
+                owner
+            ],
+        )?;
+    }
+    if let Some(creds) = creds {
+        write_credentials(&tx, owner, creds)?;
+    }
+
+    Ok(tx.commit()?)
+}
+
+// id записи -> время последней смены секрета (unix-время)
+pub fn get_secret_updated_at(
+    path: &PathBuf,
+    owner: i64,
+) -> Result<HashMap<i64, i64>, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT id, secret_updated_at FROM users WHERE owner = ?1 AND secret_updated_at IS NOT NULL";
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map(params![owner], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+// Шаблоны пользователя в порядке создания
+pub fn get_templates(path: &PathBuf, owner: i64) -> Result<Vec<EncryptedTemplate>, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT id, data, nonce FROM templates WHERE owner = ?1 ORDER BY id";
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map(params![owner], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn insert_template(
+    path: &PathBuf,
+    owner: i64,
+    data: &[u8],
+    nonce: &[u8; 24],
+) -> Result<i64, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "INSERT INTO templates (owner, data, nonce) VALUES (?1, ?2, ?3)";
+    connection.execute(sql, params![owner, data, nonce])?;
+
+    Ok(connection.last_insert_rowid())
+}
+
+pub fn delete_template(path: &PathBuf, owner: i64, id: i64) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "DELETE FROM templates WHERE id = ?1 AND owner = ?2";
+
+    match connection.execute(sql, params![id, owner]) {
+        Err(e) => Err(e.into()),
+        Ok(0) => Err(PassHiveError::NoSuchEntry(id)),
+        Ok(_) => Ok(()),
+    }
+}
+
+// entry: None - вложения всех записей пользователя
+pub fn get_attachments(
+    path: &PathBuf,
+    owner: i64,
+    entry: Option<i64>,
+) -> Result<Vec<RawAttachment>, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    // Тег Poly1305 - 16 байт в каждой части
+    let sql = "
+        SELECT a.id, a.entry, a.meta, a.meta_nonce, a.created_at,
+            (SELECT COALESCE(SUM(LENGTH(c.data) - 16), 0)
+             FROM attachment_chunks c WHERE c.attachment = a.id)
+        FROM attachments a
+        WHERE a.owner = ?1 AND (?2 IS NULL OR a.entry = ?2)
+        ORDER BY a.id";
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map(params![owner, entry], |row| {
+        Ok(RawAttachment {
+            id: row.get(0)?,
+            entry: row.get(1)?,
+            meta: row.get(2)?,
+            meta_nonce: row.get(3)?,
+            created_at: row.get(4)?,
+            size: row.get(5)?,
+        })
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+// Вложение записывается одной транзакцией: при ошибке в любой части
+// не остается ни строки attachments, ни уже записанных частей
+pub fn insert_attachment(
+    path: &PathBuf,
+    owner: i64,
+    entry: i64,
+    meta: &[u8],
+    meta_nonce: &[u8; 24],
+    stream_nonce: &[u8; 19],
+    chunks: impl Iterator<Item = Result<Vec<u8>, PassHiveError>>,
+) -> Result<i64, PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+
+    let exists: bool = tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1 AND owner = ?2)",
+        params![entry, owner],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(PassHiveError::NoSuchEntry(entry));
+    }
+
+    tx.execute(
+        "INSERT INTO attachments (owner, entry, meta, meta_nonce, stream_nonce)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![owner, entry, meta, meta_nonce, stream_nonce],
+    )?;
+    let id = tx.last_insert_rowid();
+
+    {
+        let mut stmt = tx
+            .prepare("INSERT INTO attachment_chunks (attachment, idx, data) VALUES (?1, ?2, ?3)")?;
+        for (idx, chunk) in chunks.enumerate() {
+            stmt.execute(params![id, idx as i64, chunk?])?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(id)
+}
+
+// Префикс nonce потока, которым зашифрованы части вложения
+pub fn get_stream_nonce(path: &PathBuf, owner: i64, id: i64) -> Result<[u8; 19], PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT stream_nonce FROM attachments WHERE id = ?1 AND owner = ?2";
+    match connection.query_row(sql, params![id, owner], |row| row.get(0)) {
+        Ok(nonce) => Ok(nonce),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(PassHiveError::NoSuchAttachment(id)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Части вложения по порядку, без загрузки всех сразу;
+// sink получает часть и признак того, что она последняя
+pub fn read_attachment_chunks(
+    path: &PathBuf,
+    owner: i64,
     id: i64,
-) -> Result<(), String> {
-    let connection = connect_to_db(path).map_err(|e| format!("Ошибка подключения к БД: {}", e))?;
+    mut sink: impl FnMut(&[u8], bool) -> Result<(), PassHiveError>,
+) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
 
     let sql = "
-        UPDATE users
-        SET data = ?1, name = ?2, notice = ?3, nonce = ?4
-        WHERE id = ?5";
-    match connection.execute(sql, params![enc_data, name, notice, nonce, id]) {
-        Err(e) => Err(e.to_string()),
+        SELECT c.data
+        FROM attachment_chunks c JOIN attachments a ON a.id = c.attachment
+        WHERE a.id = ?1 AND a.owner = ?2
+        ORDER BY c.idx";
+    let mut stmt = connection.prepare(sql)?;
+    let mut chunks = stmt
+        .query_map(params![id, owner], |row| row.get::<_, Vec<u8>>(0))?
+        .peekable();
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        sink(&chunk?, last)?;
+    }
+
+    Ok(())
+}
+
+pub fn delete_attachment(path: &PathBuf, owner: i64, id: i64) -> Result<(), PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let tx = connection.transaction()?;
+
+    tx.execute(
+        "DELETE FROM attachment_chunks WHERE attachment IN
+            (SELECT id FROM attachments WHERE id = ?1 AND owner = ?2)",
+        params![id, owner],
+    )?;
+    let sql = "DELETE FROM attachments WHERE id = ?1 AND owner = ?2";
+    if tx.execute(sql, params![id, owner])? == 0 {
+        return Err(PassHiveError::NoSuchAttachment(id));
+    }
+
+    Ok(tx.commit()?)
+}
+
+// Папки пользователя в порядке создания: родитель всегда раньше вложенных
+pub fn get_folders(path: &PathBuf, owner: i64) -> Result<Vec<EncryptedFolder>, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "SELECT id, parent, name, nonce FROM folders WHERE owner = ?1 ORDER BY id";
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map(params![owner], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn insert_folder(
+    path: &PathBuf,
+    owner: i64,
+    parent: Option<i64>,
+    name: &[u8],
+    nonce: &[u8; 24],
+) -> Result<i64, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "INSERT INTO folders (owner, parent, name, nonce) VALUES (?1, ?2, ?3, ?4)";
+    connection.execute(sql, params![owner, parent, name, nonce])?;
+
+    Ok(connection.last_insert_rowid())
+}
+
+// Непустую папку не дает удалить внешний ключ (users.folder, folders.parent).
+// Vault проверяет вложенные папки и записи заранее, чтобы назвать причину
+pub fn delete_folder(path: &PathBuf, owner: i64, id: i64) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "DELETE FROM folders WHERE id = ?1 AND owner = ?2";
+
+    match connection.execute(sql, params![id, owner]) {
+        Err(rusqlite::Error::SqliteFailure(e, _))
+            if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_FOREIGNKEY =>
+        {
+            Err(PassHiveError::InvalidInput(format!(
+                "папка с id {} не пуста",
+                id
+            )))
+        }
+        Err(e) => Err(e.into()),
+        Ok(0) => Err(PassHiveError::InvalidInput(format!(
+            "нет папки с id {}",
+            id
+        ))),
+        Ok(_) => Ok(()),
+    }
+}
+
+// Сколько записей лежит в каждой папке
+pub fn count_by_folder(path: &PathBuf, owner: i64) -> Result<HashMap<i64, i64>, PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    let sql = "
+        SELECT folder, COUNT(*) FROM users
+        WHERE owner = ?1 AND folder IS NOT NULL
+        GROUP BY folder";
+    let mut stmt = connection.prepare(sql)?;
+    let rows = stmt.query_map(params![owner], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+    })?;
+
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+const SET_FOLDER_SQL: &str = "UPDATE users SET folder = ?1 WHERE id = ?2 AND owner = ?3";
+
+// folder: None - убрать запись из папок
+pub fn set_folder(
+    path: &PathBuf,
+    owner: i64,
+    id: i64,
+    folder: Option<i64>,
+) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
+
+    match connection.execute(SET_FOLDER_SQL, params![folder, id, owner]) {
+        Err(e) => Err(e.into()),
+        Ok(0) => Err(PassHiveError::NoSuchEntry(id)),
         Ok(_) => Ok(()),
     }
 }
 
-pub fn add_key(path: &PathBuf, owner: i64, key: &[u8]) -> Result<(), String> {
-    let connection = connect_to_db(path).map_err(|e| format!("Ошибка подключения к БД: {}", e))?;
+pub fn add_key(path: &PathBuf, owner: i64, key: &[u8]) -> Result<(), PassHiveError> {
+    let connection = connect_to_db(path)?;
 
     let sql = "INSERT INTO keys (owner, pubkey) VALUES (?1, ?2)";
-    connection
-        .execute(sql, params![owner, key])
-        .map_err(|e| format!("Ошибка внесения ключа в бд! {}", e))?;
+    connection.execute(sql, params![owner, key])?;
 
     Ok(())
 }
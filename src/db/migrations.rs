@@ -0,0 +1,425 @@
+use super::db_work::connect_to_db;
+use crate::error::PassHiveError;
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Шаг миграции: переводит схему с version - 1 на version.
+// Каждый шаг выполняется в своей транзакции вместе с записью PRAGMA user_version.
+struct Migration {
+    version: u32,
+    apply: fn(&Connection) -> Result<(), PassHiveError>,
+}
+
+// Порядок важен: новые шаги добавляются только в конец
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        apply: create_base_tables,
+    },
+    Migration {
+        version: 2,
+        apply: add_field_nonces,
+    },
+    Migration {
+        version: 3,
+        apply: add_vault_key,
+    },
+    Migration {
+        version: 4,
+        apply: add_kdf_params,
+    },
+    Migration {
+        version: 5,
+        apply: add_secret_updated_at,
+    },
+    Migration {
+        version: 6,
+        apply: create_templates,
+    },
+    Migration {
+        version: 7,
+        apply: add_custom_fields,
+    },
+    Migration {
+        version: 8,
+        apply: create_attachments,
+    },
+    Migration {
+        version: 9,
+        apply: create_folders,
+    },
+    Migration {
+        version: 10,
+        apply: add_labels,
+    },
+];
+
+// Версия схемы, которую понимает эта сборка
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+// Приводит passhive.db к SCHEMA_VERSION.
+// Перед изменением существующей БД рядом кладется ее копия - ее путь возвращается.
+// БД от более новой версии программы не трогается.
+pub fn migrate(path: &PathBuf) -> Result<Option<PathBuf>, PassHiveError> {
+    let mut connection = connect_to_db(path)?;
+
+    let found = schema_version(&connection)?;
+    if found > SCHEMA_VERSION {
+        return Err(PassHiveError::DbTooNew {
+            found,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    if found == SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    let mut backup = None;
+    if has_tables(&connection)? {
+        backup = Some(backup_db(path, found)?);
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > found) {
+        let tx = connection.transaction()?;
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+    }
+
+    Ok(backup)
+}
+
+fn schema_version(connection: &Connection) -> Result<u32, PassHiveError> {
+    Ok(connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+fn has_tables(connection: &Connection) -> Result<bool, PassHiveError> {
+    let sql = "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'";
+    let count: i64 = connection.query_row(sql, [], |row| row.get(0))?;
+    Ok(count > 0)
+}
+
+// Копия до миграции: passhive.db.v<версия>.bak в том же каталоге
+fn backup_db(path: &Path, version: u32) -> Result<PathBuf, PassHiveError> {
+    let backup = path.join(format!("passhive.db.v{}.bak", version));
+    fs::copy(path.join("passhive.db"), &backup)?;
+    Ok(backup)
+}
+
+// Все копии до миграций в каталоге БД, включая сделанные при прошлых запусках
+pub fn backups(path: &Path) -> Result<Vec<PathBuf>, PassHiveError> {
+    let mut found = Vec::new();
+    for file in fs::read_dir(path)? {
+        let file = file?.path();
+        let name = file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with("passhive.db.v") && name.ends_with(".bak") {
+            found.push(file);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+// Схема до появления версий. IF NOT EXISTS - чтобы старые БД с user_version = 0
+// проходили этот шаг без ошибок.
+fn create_base_tables(connection: &Connection) -> Result<(), PassHiveError> {
+    let sql_meta = "
+        CREATE TABLE IF NOT EXISTS meta (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_login TEXT NOT NULL,
+            user_create_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            user_passwd TEXT NOT NULL,
+            salt TEXT NOT NULL
+        )";
+    connection.execute(sql_meta, [])?;
+
+    let sql_users = "
+        CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            owner INTEGER NOT NULL,
+            data_type TEXT NOT NULL,
+            data BLOB NOT NULL,
+            name BLOB,
+            notice BLOB,
+            nonce BLOB NOT NULL
+        )";
+    connection.execute(sql_users, [])?;
+
+    let sql_keys = "
+        CREATE TABLE IF NOT EXISTS keys (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner INTEGER NOT NULL,
+        pubkey BLOB NOT NULL
+        )";
+    connection.execute(sql_keys, [])?;
+
+    Ok(())
+}
+
+fn add_field_nonces(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(
+        connection,
+        "users",
+        &[("name_nonce", "BLOB"), ("notice_nonce", "BLOB")],
+    )
+}
+
+fn add_vault_key(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(
+        connection,
+        "meta",
+        &[("vault_key", "BLOB"), ("vault_key_nonce", "BLOB")],
+    )
+}
+
+fn add_kdf_params(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(
+        connection,
+        "meta",
+        &[("kdf_params", "TEXT"), ("kdf_pending", "TEXT")],
+    )
+}
+
+// Время последней смены секрета записи (unix-время). Настоящая дата для старых
+// записей неизвестна - отсчет их возраста начинается с момента миграции
+fn add_secret_updated_at(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(connection, "users", &[("secret_updated_at", "INTEGER")])?;
+    connection.execute(
+        "UPDATE users SET secret_updated_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE secret_updated_at IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+// Шаблоны записей: описание шаблона целиком, включая название, шифруется ключом хранилища
+fn create_templates(connection: &Connection) -> Result<(), PassHiveError> {
+    let sql = "
+        CREATE TABLE IF NOT EXISTS templates (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            owner INTEGER NOT NULL,
+            data BLOB NOT NULL,
+            nonce BLOB NOT NULL
+        )";
+    connection.execute(sql, [])?;
+    Ok(())
+}
+
+// Дополнительные поля записи: список в JSON, шифруется отдельно от data со своим nonce
+fn add_custom_fields(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(
+        connection,
+        "users",
+        &[("custom_fields", "BLOB"), ("custom_fields_nonce", "BLOB")],
+    )
+}
+
+// Вложения записей: имя файла и MIME-тип шифруются вместе в meta,
+// содержимое - частями по crypto::CHUNK_SIZE в attachment_chunks
+fn create_attachments(connection: &Connection) -> Result<(), PassHiveError> {
+    let sql_attachments = "
+        CREATE TABLE IF NOT EXISTS attachments (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            owner INTEGER NOT NULL,
+            entry INTEGER NOT NULL REFERENCES users(id),
+            meta BLOB NOT NULL,
+            meta_nonce BLOB NOT NULL,
+            stream_nonce BLOB NOT NULL,
+            created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+        )";
+    connection.execute(sql_attachments, [])?;
+
+    let sql_chunks = "
+        CREATE TABLE IF NOT EXISTS attachment_chunks (
+            attachment INTEGER NOT NULL REFERENCES attachments(id),
+            idx INTEGER NOT NULL,
+            data BLOB NOT NULL,
+            PRIMARY KEY (attachment, idx)
+        )";
+    connection.execute(sql_chunks, [])?;
+    Ok(())
+}
+
+// Папки записей: имя каждой папки шифруется, вложенность - через parent.
+// users.folder: NULL - запись вне папок
+fn create_folders(connection: &Connection) -> Result<(), PassHiveError> {
+    let sql = "
+        CREATE TABLE IF NOT EXISTS folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            owner INTEGER NOT NULL,
+            parent INTEGER REFERENCES folders(id),
+            name BLOB NOT NULL,
+            nonce BLOB NOT NULL
+        )";
+    connection.execute(sql, [])?;
+
+    add_missing_columns(
+        connection,
+        "users",
+        &[("folder", "INTEGER REFERENCES folders(id)")],
+    )
+}
+
+// Теги и флаг избранного: JSON, шифруется отдельно от data со своим nonce
+fn add_labels(connection: &Connection) -> Result<(), PassHiveError> {
+    add_missing_columns(
+        connection,
+        "users",
+        &[("labels", "BLOB"), ("labels_nonce", "BLOB")],
+    )
+}
+
+// БД без user_version могли уже получить часть колонок - добавляем только недостающие
+fn add_missing_columns(
+    connection: &Connection,
+    table: &str,
+    columns: &[(&str, &str)],
+) -> Result<(), PassHiveError> {
+    let mut stmt =
+        connection.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))?;
+    let existing: Vec<String> = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect())?;
+
+    for (column, sql_type) in columns {
+        if !existing.iter().any(|c| c == column) {
+            connection.execute(
+                &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, sql_type),
+                [],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn columns(connection: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = connection
+            .prepare(&format!("SELECT name FROM pragma_table_info('{}')", table))
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect())
+            .unwrap()
+    }
+
+    // Схема версии без миграций (user_version = 0) с одной строкой в users
+    fn baseline_db() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let connection = Connection::open(dir.path().join("passhive.db")).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE meta (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_login TEXT NOT NULL,
+                    user_create_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    user_passwd TEXT NOT NULL,
+                    salt TEXT NOT NULL
+                );
+                CREATE TABLE users (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner INTEGER NOT NULL,
+                    data_type TEXT NOT NULL,
+                    data BLOB NOT NULL,
+                    name BLOB,
+                    notice BLOB,
+                    nonce BLOB NOT NULL
+                );
+                CREATE TABLE keys (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner INTEGER NOT NULL,
+                    pubkey BLOB NOT NULL
+                );
+                INSERT INTO meta (user_login, user_passwd, salt) VALUES ('alice', 'hash', 'salt');
+                INSERT INTO users (owner, data_type, data, name, notice, nonce)
+                    VALUES (1, 'password', x'0102', x'03', x'04', zeroblob(24));",
+            )
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn baseline_db_migrates_to_current_schema() {
+        let dir = baseline_db();
+        let path = dir.path().to_path_buf();
+
+        let backup = migrate(&path).unwrap().expect("нет копии БД");
+        assert_eq!(backup, path.join("passhive.db.v0.bak"));
+        assert!(backup.exists());
+        assert_eq!(backups(&path).unwrap(), [backup]);
+
+        let connection = connect_to_db(&path).unwrap();
+        assert_eq!(schema_version(&connection).unwrap(), SCHEMA_VERSION);
+        let users = columns(&connection, "users");
+        for column in [
+            "name_nonce",
+            "notice_nonce",
+            "secret_updated_at",
+            "custom_fields",
+            "custom_fields_nonce",
+            "folder",
+            "labels",
+            "labels_nonce",
+        ] {
+            assert!(users.iter().any(|c| c == column), "нет users.{}", column);
+        }
+        let meta = columns(&connection, "meta");
+        for column in ["vault_key", "vault_key_nonce", "kdf_params", "kdf_pending"] {
+            assert!(meta.iter().any(|c| c == column), "нет meta.{}", column);
+        }
+        for table in ["templates", "attachments", "attachment_chunks", "folders"] {
+            assert!(
+                !columns(&connection, table).is_empty(),
+                "нет таблицы {}",
+                table
+            );
+        }
+
+        // Старая строка не потерялась, возраст секрета отсчитывается от миграции
+        let (data, updated_at): (Vec<u8>, Option<i64>) = connection
+            .query_row("SELECT data, secret_updated_at FROM users", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(data, vec![1, 2]);
+        assert!(updated_at.is_some());
+    }
+
+    #[test]
+    fn new_db_gets_current_schema_without_backup() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+
+        assert!(migrate(&path).unwrap().is_none());
+        assert_eq!(
+            schema_version(&connect_to_db(&path).unwrap()).unwrap(),
+            SCHEMA_VERSION
+        );
+        // Повторный запуск ничего не делает
+        assert!(migrate(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn newer_db_is_left_alone() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+        migrate(&path).unwrap();
+        connect_to_db(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+
+        assert!(matches!(
+            migrate(&path),
+            Err(PassHiveError::DbTooNew { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+}
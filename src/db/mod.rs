@@ -1,2 +1,3 @@
 pub mod db_work;
+pub mod migrations;
 pub mod models;
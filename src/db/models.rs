@@ -1,5 +1,13 @@
-use serde::{Deserialize, Serialize};
+use crate::card;
+use crate::error::PassHiveError;
+use crate::otp;
+use crate::ssh;
+use crate::template::FieldKind;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use strum_macros::EnumIter;
+use zeroize::Zeroize;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct UserMeta {
@@ -10,7 +18,7 @@ struct UserMeta {
     salt: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Zeroize)]
 pub enum Sex {
     Male,
     Female,
@@ -32,24 +40,102 @@ impl Default for Sex {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, EnumIter)]
+// Хэш-функция HMAC для одноразовых кодов (RFC 4226/6238)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Zeroize, Default)]
+pub enum OtpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl fmt::Display for OtpAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OtpAlgorithm::Sha1 => write!(f, "SHA1"),
+            OtpAlgorithm::Sha256 => write!(f, "SHA256"),
+            OtpAlgorithm::Sha512 => write!(f, "SHA512"),
+        }
+    }
+}
+
+// Значение поля записи по шаблону вместе с его именем и видом
+#[derive(Debug, Serialize, Deserialize, Clone, Zeroize, Default)]
+pub struct TemplateValue {
+    pub name: String,
+    pub kind: FieldKind,
+    pub value: String,
+}
+
+// Дополнительное поле записи любого типа: "Секретный вопрос", "PIN"...
+// hidden - значение не показывается в таблице
+#[derive(Debug, Serialize, Deserialize, Clone, Zeroize, Default, PartialEq)]
+pub struct CustomField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+// Теги и избранное записи: шифруются вместе одним JSON, отдельно от data
+#[derive(Debug, Serialize, Deserialize, Clone, Zeroize, Default, PartialEq)]
+pub struct Labels {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, EnumIter, Zeroize)]
 pub enum DataType {
+    // Поля с serde(default) появились позже: старые записи читаются с пустыми значениями
     Password {
         password: String,
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        urls: Vec<String>,
+        // Секрет TOTP в base32 или otpauth:// URI
+        #[serde(default)]
+        totp: Option<String>,
     },
 
     Card {
         num: String,
-        cvv: u16,
+        // Раньше CVV хранился числом и терял ведущие нули
+        #[serde(deserialize_with = "string_or_number")]
+        cvv: String,
         bank: String,
+        #[serde(default)]
+        holder: String,
+        // Срок действия: месяц 1..=12 и полный год
+        #[serde(default)]
+        exp_month: Option<u8>,
+        #[serde(default)]
+        exp_year: Option<u16>,
     },
 
+    // serial и num - внутренний паспорт РФ, остальные поля - заграничный или иностранный
     Passport {
         fsl: String,
         date: String,
         sex: Sex,
         serial: u16,
         num: u32,
+        // Номер документа из MRZ: латинские буквы и цифры
+        #[serde(default)]
+        doc_number: String,
+        // Коды стран ICAO: RUS, DEU...
+        #[serde(default)]
+        country: String,
+        #[serde(default)]
+        nationality: String,
+        #[serde(default)]
+        authority: String,
+        #[serde(default)]
+        issue_date: String,
+        #[serde(default)]
+        expiry_date: String,
     },
 
     Document {
@@ -65,16 +151,76 @@ pub enum DataType {
         token: String,
         from: String,
     },
+
+    // Секрет двухфакторной аутентификации. counter: None - TOTP, Some - HOTP
+    Otp {
+        secret: String,
+        issuer: String,
+        account: String,
+        algorithm: OtpAlgorithm,
+        digits: u32,
+        period: u64,
+        counter: Option<u64>,
+    },
+
+    // Закрытый ключ OpenSSH без парольной фразы: его защищает шифрование хранилища
+    SshKey {
+        private_key: String,
+        public_key: String,
+        comment: String,
+    },
+
+    // Запись по шаблону пользователя; template - название шаблона на момент создания
+    Templated {
+        template: String,
+        fields: Vec<TemplateValue>,
+    },
 }
 
 impl DataType {
     pub fn to_string(&self) -> String {
         match self {
-            DataType::Password { password } => {
-                format!("Пароль: {}", password)
+            DataType::Password {
+                password,
+                username,
+                urls,
+                totp,
+            } => {
+                let mut lines = Vec::new();
+                if !username.is_empty() {
+                    lines.push(format!("Логин: {}", username));
+                }
+                lines.push(format!("Пароль: {}", password));
+                if !urls.is_empty() {
+                    lines.push(format!("Сайты: {}", urls.join(", ")));
+                }
+                if let Some(totp) = totp {
+                    lines.push(format!("TOTP: {}", totp));
+                }
+                lines.join(",\n ")
             }
-            DataType::Card { num, cvv, bank } => {
-                format!("Номер: {},\n CVV: {},\n, Банк: {}", num, cvv, bank)
+            DataType::Card {
+                num,
+                cvv,
+                bank,
+                holder,
+                exp_month,
+                exp_year,
+            } => {
+                let mut lines = Vec::new();
+                match card::detect_brand(num) {
+                    Some(brand) => lines.push(format!("Номер: {} ({})", num, brand)),
+                    None => lines.push(format!("Номер: {}", num)),
+                }
+                if !holder.is_empty() {
+                    lines.push(format!("Держатель: {}", holder));
+                }
+                if let (Some(month), Some(year)) = (exp_month, exp_year) {
+                    lines.push(format!("Срок: {}", card::format_expiry(*month, *year)));
+                }
+                lines.push(format!("CVV: {}", cvv));
+                lines.push(format!("Банк: {}", bank));
+                lines.join(",\n ")
             }
             DataType::Document { text } => {
                 format!("Содержимое : {}", text)
@@ -85,21 +231,91 @@ impl DataType {
             DataType::WifiConfig { name, password } => {
                 format!("Название сети: {},\n Пароль: {}", name, password)
             }
+            DataType::Otp {
+                secret,
+                issuer,
+                account,
+                algorithm,
+                digits,
+                period,
+                counter,
+            } => {
+                let kind = match counter {
+                    None => format!("TOTP, {} с", period),
+                    Some(counter) => format!("HOTP, счетчик {}", counter),
+                };
+                format!(
+                    "Сервис: {},\n Аккаунт: {},\n Секрет: {},\n {}, {}, {} цифр",
+                    issuer, account, secret, kind, algorithm, digits
+                )
+            }
+            // Сам закрытый ключ в таблицу не выводится: он длинный и нужен только агенту
+            DataType::SshKey {
+                public_key,
+                comment,
+                ..
+            } => {
+                let mut lines = Vec::new();
+                match ssh::describe(public_key) {
+                    Some((algorithm, fingerprint)) => {
+                        lines.push(format!("Тип: {}", algorithm));
+                        lines.push(format!("Отпечаток: {}", fingerprint));
+                    }
+                    None => lines.push("Открытый ключ не читается".to_string()),
+                }
+                if !comment.is_empty() {
+                    lines.push(format!("Комментарий: {}", comment));
+                }
+                lines.join(",\n ")
+            }
+            DataType::Templated { template, fields } => {
+                let mut lines = vec![format!("Шаблон: {}", template)];
+                for field in fields.iter().filter(|field| !field.value.is_empty()) {
+                    // Строки многострочного поля выравниваются под первой
+                    lines.push(format!(
+                        "{}: {}",
+                        field.name,
+                        field.value.replace('\n', "\n   ")
+                    ));
+                }
+                lines.join(",\n ")
+            }
             DataType::Passport {
                 fsl,
                 date,
                 sex,
                 serial,
                 num,
+                doc_number,
+                country,
+                nationality,
+                authority,
+                issue_date,
+                expiry_date,
             } => {
-                format!(
-                    "ФИО: {},\n Дата рождения: {},\n Пол: {},\n Серия: {},\n Номер: {}",
-                    fsl,
-                    date,
-                    sex.to_string(),
-                    serial,
-                    num
-                )
+                let mut lines = vec![
+                    format!("ФИО: {}", fsl),
+                    format!("Дата рождения: {}", date),
+                    format!("Пол: {}", sex.to_string()),
+                ];
+                if *serial != 0 || *num != 0 {
+                    lines.push(format!("Серия: {}", serial));
+                    lines.push(format!("Номер: {}", num));
+                }
+                let optional = [
+                    ("Номер документа", doc_number),
+                    ("Страна", country),
+                    ("Гражданство", nationality),
+                    ("Кем выдан", authority),
+                    ("Дата выдачи", issue_date),
+                    ("Действителен до", expiry_date),
+                ];
+                for (label, value) in optional {
+                    if !value.is_empty() {
+                        lines.push(format!("{}: {}", label, value));
+                    }
+                }
+                lines.join(",\n ")
             }
         }
     }
@@ -107,16 +323,21 @@ impl DataType {
     pub fn name(&self) -> String {
         match self {
             DataType::Card { .. } => {
-                return "Банковская карта (номер, CVV, банк)".to_string();
+                "Банковская карта (номер, держатель, срок, CVV, банк)".to_string()
             }
-            DataType::Token { .. } => return "Токен (токен, от чего)".to_string(),
-            DataType::Document { .. } => return "Документ (текст)".to_string(),
+            DataType::Token { .. } => "Токен (токен, от чего)".to_string(),
+            DataType::Document { .. } => "Документ (текст)".to_string(),
             DataType::Passport { .. } => {
-                return "Пасспорт (ФИО, дата рождения, пол, серия, номер)".to_string();
+                "Пасспорт (ФИО, дата рождения, пол, серия, номер, MRZ)".to_string()
+            }
+            DataType::Password { .. } => "Пароль (логин, пароль, сайты, TOTP)".to_string(),
+            DataType::WifiConfig { .. } => "Wifi сеть (ip/название, пароль)".to_string(),
+            DataType::Otp { .. } => {
+                "Одноразовые коды (сервис, аккаунт, секрет TOTP/HOTP)".to_string()
             }
-            DataType::Password { .. } => return "Пароль (пароль)".to_string(),
-            DataType::WifiConfig { .. } => {
-                return "Wifi сеть (ip/название, пароль)".to_string();
+            DataType::SshKey { .. } => "Ключ SSH (закрытый ключ, комментарий)".to_string(),
+            DataType::Templated { .. } => {
+                "Запись по шаблону (поля задает пользователь)".to_string()
             }
         }
     }
@@ -129,15 +350,228 @@ impl DataType {
             DataType::Passport { .. } => "passport".to_string(),
             DataType::Password { .. } => "password".to_string(),
             DataType::WifiConfig { .. } => "wificonfig".to_string(),
+            DataType::Otp { .. } => "otp".to_string(),
+            DataType::SshKey { .. } => "sshkey".to_string(),
+            DataType::Templated { .. } => "template".to_string(),
+        }
+    }
+
+    pub fn from_formal_name(formal_name: &str) -> Option<DataType> {
+        match formal_name {
+            "password" => Some(new_password()),
+            "passport" => Some(new_passport()),
+            "document" => Some(new_doc()),
+            "wificonfig" => Some(new_wificonfig()),
+            "card" => Some(new_card()),
+            "token" => Some(new_token()),
+            "otp" => Some(new_otp()),
+            "sshkey" => Some(new_sshkey()),
+            "template" => Some(new_templated()),
+            _ => None,
+        }
+    }
+
+    // Поле, которое читается из stdin при добавлении без интерактива.
+    // У записи по шаблону - первое секретное поле, иначе первое поле
+    pub fn secret_field(&self) -> String {
+        match self {
+            DataType::Password { .. } => "password".to_string(),
+            DataType::Card { .. } => "num".to_string(),
+            DataType::Passport { .. } => "num".to_string(),
+            DataType::Document { .. } => "text".to_string(),
+            DataType::WifiConfig { .. } => "password".to_string(),
+            DataType::Token { .. } => "token".to_string(),
+            DataType::Otp { .. } => "secret".to_string(),
+            DataType::SshKey { .. } => "private_key".to_string(),
+            DataType::Templated { fields, .. } => fields
+                .iter()
+                .find(|field| field.kind == FieldKind::Secret)
+                .or(fields.first())
+                .map(|field| field.name.clone())
+                .unwrap_or_else(|| "template".to_string()),
+        }
+    }
+
+    // Поля по именам из serde: так новые поля вариантов доступны без отдельного кода
+    pub fn fields(&self) -> Result<Vec<(String, String)>, PassHiveError> {
+        // Поля шаблона выводятся в порядке шаблона, а не по алфавиту
+        if let DataType::Templated { template, fields } = self {
+            let mut total = vec![("template".to_string(), template.clone())];
+            for field in fields {
+                total.push((field.name.clone(), field.value.clone()));
+            }
+            return Ok(total);
+        }
+
+        let mut total = Vec::new();
+        for (key, value) in self.field_map()? {
+            let text = match value {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(","),
+                other => other.to_string(),
+            };
+            total.push((key, text));
+        }
+        Ok(total)
+    }
+
+    // Значение приводится к типу поля: число для чисел, строка для остального
+    pub fn set_field(&mut self, name: &str, value: &str) -> Result<(), PassHiveError> {
+        // Поля записи по шаблону - элементы списка, а не поля варианта
+        if let DataType::Templated { template, fields } = self {
+            return match fields.iter_mut().find(|field| field.name == name) {
+                Some(field) => {
+                    field.value = field.kind.normalize(value).map_err(|e| match e {
+                        PassHiveError::InvalidInput(reason) => {
+                            PassHiveError::InvalidInput(format!("поле {}: {}", name, reason))
+                        }
+                        other => other,
+                    })?;
+                    Ok(())
+                }
+                None => Err(PassHiveError::InvalidInput(format!(
+                    "в шаблоне {} нет поля {}",
+                    template, name
+                ))),
+            };
+        }
+
+        let variant = self.variant_name()?;
+        let mut map = self.field_map()?;
+
+        // Варианты значения по типу поля; подходит первый, который принимает serde
+        let candidates = match map.get(name) {
+            None => {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "у типа {} нет поля {}",
+                    self.formal_name(),
+                    name
+                )));
+            }
+            // Списки задаются через запятую: urls=a.com,b.com
+            Some(serde_json::Value::Array(_)) => vec![serde_json::Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| serde_json::Value::String(item.to_string()))
+                    .collect(),
+            )],
+            // Пустое значение убирает необязательное поле (totp, counter),
+            // обязательное строковое поле становится пустой строкой
+            Some(_) if value.is_empty() => {
+                vec![
+                    serde_json::Value::Null,
+                    serde_json::Value::String(String::new()),
+                ]
+            }
+            Some(serde_json::Value::Number(_)) => match value.trim().parse::<u64>() {
+                Ok(num) => vec![serde_json::Value::from(num)],
+                Err(_) => {
+                    return Err(PassHiveError::InvalidInput(format!(
+                        "поле {} должно быть числом",
+                        name
+                    )));
+                }
+            },
+            // Необязательное поле может быть и строкой, и числом (counter)
+            Some(serde_json::Value::Null) => {
+                let mut candidates = vec![serde_json::Value::String(value.to_string())];
+                if let Ok(num) = value.trim().parse::<u64>() {
+                    candidates.insert(0, serde_json::Value::from(num));
+                }
+                candidates
+            }
+            Some(_) => vec![serde_json::Value::String(value.to_string())],
+        };
+
+        let mut rebuilt = Err(PassHiveError::InvalidInput(format!("пустое поле {}", name)));
+        for candidate in candidates {
+            map.insert(name.to_string(), candidate);
+            let mut tagged = serde_json::Map::new();
+            tagged.insert(variant.clone(), serde_json::Value::Object(map.clone()));
+            rebuilt = serde_json::from_value::<DataType>(serde_json::Value::Object(tagged))
+                .map_err(|e| {
+                    PassHiveError::InvalidInput(format!("неверное значение поля {}: {}", name, e))
+                });
+            if rebuilt.is_ok() {
+                break;
+            }
+        }
+
+        *self = rebuilt?;
+        Ok(())
+    }
+
+    fn variant_name(&self) -> Result<String, PassHiveError> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(tagged)) => match tagged.into_iter().next() {
+                Some((variant, _)) => Ok(variant),
+                None => Err(PassHiveError::Crypto("пустой тип данных".to_string())),
+            },
+            Ok(_) => Err(PassHiveError::Crypto(
+                "неожиданный вид типа данных".to_string(),
+            )),
+            Err(e) => Err(PassHiveError::Crypto(e.to_string())),
+        }
+    }
+
+    // Поля варианта с их типами: строки, числа, пол - строкой
+    // У записи по шаблону - название шаблона и поля шаблона строками
+    pub fn field_map(&self) -> Result<serde_json::Map<String, serde_json::Value>, PassHiveError> {
+        if let DataType::Templated { template, fields } = self {
+            let mut map = serde_json::Map::new();
+            map.insert("template".to_string(), template.clone().into());
+            for field in fields {
+                map.insert(field.name.clone(), field.value.clone().into());
+            }
+            return Ok(map);
+        }
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(tagged)) => match tagged.into_iter().next() {
+                Some((_, serde_json::Value::Object(map))) => Ok(map),
+                _ => Err(PassHiveError::Crypto(
+                    "неожиданный вид типа данных".to_string(),
+                )),
+            },
+            Ok(_) => Err(PassHiveError::Crypto(
+                "неожиданный вид типа данных".to_string(),
+            )),
+            Err(e) => Err(PassHiveError::Crypto(e.to_string())),
         }
     }
 }
 
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    Ok(match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s,
+        StringOrNumber::Number(n) => n.to_string(),
+    })
+}
+
 fn new_card() -> DataType {
     DataType::Card {
         num: "".to_string(),
-        cvv: 0,
+        cvv: "".to_string(),
         bank: "".to_string(),
+        holder: "".to_string(),
+        exp_month: None,
+        exp_year: None,
     }
 }
 
@@ -161,6 +595,12 @@ fn new_passport() -> DataType {
         sex: Sex::NotDeclaredYet,
         serial: 0,
         num: 0,
+        doc_number: "".to_string(),
+        country: "".to_string(),
+        nationality: "".to_string(),
+        authority: "".to_string(),
+        issue_date: "".to_string(),
+        expiry_date: "".to_string(),
     }
 }
 
@@ -171,12 +611,59 @@ fn new_wificonfig() -> DataType {
     }
 }
 
+fn new_otp() -> DataType {
+    DataType::Otp {
+        secret: "".to_string(),
+        issuer: "".to_string(),
+        account: "".to_string(),
+        algorithm: OtpAlgorithm::Sha1,
+        digits: otp::DEFAULT_DIGITS,
+        period: otp::DEFAULT_PERIOD,
+        counter: None,
+    }
+}
+
+fn new_sshkey() -> DataType {
+    DataType::SshKey {
+        private_key: "".to_string(),
+        public_key: "".to_string(),
+        comment: "".to_string(),
+    }
+}
+
+fn new_templated() -> DataType {
+    DataType::Templated {
+        template: "".to_string(),
+        fields: Vec::new(),
+    }
+}
+
 fn new_password() -> DataType {
     DataType::Password {
         password: "".to_string(),
+        username: "".to_string(),
+        urls: Vec::new(),
+        totp: None,
     }
 }
 
+// Строка users как она лежит в БД, до проверки типа данных
+pub struct RawUserRow {
+    pub id: i64,
+    pub data_type: String,
+    pub data: Vec<u8>,
+    pub name: Vec<u8>,
+    pub notice: Vec<u8>,
+    pub nonce: [u8; 24],
+    pub name_nonce: Option<[u8; 24]>,
+    pub notice_nonce: Option<[u8; 24]>,
+    pub custom_fields: Option<Vec<u8>>,
+    pub custom_fields_nonce: Option<[u8; 24]>,
+    pub folder: Option<i64>,
+    pub labels: Option<Vec<u8>>,
+    pub labels_nonce: Option<[u8; 24]>,
+}
+
 pub struct UserData {
     pub id: i64,
     pub data: Vec<u8>,
@@ -184,6 +671,13 @@ pub struct UserData {
     pub nonce: [u8; 24],
     pub notice: Vec<u8>,
     pub name: Vec<u8>,
+    pub name_nonce: Option<[u8; 24]>, // None - старая запись, где name и notice шифровались nonce от data
+    pub notice_nonce: Option<[u8; 24]>,
+    // None - запись без дополнительных полей, сохраненная до их появления
+    pub custom_fields: Option<(Vec<u8>, [u8; 24])>,
+    pub folder: Option<i64>,
+    // None - запись без тегов, сохраненная до их появления
+    pub labels: Option<(Vec<u8>, [u8; 24])>,
 }
 
 impl UserData {
@@ -194,36 +688,463 @@ impl UserData {
         nonce: [u8; 24],
         notice: Vec<u8>,
         name: Vec<u8>,
-    ) -> Self {
-        let data_typ: DataType;
-        match data_type.as_str() {
-            "password" => data_typ = new_password(),
-            "passport" => data_typ = new_passport(),
-            "document" => data_typ = new_doc(),
-            "wificonfig" => data_typ = new_wificonfig(),
-            "card" => data_typ = new_card(),
-            "token" => data_typ = new_token(),
-            _ => unreachable!("ОШИБКА ТИПА ДАННЫХ В БД!"),
-        }
-        UserData {
+    ) -> Result<Self, PassHiveError> {
+        let data_typ = match DataType::from_formal_name(&data_type) {
+            Some(typ) => typ,
+            None => {
+                return Err(PassHiveError::CorruptRow {
+                    id,
+                    reason: format!("неизвестный тип данных {}", data_type),
+                });
+            }
+        };
+        Ok(UserData {
             id,
             data,
             data_type: data_typ,
             nonce,
             notice,
             name,
+            name_nonce: None,
+            notice_nonce: None,
+            custom_fields: None,
+            folder: None,
+            labels: None,
+        })
+    }
+
+    pub fn from_raw(raw: RawUserRow) -> Result<Self, PassHiveError> {
+        let mut user_data = UserData::new(
+            raw.id,
+            raw.data,
+            raw.data_type,
+            raw.nonce,
+            raw.notice,
+            raw.name,
+        )?;
+        user_data.name_nonce = raw.name_nonce;
+        user_data.notice_nonce = raw.notice_nonce;
+        user_data.custom_fields = raw.custom_fields.zip(raw.custom_fields_nonce);
+        user_data.folder = raw.folder;
+        user_data.labels = raw.labels.zip(raw.labels_nonce);
+        Ok(user_data)
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.name_nonce.is_none() || self.notice_nonce.is_none()
+    }
+
+    pub fn name_nonce(&self) -> &[u8; 24] {
+        self.name_nonce.as_ref().unwrap_or(&self.nonce)
+    }
+
+    pub fn notice_nonce(&self) -> &[u8; 24] {
+        self.notice_nonce.as_ref().unwrap_or(&self.nonce)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, EnumIter)]
+pub enum KdfAlgorithm {
+    Argon2id,
+    Argon2i,
+    Argon2d,
+}
+
+impl fmt::Display for KdfAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KdfAlgorithm::Argon2id => write!(f, "Argon2id"),
+            KdfAlgorithm::Argon2i => write!(f, "Argon2i"),
+            KdfAlgorithm::Argon2d => write!(f, "Argon2d"),
+        }
+    }
+}
+
+// Параметры Argon2 пользователя. Хранятся в meta.kdf_params в виде JSON
+// и используются и для хэша пароля, и для ключа из пароля
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    pub algorithm: KdfAlgorithm,
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    // Argon2::default() - так выводился ключ до появления meta.kdf_params
+    pub fn legacy() -> Self {
+        KdfParams {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+
+    pub fn recommended() -> Self {
+        KdfParams {
+            algorithm: KdfAlgorithm::Argon2id,
+            memory_kib: 65536,
+            iterations: 10,
+            parallelism: 3,
         }
     }
 }
 
+impl fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, память: {} МиБ, итераций: {}, потоков: {}",
+            self.algorithm,
+            self.memory_kib / 1024,
+            self.iterations,
+            self.parallelism
+        )
+    }
+}
+
+// Все, что меняется при смене пароля или параметров KDF
+pub struct Credentials {
+    pub hash: String,
+    pub salt: String,
+    pub wrapped_key: Vec<u8>,
+    pub key_nonce: [u8; 24],
+    pub kdf: KdfParams,
+}
+
+// Ключ хранилища, зашифрованный ключом из пароля, и его nonce
+pub type WrappedKey = (Vec<u8>, [u8; 24]);
+
+// Строка templates: id, зашифрованное описание шаблона и его nonce
+pub type EncryptedTemplate = (i64, Vec<u8>, [u8; 24]);
+
+// Строка folders: id, родитель, зашифрованное имя и его nonce
+pub type EncryptedFolder = (i64, Option<i64>, Vec<u8>, [u8; 24]);
+
+// Строка attachments до расшифровки. size - сумма размеров частей без тегов AEAD
+pub struct RawAttachment {
+    pub id: i64,
+    pub entry: i64,
+    pub meta: Vec<u8>,
+    pub meta_nonce: [u8; 24],
+    pub size: u64,
+    pub created_at: i64,
+}
+
+// Зашифрованная часть attachments.meta
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AttachmentMeta {
+    pub filename: String,
+    pub mime: String,
+}
+
+// Вложение без содержимого: его выдает Vault::extract_attachment потоком
+#[derive(Debug, Serialize, Clone)]
+pub struct Attachment {
+    pub id: i64,
+    pub entry: i64,
+    pub filename: String,
+    pub mime: String,
+    pub size: u64,
+    pub created_at: i64,
+}
+
+// Зашифрованная запись: у каждого поля свой nonce
+pub struct EncryptedFields {
+    pub data: Vec<u8>,
+    pub nonce: [u8; 24],
+    pub name: Vec<u8>,
+    pub name_nonce: [u8; 24],
+    pub notice: Vec<u8>,
+    pub notice_nonce: [u8; 24],
+    pub custom_fields: Vec<u8>,
+    pub custom_fields_nonce: [u8; 24],
+    pub labels: Vec<u8>,
+    pub labels_nonce: [u8; 24],
+}
+
+// Расшифрованная запись
+pub struct ShowableData {
+    pub id: i64,
+    pub name: String,
+    pub notice: String,
+    pub data: DataType,
+    pub custom_fields: Vec<CustomField>,
+    // id папки; None - запись вне папок
+    pub folder: Option<i64>,
+    pub labels: Labels,
+}
+
+// Стабильная схема для --format json/yaml:
+// { "id": 1, "type": "password", "name": "...", "notice": "...", "fields": { ... },
+//   "custom_fields": [{ "name": "...", "value": "...", "hidden": false }],
+//   "tags": ["..."], "favorite": false, "folder": "Работа/AWS" }
+// type - formal_name, fields - поля варианта DataType под их serde-именами.
+// folder есть только у EntryWithFolder: сама запись знает лишь id папки.
+// Новые поля могут добавляться, существующие не переименовываются.
+impl Serialize for ShowableData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self, None, serializer)
+    }
+}
+
+// Запись вместе с путем ее папки; None - запись вне папок
+pub struct EntryWithFolder<'a> {
+    pub entry: &'a ShowableData,
+    pub folder: Option<&'a str>,
+}
+
+impl Serialize for EntryWithFolder<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_entry(self.entry, Some(self.folder), serializer)
+    }
+}
+
+// folder: None - поле не выводится, Some(None) - "folder": null
+fn serialize_entry<S: Serializer>(
+    entry: &ShowableData,
+    folder: Option<Option<&str>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let fields = entry.data.field_map().map_err(serde::ser::Error::custom)?;
+
+    let len = if folder.is_some() { 9 } else { 8 };
+    let mut state = serializer.serialize_struct("ShowableData", len)?;
+    state.serialize_field("id", &entry.id)?;
+    state.serialize_field("type", &entry.data.formal_name())?;
+    state.serialize_field("name", &entry.name)?;
+    state.serialize_field("notice", &entry.notice)?;
+    state.serialize_field("fields", &fields)?;
+    state.serialize_field("custom_fields", &entry.custom_fields)?;
+    state.serialize_field("tags", &entry.labels.tags)?;
+    state.serialize_field("favorite", &entry.labels.favorite)?;
+    if let Some(folder) = folder {
+        state.serialize_field("folder", &folder)?;
+    }
+    state.end()
+}
+
+impl Zeroize for ShowableData {
+    fn zeroize(&mut self) {
+        self.name.zeroize();
+        self.notice.zeroize();
+        self.data.zeroize();
+        self.custom_fields.zeroize();
+        self.labels.zeroize();
+    }
+}
+
+impl Drop for ShowableData {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
 pub struct DataAndMeta {
     pub data: DataType,
     pub name: String,
     pub notice: String,
+    pub custom_fields: Vec<CustomField>,
+    pub folder: Option<i64>,
+    pub labels: Labels,
 }
 
 impl DataAndMeta {
     pub fn new(data: DataType, name: String, notice: String) -> Self {
-        DataAndMeta { data, name, notice }
+        DataAndMeta {
+            data,
+            name,
+            notice,
+            custom_fields: Vec::new(),
+            folder: None,
+            labels: Labels::default(),
+        }
+    }
+}
+
+impl Zeroize for DataAndMeta {
+    fn zeroize(&mut self) {
+        self.data.zeroize();
+        self.name.zeroize();
+        self.notice.zeroize();
+        self.custom_fields.zeroize();
+        self.labels.zeroize();
+    }
+}
+
+impl Drop for DataAndMeta {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_field_converts_values_to_field_types() {
+        let mut data = new_passport();
+        data.set_field("fsl", "Иванов Иван").unwrap();
+        data.set_field("serial", "4510").unwrap();
+
+        let map = data.field_map().unwrap();
+        assert_eq!(map["fsl"], "Иванов Иван");
+        assert_eq!(map["serial"], 4510);
+        assert!(data.set_field("serial", "45a0").is_err());
+        assert!(data.set_field("serial", "70000").is_err());
+    }
+
+    fn entry() -> ShowableData {
+        ShowableData {
+            id: 7,
+            name: "github".to_string(),
+            notice: String::new(),
+            data: DataType::Token {
+                token: "ghp_1".to_string(),
+                from: "github".to_string(),
+            },
+            custom_fields: Vec::new(),
+            folder: Some(3),
+            labels: Labels {
+                tags: vec!["работа".to_string()],
+                favorite: true,
+            },
+        }
+    }
+
+    #[test]
+    fn entry_serializes_to_documented_schema() {
+        let json = serde_json::to_value(entry()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "id": 7,
+                "type": "token",
+                "name": "github",
+                "notice": "",
+                "fields": { "token": "ghp_1", "from": "github" },
+                "custom_fields": [],
+                "tags": ["работа"],
+                "favorite": true,
+            })
+        );
+    }
+
+    #[test]
+    fn old_password_rows_get_empty_new_fields() {
+        let data: DataType = serde_json::from_str(r#"{"Password":{"password":"x"}}"#).unwrap();
+
+        assert!(matches!(
+            data,
+            DataType::Password { password, username, urls, totp: None }
+                if password == "x" && username.is_empty() && urls.is_empty()
+        ));
+    }
+
+    #[test]
+    fn password_urls_and_totp_are_set_from_text() {
+        let mut data = new_password();
+        data.set_field("urls", "github.com, , gitlab.com").unwrap();
+        data.set_field("totp", "JBSWY3DPEHPK3PXP").unwrap();
+        assert!(matches!(
+            &data,
+            DataType::Password { urls, totp: Some(totp), .. }
+                if urls == &["github.com", "gitlab.com"] && totp == "JBSWY3DPEHPK3PXP"
+        ));
+
+        // Пустое значение убирает необязательное поле
+        data.set_field("totp", "").unwrap();
+        assert!(matches!(&data, DataType::Password { totp: None, .. }));
+        assert_eq!(
+            data.fields().unwrap(),
+            vec![
+                ("password".to_string(), String::new()),
+                ("totp".to_string(), String::new()),
+                ("urls".to_string(), "github.com,gitlab.com".to_string()),
+                ("username".to_string(), String::new()),
+            ]
+        );
+    }
+
+    // Адрес и емкость буфера строки: zeroize затирает его, не освобождая
+    fn buffer(text: &String) -> (*const u8, usize) {
+        (text.as_ptr(), text.capacity())
+    }
+
+    fn is_zeroed((ptr, len): (*const u8, usize)) -> bool {
+        unsafe { std::slice::from_raw_parts(ptr, len) }
+            .iter()
+            .all(|byte| *byte == 0)
+    }
+
+    #[test]
+    fn entries_are_zeroized_in_place() {
+        let mut shown = entry();
+        let name = buffer(&shown.name);
+        let token = match &shown.data {
+            DataType::Token { token, .. } => buffer(token),
+            _ => unreachable!(),
+        };
+        shown.zeroize();
+        assert!(is_zeroed(name));
+        assert!(is_zeroed(token));
+        assert!(shown.name.is_empty() && shown.labels.tags.is_empty());
+
+        let mut added = DataAndMeta::new(
+            DataType::Document {
+                text: "секретный текст".to_string(),
+            },
+            "договор".to_string(),
+            "заметка".to_string(),
+        );
+        let name = buffer(&added.name);
+        let notice = buffer(&added.notice);
+        let text = match &added.data {
+            DataType::Document { text } => buffer(text),
+            _ => unreachable!(),
+        };
+        added.zeroize();
+        assert!(is_zeroed(name));
+        assert!(is_zeroed(notice));
+        assert!(is_zeroed(text));
+    }
+
+    #[test]
+    fn entry_with_folder_adds_folder_path() {
+        let entry = entry();
+        let in_folder = EntryWithFolder {
+            entry: &entry,
+            folder: Some("Работа/AWS"),
+        };
+        let outside = EntryWithFolder {
+            entry: &entry,
+            folder: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&in_folder).unwrap()["folder"],
+            "Работа/AWS"
+        );
+        let json = serde_json::to_value(&outside).unwrap();
+        assert!(json["folder"].is_null());
+        assert!(json.as_object().unwrap().contains_key("folder"));
+    }
+
+    #[test]
+    fn set_field_rejects_unknown_fields() {
+        let mut data = new_token();
+        data.set_field("from", "github").unwrap();
+
+        assert!(matches!(
+            data.set_field("password", "x"),
+            Err(PassHiveError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            &data,
+            DataType::Token { from, .. } if from == "github"
+        ));
     }
 }
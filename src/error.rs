@@ -0,0 +1,138 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PassHiveError {
+    // Ошибка шифрования, KDF или генератора случайных чисел
+    Crypto(String),
+    // Ключ хранилища не расшифровался ключом из пароля
+    WrongPassword,
+    // Ошибка SQLite: открытие файла, запрос, транзакция
+    Db(rusqlite::Error),
+    // Таблица или колонка не совпадает с тем, что ожидает программа
+    SchemaMismatch(String),
+    // Строка хранилища (запись, шаблон, папка, вложение) не расшифровывается или не разбирается
+    CorruptRow { id: i64, reason: String },
+    // Пользователь с таким логином уже зарегистрирован
+    UserExists,
+    // Хранилище заблокировано: сначала нужен unlock
+    Locked,
+    // У пользователя нет записи с таким id
+    NoSuchEntry(i64),
+    // У пользователя нет вложения с таким id
+    NoSuchAttachment(i64),
+    // Версия схемы БД выше, чем поддерживает программа
+    DbTooNew { found: u32, supported: u32 },
+    // Ошибка файловой системы, например при копировании БД
+    Io(std::io::Error),
+    // Неверное значение от пользователя: неизвестное поле, не число и т.п.
+    InvalidInput(String),
+}
+
+impl PassHiveError {
+    pub fn corrupt_row(id: i64, cause: PassHiveError) -> Self {
+        PassHiveError::CorruptRow {
+            id,
+            reason: cause.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for PassHiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassHiveError::Crypto(msg) => write!(f, "Ошибка шифрования: {}", msg),
+            PassHiveError::WrongPassword => write!(f, "Неверный пароль"),
+            PassHiveError::Db(e) => write!(f, "Ошибка базы данных: {}", e),
+            PassHiveError::SchemaMismatch(msg) => {
+                write!(f, "Схема базы данных не совпадает с ожидаемой: {}", msg)
+            }
+            PassHiveError::CorruptRow { id, reason } => {
+                write!(f, "Запись {} повреждена: {}", id, reason)
+            }
+            PassHiveError::UserExists => {
+                write!(f, "Пользователь с таким логином уже существует")
+            }
+            PassHiveError::Locked => write!(f, "Хранилище заблокировано"),
+            PassHiveError::NoSuchEntry(id) => write!(f, "Нет записи с id {}", id),
+            PassHiveError::NoSuchAttachment(id) => write!(f, "Нет вложения с id {}", id),
+            PassHiveError::DbTooNew { found, supported } => write!(
+                f,
+                "База данных создана более новой версией PassHive (схема {}, поддерживается до {}). Обновите программу",
+                found, supported
+            ),
+            PassHiveError::Io(e) => write!(f, "Ошибка файловой системы: {}", e),
+            PassHiveError::InvalidInput(msg) => write!(f, "Неверные данные: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PassHiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PassHiveError::Db(e) => Some(e),
+            PassHiveError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for PassHiveError {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::InvalidColumnIndex(_)
+            | rusqlite::Error::InvalidColumnName(_)
+            | rusqlite::Error::InvalidColumnType(..) => {
+                PassHiveError::SchemaMismatch(e.to_string())
+            }
+            rusqlite::Error::SqliteFailure(_, Some(ref msg))
+                if msg.starts_with("no such table") || msg.starts_with("no such column") =>
+            {
+                PassHiveError::SchemaMismatch(e.to_string())
+            }
+            _ => PassHiveError::Db(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for PassHiveError {
+    fn from(e: std::io::Error) -> Self {
+        PassHiveError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_errors_are_told_apart_from_db_errors() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        let missing_table: PassHiveError = connection
+            .execute("DELETE FROM users", [])
+            .unwrap_err()
+            .into();
+        assert!(matches!(missing_table, PassHiveError::SchemaMismatch(_)));
+
+        connection
+            .execute("CREATE TABLE users (id INTEGER)", [])
+            .unwrap();
+        let missing_column: PassHiveError = connection
+            .execute("UPDATE users SET name = 1", [])
+            .unwrap_err()
+            .into();
+        assert!(matches!(missing_column, PassHiveError::SchemaMismatch(_)));
+
+        let syntax: PassHiveError = connection.execute("DELET", []).unwrap_err().into();
+        assert!(matches!(syntax, PassHiveError::Db(_)));
+        assert!(std::error::Error::source(&syntax).is_some());
+    }
+
+    #[test]
+    fn corrupt_row_keeps_cause() {
+        let error = PassHiveError::corrupt_row(5, PassHiveError::Crypto("aead".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Запись 5 повреждена: Ошибка шифрования: aead"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::error::PassHiveError;
+use serde::Serialize;
+
+// Папки записей. Вложенность задается parent, имя каждой папки шифруется отдельно,
+// путь "Работа/AWS" собирается из имен после расшифровки
+
+pub const SEPARATOR: char = '/';
+
+#[derive(Debug, Serialize, Clone)]
+pub struct Folder {
+    pub id: i64,
+    // None - папка верхнего уровня
+    pub parent: Option<i64>,
+    pub name: String,
+    pub path: String,
+}
+
+pub fn validate_name(name: &str) -> Result<(), PassHiveError> {
+    if name.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "у папки должно быть имя".to_string(),
+        ));
+    }
+    if name.contains([SEPARATOR, '\n']) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в имени папки {} не может быть символа / и перевода строки",
+            name
+        )));
+    }
+    Ok(())
+}
+
+// "Работа / AWS/" -> ["Работа", "AWS"]
+pub fn split_path(path: &str) -> Result<Vec<String>, PassHiveError> {
+    let parts: Vec<String> = path
+        .split(SEPARATOR)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect();
+    if parts.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "укажите путь папки, например Работа/AWS".to_string(),
+        ));
+    }
+    for part in &parts {
+        validate_name(part)?;
+    }
+    Ok(parts)
+}
+
+// Папки с полными путями, по алфавиту путей: вложенные идут сразу за родителем.
+// rows - (id, parent, имя)
+pub fn with_paths(rows: Vec<(i64, Option<i64>, String)>) -> Vec<Folder> {
+    let mut folders: Vec<Folder> = rows
+        .iter()
+        .map(|(id, parent, name)| {
+            let mut path = name.clone();
+            let mut current = *parent;
+            // Глубина ограничена числом папок: испорченный parent не зациклит обход
+            for _ in 0..rows.len() {
+                let Some(parent) = rows.iter().find(|(id, ..)| Some(*id) == current) else {
+                    break;
+                };
+                path = format!("{}{}{}", parent.2, SEPARATOR, path);
+                current = parent.1;
+            }
+            Folder {
+                id: *id,
+                parent: *parent,
+                name: name.clone(),
+                path,
+            }
+        })
+        .collect();
+    folders.sort_by(|a, b| a.path.cmp(&b.path));
+    folders
+}
+
+pub fn find<'a>(folders: &'a [Folder], path: &str) -> Option<&'a Folder> {
+    let path = split_path(path).ok()?.join(&SEPARATOR.to_string());
+    folders.iter().find(|folder| folder.path == path)
+}
+
+pub fn by_id(folders: &[Folder], id: i64) -> Option<&Folder> {
+    folders.iter().find(|folder| folder.id == id)
+}
+
+// Путь папки записи; у записи без папки - пустая строка
+pub fn path_of(folders: &[Folder], id: Option<i64>) -> String {
+    id.and_then(|id| by_id(folders, id))
+        .map(|folder| folder.path.clone())
+        .unwrap_or_default()
+}
+
+// Папка и все вложенные в нее
+pub fn subtree(folders: &[Folder], root: &Folder) -> Vec<i64> {
+    let prefix = format!("{}{}", root.path, SEPARATOR);
+    folders
+        .iter()
+        .filter(|folder| folder.id == root.id || folder.path.starts_with(&prefix))
+        .map(|folder| folder.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folders() -> Vec<Folder> {
+        with_paths(vec![
+            (3, Some(1), "AWS".to_string()),
+            (1, None, "Работа".to_string()),
+            (2, None, "Дом".to_string()),
+            (4, Some(3), "prod".to_string()),
+            (5, None, "Работа2".to_string()),
+        ])
+    }
+
+    #[test]
+    fn path_is_split_and_validated() {
+        assert_eq!(split_path(" Работа / AWS/").unwrap(), ["Работа", "AWS"]);
+        assert!(split_path(" / ").is_err());
+        assert!(split_path("Работа/a\nb").is_err());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(validate_name("AWS").is_ok());
+    }
+
+    #[test]
+    fn paths_are_built_from_parents_and_sorted() {
+        let folders = folders();
+        let paths: Vec<&str> = folders.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["Дом", "Работа", "Работа/AWS", "Работа/AWS/prod", "Работа2"]
+        );
+
+        // Зацикленные parent не зацикливают обход
+        let looped = with_paths(vec![
+            (1, Some(2), "a".to_string()),
+            (2, Some(1), "b".to_string()),
+        ]);
+        assert_eq!(looped.len(), 2);
+    }
+
+    #[test]
+    fn folders_are_found_by_path_and_id() {
+        let folders = folders();
+        assert_eq!(find(&folders, "Работа / AWS/").unwrap().id, 3);
+        assert!(find(&folders, "AWS").is_none());
+        assert_eq!(by_id(&folders, 4).unwrap().path, "Работа/AWS/prod");
+        assert_eq!(path_of(&folders, Some(3)), "Работа/AWS");
+        assert_eq!(path_of(&folders, None), "");
+        assert_eq!(path_of(&folders, Some(42)), "");
+    }
+
+    #[test]
+    fn subtree_excludes_folders_with_common_prefix() {
+        let folders = folders();
+        let mut ids = subtree(&folders, by_id(&folders, 1).unwrap());
+        ids.sort();
+        assert_eq!(ids, [1, 3, 4]);
+        assert_eq!(subtree(&folders, by_id(&folders, 4).unwrap()), [4]);
+    }
+}
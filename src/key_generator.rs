@@ -1 +1,408 @@
+use crate::error::PassHiveError;
+use rand::TryRngCore;
+use rand_core::OsRng;
+use ssh_key::{Algorithm, LineEnding};
+use std::{fmt, fs, path::Path};
+use strum_macros::EnumIter;
+use zeroize::Zeroizing;
 
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+// Символы, которые легко спутать при чтении или ручном вводе
+const AMBIGUOUS: &str = "Il1O0o|`'\"";
+
+// Настройки генератора паролей. Из каждого включенного класса
+// в пароль гарантированно попадает хотя бы один символ
+#[derive(Debug, Clone, Copy)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    pub exclude_ambiguous: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    fn classes(&self) -> Vec<Vec<char>> {
+        let enabled = [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ];
+
+        enabled
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, class)| {
+                class
+                    .chars()
+                    .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+pub fn generate_password(policy: &PasswordPolicy) -> Result<Zeroizing<String>, PassHiveError> {
+    let classes = policy.classes();
+
+    if classes.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "нужно выбрать хотя бы один класс символов".to_string(),
+        ));
+    }
+    if policy.length < classes.len() {
+        return Err(PassHiveError::InvalidInput(format!(
+            "длина пароля должна быть не меньше {} - по символу из каждого класса",
+            classes.len()
+        )));
+    }
+
+    let all: Vec<char> = classes.iter().flatten().copied().collect();
+    let mut chars: Zeroizing<Vec<char>> = Zeroizing::new(Vec::with_capacity(policy.length));
+
+    for class in classes.iter() {
+        chars.push(class[random_below(class.len())?]);
+    }
+    while chars.len() < policy.length {
+        chars.push(all[random_below(all.len())?]);
+    }
+
+    // Фишер-Йетс: обязательные символы не должны стоять в начале
+    for i in (1..chars.len()).rev() {
+        let j = random_below(i + 1)?;
+        chars.swap(i, j);
+    }
+
+    Ok(Zeroizing::new(chars.iter().collect()))
+}
+
+// Встроенные словари для парольных фраз, по слову в строке.
+// Английский - список BIP-39 (2048 слов, 11 бит на слово), русский - частые
+// существительные без ё. Большой список EFF (7776 слов) подключается через --wordlist
+const ENGLISH_WORDS: &str = include_str!("wordlists/english.txt");
+const RUSSIAN_WORDS: &str = include_str!("wordlists/russian.txt");
+
+#[derive(Debug, Clone, Copy, PartialEq, EnumIter)]
+pub enum Wordlist {
+    English,
+    Russian,
+}
+
+impl Wordlist {
+    pub fn words(&self) -> Vec<&'static str> {
+        let text = match self {
+            Wordlist::English => ENGLISH_WORDS,
+            Wordlist::Russian => RUSSIAN_WORDS,
+        };
+        text.lines().filter(|line| !line.is_empty()).collect()
+    }
+}
+
+impl fmt::Display for Wordlist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Wordlist::English => write!(f, "английский ({} слов)", self.words().len()),
+            Wordlist::Russian => write!(f, "русский ({} слов)", self.words().len()),
+        }
+    }
+}
+
+// Словарь из файла. Берется последнее слово строки,
+// поэтому подходят и списки EFF вида "11111<TAB>abacus"
+pub fn load_wordlist(path: &Path) -> Result<Vec<String>, PassHiveError> {
+    let text = fs::read_to_string(path)?;
+
+    let mut words: Vec<String> = text
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(|word| word.to_string())
+        .collect();
+    words.sort();
+    words.dedup();
+
+    if words.len() < 2 {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в словаре {} меньше двух слов",
+            path.display()
+        )));
+    }
+    Ok(words)
+}
+
+#[derive(Debug, Clone)]
+pub struct PassphrasePolicy {
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: bool,
+    pub append_digit: bool,
+    pub append_symbol: bool,
+}
+
+// Размер большого списка EFF: энтропия шести его слов (77,5 бит) - ориентир
+// для числа слов по умолчанию в любом словаре
+pub const EFF_LARGE_LEN: usize = 7776;
+
+impl PassphrasePolicy {
+    // Сколько слов из словаря такого размера дают не меньше энтропии, чем слова
+    // по умолчанию из списка EFF: для EFF - 6, для BIP-39 - 8
+    pub fn words_for(wordlist_len: usize) -> usize {
+        let words = PassphrasePolicy::default().words;
+        if wordlist_len < 2 {
+            return words;
+        }
+        let target = words as f64 * (EFF_LARGE_LEN as f64).log2();
+        // Допуск на округление: словарь размера EFF должен давать ровно 6 слов
+        (target / (wordlist_len as f64).log2() - 1e-9).ceil() as usize
+    }
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        PassphrasePolicy {
+            words: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+            append_digit: false,
+            append_symbol: false,
+        }
+    }
+}
+
+pub struct Passphrase {
+    pub phrase: Zeroizing<String>,
+    // Энтропия при условии, что словарь и настройки известны атакующему
+    pub entropy_bits: f64,
+}
+
+pub fn generate_passphrase<S: AsRef<str>>(
+    wordlist: &[S],
+    policy: &PassphrasePolicy,
+) -> Result<Passphrase, PassHiveError> {
+    if policy.words == 0 {
+        return Err(PassHiveError::InvalidInput(
+            "в фразе должно быть хотя бы одно слово".to_string(),
+        ));
+    }
+    if wordlist.len() < 2 {
+        return Err(PassHiveError::InvalidInput(
+            "в словаре меньше двух слов".to_string(),
+        ));
+    }
+
+    let mut chosen: Vec<Zeroizing<String>> = Vec::with_capacity(policy.words);
+    for _ in 0..policy.words {
+        let word = wordlist[random_below(wordlist.len())?].as_ref();
+        if policy.capitalize {
+            chosen.push(Zeroizing::new(capitalize(word)));
+        } else {
+            chosen.push(Zeroizing::new(word.to_string()));
+        }
+    }
+
+    let mut phrase = Zeroizing::new(
+        chosen
+            .iter()
+            .map(|word| word.as_str())
+            .collect::<Vec<&str>>()
+            .join(&policy.separator),
+    );
+    let mut entropy_bits = policy.words as f64 * (wordlist.len() as f64).log2();
+
+    if policy.append_digit {
+        let digits: Vec<char> = DIGITS.chars().collect();
+        phrase.push(digits[random_below(digits.len())?]);
+        entropy_bits += (digits.len() as f64).log2();
+    }
+    if policy.append_symbol {
+        let symbols: Vec<char> = SYMBOLS.chars().collect();
+        phrase.push(symbols[random_below(symbols.len())?]);
+        entropy_bits += (symbols.len() as f64).log2();
+    }
+
+    Ok(Passphrase {
+        phrase,
+        entropy_bits,
+    })
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Равномерное число из [0, bound) без смещения по модулю:
+// значения из неполного последнего диапазона отбрасываются
+pub fn random_below(bound: usize) -> Result<usize, PassHiveError> {
+    let bound = bound as u64;
+    let zone = u64::MAX - (u64::MAX % bound);
+
+    loop {
+        let value = OsRng.try_next_u64().map_err(|e| {
+            PassHiveError::Crypto(format!("Ошибка генератора случайных чисел: {}", e))
+        })?;
+        if value < zone {
+            return Ok((value % bound) as usize);
+        }
+    }
+}
+
+// Новый ключ SSH ed25519: (закрытый ключ OpenSSH без парольной фразы, строка открытого ключа).
+// Парольная фраза не нужна - ключ хранится только в зашифрованном хранилище
+pub fn generate_ssh_key(comment: &str) -> Result<(Zeroizing<String>, String), PassHiveError> {
+    let mut key =
+        ssh_key::PrivateKey::random(&mut ssh_key::rand_core::OsRng, Algorithm::Ed25519)
+            .map_err(|e| PassHiveError::Crypto(format!("Ошибка генерации ключа SSH: {}", e)))?;
+    key.set_comment(comment);
+
+    let private = key
+        .to_openssh(LineEnding::LF)
+        .map_err(|e| PassHiveError::Crypto(e.to_string()))?;
+    let public = key
+        .public_key()
+        .to_openssh()
+        .map_err(|e| PassHiveError::Crypto(e.to_string()))?;
+    Ok((private, public))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn password_has_every_enabled_class() {
+        let policy = PasswordPolicy {
+            length: 4,
+            ..PasswordPolicy::default()
+        };
+        for _ in 0..50 {
+            let password = generate_password(&policy).unwrap();
+            assert_eq!(password.chars().count(), 4);
+            for class in [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS] {
+                assert!(password.chars().any(|c| class.contains(c)), "{}", *password);
+            }
+        }
+    }
+
+    #[test]
+    fn password_skips_ambiguous_and_disabled_classes() {
+        let policy = PasswordPolicy {
+            length: 64,
+            symbols: false,
+            exclude_ambiguous: true,
+            ..PasswordPolicy::default()
+        };
+        let password = generate_password(&policy).unwrap();
+
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert!(!password.chars().any(|c| AMBIGUOUS.contains(c)));
+    }
+
+    #[test]
+    fn password_policy_must_be_satisfiable() {
+        let none = PasswordPolicy {
+            lowercase: false,
+            uppercase: false,
+            digits: false,
+            symbols: false,
+            ..PasswordPolicy::default()
+        };
+        assert!(generate_password(&none).is_err());
+
+        let short = PasswordPolicy {
+            length: 3,
+            ..PasswordPolicy::default()
+        };
+        assert!(generate_password(&short).is_err());
+    }
+
+    #[test]
+    fn default_word_count_matches_eff_entropy() {
+        assert_eq!(PassphrasePolicy::words_for(EFF_LARGE_LEN), 6);
+        assert_eq!(PassphrasePolicy::words_for(2048), 8);
+        assert_eq!(
+            PassphrasePolicy::words_for(Wordlist::Russian.words().len()),
+            7
+        );
+        for len in [2, 100, 2048, 7776, 100_000] {
+            let words = PassphrasePolicy::words_for(len) as f64;
+            assert!(words * (len as f64).log2() >= 6.0 * (EFF_LARGE_LEN as f64).log2() - 1e-6);
+        }
+    }
+
+    #[test]
+    fn builtin_wordlists_have_unique_words() {
+        for wordlist in [Wordlist::English, Wordlist::Russian] {
+            let mut words = wordlist.words();
+            let len = words.len();
+            words.sort();
+            words.dedup();
+            assert_eq!(words.len(), len, "{}", wordlist);
+        }
+        assert_eq!(Wordlist::English.words().len(), 2048);
+    }
+
+    #[test]
+    fn passphrase_uses_words_from_the_list() {
+        let wordlist = ["alpha", "beta", "gamma", "delta"];
+        let policy = PassphrasePolicy {
+            words: 5,
+            separator: ".".to_string(),
+            capitalize: true,
+            append_digit: true,
+            ..PassphrasePolicy::default()
+        };
+        let passphrase = generate_passphrase(&wordlist, &policy).unwrap();
+
+        let (words, digit) = passphrase.phrase.split_at(passphrase.phrase.len() - 1);
+        assert!(digit.chars().all(|c| c.is_ascii_digit()));
+        let words: Vec<&str> = words.split('.').collect();
+        assert_eq!(words.len(), 5);
+        for word in words {
+            assert!(wordlist.contains(&word.to_lowercase().as_str()), "{}", word);
+        }
+        assert!((passphrase.entropy_bits - (10.0 + 10f64.log2())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn eff_wordlist_file_is_loaded_by_last_column() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("eff.txt");
+        fs::write(&path, "11111\tabacus\n11112\tabdomen\n11113\tabacus\n").unwrap();
+
+        assert_eq!(load_wordlist(&path).unwrap(), vec!["abacus", "abdomen"]);
+
+        fs::write(&path, "11111\tabacus\n").unwrap();
+        assert!(load_wordlist(&path).is_err());
+    }
+
+    #[test]
+    fn random_below_stays_in_range() {
+        let mut seen = [false; 3];
+        for _ in 0..300 {
+            seen[random_below(3).unwrap()] = true;
+        }
+        assert_eq!(seen, [true; 3]);
+        assert_eq!(random_below(1).unwrap(), 0);
+    }
+}
@@ -0,0 +1,20 @@
+pub mod attachment;
+pub mod audit;
+pub mod breach;
+pub mod card;
+pub mod crypto;
+pub mod custom_fields;
+pub mod db;
+pub mod error;
+pub mod folder;
+pub mod key_generator;
+pub mod otp;
+pub mod passport;
+pub mod secret;
+pub mod ssh;
+#[cfg(unix)]
+pub mod ssh_agent;
+pub mod strength;
+pub mod tag;
+pub mod template;
+pub mod vault;
@@ -1,208 +1,202 @@
 mod cli;
-mod crypto;
-mod db;
+mod commands;
 mod os_work;
-use crate::{
-    cli::throw_err,
-    crypto::{create_crypto_key, encrypt_str_with_nonce},
-    db::{db_work::*, models::*},
-};
+use clap::Parser;
 use colored::Colorize;
 use crossterm::{
     event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
     terminal::{disable_raw_mode, enable_raw_mode},
 };
-use db::{db_work, models};
-use std::{collections::BTreeMap, path::PathBuf};
-
-pub struct ShowableData {
-    pub id: i64,
-    pub name: String,
-    pub notice: String,
-    pub data: models::DataType,
+#[cfg(unix)]
+use passhive::ssh_agent::{self, SshAgent};
+use passhive::{
+    audit::AuditPolicy,
+    breach::{self, BreachIndex},
+    crypto,
+    db::models::*,
+    error::PassHiveError,
+    folder::{self, Folder},
+    secret, ssh, tag,
+    vault::{UnlockReport, Vault},
+};
+use std::collections::{BTreeMap, HashMap};
+use strum::IntoEnumIterator;
+
+fn init_user_data(vault: &Vault, grouping: cli::Grouping) -> BTreeMap<String, Vec<ShowableData>> {
+    match vault.list() {
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+            group_rows(vault, Vec::new(), grouping)
+        }
+        Ok(rows) => group_rows(vault, rows, grouping),
+    }
 }
 
-fn init_user_data(path: &PathBuf, id: i64, key: &[u8; 32]) -> BTreeMap<String, Vec<ShowableData>> {
-    let mut uploaded_data = vec![];
-    match db_work::get_all_user_data(path, id) {
-        Err(e) => cli::throw_err(e.to_string()),
-        Ok(data) => uploaded_data = data,
+// Раздел таблицы для записи: формальное имя типа или путь папки ("" - вне папок)
+fn group_key(row: &ShowableData, grouping: cli::Grouping, folders: &[Folder]) -> String {
+    match grouping {
+        cli::Grouping::Type => row.data.formal_name(),
+        cli::Grouping::Folder => folder::path_of(folders, row.folder),
     }
+}
 
+fn group_rows(
+    vault: &Vault,
+    rows: Vec<ShowableData>,
+    grouping: cli::Grouping,
+) -> BTreeMap<String, Vec<ShowableData>> {
     let mut total: BTreeMap<String, Vec<ShowableData>> = BTreeMap::new();
 
-    total.insert("password".to_string(), Vec::new());
-    total.insert("card".to_string(), Vec::new());
-    total.insert("document".to_string(), Vec::new());
-    total.insert("token".to_string(), Vec::new());
-    total.insert("wificonfig".to_string(), Vec::new());
-    total.insert("passport".to_string(), Vec::new());
-
-    for data in uploaded_data.iter() {
-        let decrypted = crypto::decrypt_data(&data.data, &data.nonce, key);
-        match decrypted {
-            Err(e) => cli::throw_err(e),
-            Ok(decrypted_data) => {
-                let mut entry = String::new();
-                let check_type = data.data_type.clone();
-                match check_type {
-                    DataType::Card { .. } => entry = "card".to_string(),
-                    DataType::Token { .. } => entry = "token".to_string(),
-                    DataType::Passport { .. } => entry = "passport".to_string(),
-                    DataType::WifiConfig { .. } => entry = "wificonfig".to_string(),
-                    DataType::Password { .. } => entry = "password".to_string(),
-                    DataType::Document { .. } => entry = "document".to_string(),
-                }
-                match crypto::decrypt_str(&data.name, &data.nonce, key) {
-                    Ok(nam) => match crypto::decrypt_str(&data.notice, &data.nonce, key) {
-                        Ok(not) => total.get_mut(&entry).unwrap().push(ShowableData {
-                            id: data.id,
-                            name: nam,
-                            notice: not,
-                            data: decrypted_data,
-                        }),
-                        Err(e) => cli::throw_err(e),
-                    },
-                    Err(e) => cli::throw_err(e),
-                }
-            }
+    if grouping == cli::Grouping::Type {
+        for data_type in DataType::iter() {
+            total.insert(data_type.formal_name(), Vec::new());
         }
     }
 
+    let folders = vault.folders().unwrap_or_else(|e| {
+        cli::show_err(e);
+        Vec::new()
+    });
+    for row in rows {
+        total
+            .entry(group_key(&row, grouping, &folders))
+            .or_default()
+            .push(row);
+    }
+    // После перегруппировки записи в разделе снова идут в порядке добавления
+    for section in total.values_mut() {
+        section.sort_by_key(|row| row.id);
+    }
+
     total
 }
 
-fn reg(path: &PathBuf) -> bool {
-    let (login, password) = cli::registration();
+// Записи уже расшифрованы - раскладываются заново без обращения к строкам БД.
+// Не прошедшие фильтр уходят в hidden: в таблице и в ее нумерации их нет
+fn regroup(
+    vault: &Vault,
+    all_rows: &mut BTreeMap<String, Vec<ShowableData>>,
+    hidden: &mut Vec<ShowableData>,
+    grouping: cli::Grouping,
+    filter: &cli::Filter,
+) {
+    let rows = std::mem::take(all_rows)
+        .into_values()
+        .flatten()
+        .chain(std::mem::take(hidden));
+    let (shown, rest): (Vec<ShowableData>, Vec<ShowableData>) =
+        rows.partition(|row| filter.matches(row));
+    *hidden = rest;
+    *all_rows = group_rows(vault, shown, grouping);
+}
+
+fn reg(vault: &Vault, min_strength: u8) -> bool {
+    let (login, password) = cli::registration(min_strength);
 
-    match db_work::user_exists(path, &login) {
-        Err(e) => cli::throw_err(e),
-        Ok(b) => {
-            if b {
-                cli::user_exists_err();
-                return false;
-            }
+    let spinner = cli::spinner(cli::HASHING_MSG);
+    let registered = vault.register(&login, &password);
+    spinner.finish_and_clear();
+
+    match registered {
+        Err(PassHiveError::UserExists) => {
+            cli::user_exists_err();
+            false
         }
-    }
-    match crypto::encode(password) {
-        Ok((hash, salt)) => {
+        Err(e) => {
+            cli::show_err(e);
+            false
+        }
+        Ok(_) => {
             cli::regist_success(&login);
-
-            match db_work::reg_new_user(&path, &login, &hash, &salt) {
-                Ok(row) => {
-                    cli::registration_success(&login);
-                    return true;
-                }
-                Err(e) => cli::throw_err(e),
-            }
+            cli::registration_success(&login);
+            true
         }
-        Err(e) => cli::throw_err(e),
     }
-
-    unreachable!("Ошибка в цикле регистрации!");
 }
 
-fn auth(path: &PathBuf) -> (i64, [u8; 32]) {
-    //ID в БД + ключ
+fn auth(vault: &mut Vault) -> UnlockReport {
     loop {
         print!("\x1B[2J\x1B[1;1H");
-        let (input_login, input_password) = cli::get_auth_data(&path);
-
-        match db_work::find_by_login(path, &input_login) {
-            Err(e) => cli::throw_err(e),
-            Ok(response) => match response {
-                Some((db_id, hash)) => match crypto::check_password(&hash, &input_password) {
-                    Err(e) => cli::throw_err(e),
-                    Ok(is_correct) => {
-                        if is_correct {
-                            cli::auth_seccess();
-                            match db_work::get_salt_by_id(path, db_id) {
-                                Err(e) => {
-                                    cli::throw_err(e);
-                                }
-                                Ok(salt) => {
-                                    match create_crypto_key(salt.as_str(), input_password.as_str())
-                                    {
-                                        Err(e) => cli::throw_err(e),
-                                        Ok(key) => return (db_id, key),
-                                    }
-                                }
-                            }
-                        } else {
-                            cli::auth_failure();
-                            continue;
-                        }
-                    }
-                },
-                _ => {
-                    println!(
-                        "{}",
-                        "Ошибка аутентификации. Попробуйте еще раз.".purple().bold()
-                    );
-                    continue;
-                }
-            },
+        let (input_login, input_password) = cli::get_auth_data(vault.path());
+
+        let spinner = cli::spinner(cli::CHECKING_MSG);
+        let unlocked = vault.unlock(&input_login, &input_password);
+        spinner.finish_and_clear();
+
+        match unlocked {
+            Err(PassHiveError::WrongPassword) => {
+                cli::auth_failure();
+                continue;
+            }
+            Err(e) => {
+                cli::show_err(e);
+                cli::wait_enter();
+            }
+            Ok(report) => {
+                cli::auth_seccess();
+                return report;
+            }
         }
     }
 }
 
 fn add_row_mode(
-    path: &PathBuf,
-    id: i64,
-    key: &[u8; 32],
+    vault: &Vault,
     all_rows: &mut BTreeMap<String, Vec<ShowableData>>,
+    grouping: cli::Grouping,
 ) {
     let new_row;
-    match cli::get_new_row_data() {
+    let templates = match vault.templates() {
+        Ok(templates) => templates,
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+            return;
+        }
+    };
+    match cli::get_new_row_data(&templates) {
         Err(e) => {
-            cli::throw_err(e);
+            cli::show_err(e);
+            cli::wait_enter();
             return;
         }
         Ok(row) => new_row = row,
     }
 
-    match crypto::encrypt_data(&new_row.data, key) {
-        Err(e) => cli::throw_err(e),
-        Ok((enc_data, nonce)) => {
-            match crypto::encrypt_str_with_nonce(&new_row.name.as_str(), key, &nonce) {
-                Err(e) => cli::throw_err(e),
-                Ok(enc_name) => {
-                    match encrypt_str_with_nonce(&new_row.notice.as_str(), key, &nonce) {
-                        Err(e) => cli::throw_err(e),
-                        Ok(enc_notice) => match db_work::insert_row(
-                            path,
-                            id,
-                            &new_row.data.formal_name(),
-                            &enc_data,
-                            &enc_name,
-                            &enc_notice,
-                            &nonce,
-                        ) {
-                            Err(e) => cli::throw_err(e),
-                            Ok(fresh_id) => {
-                                all_rows.get_mut(&new_row.data.formal_name()).unwrap().push(
-                                    ShowableData {
-                                        id: fresh_id,
-                                        name: new_row.name,
-                                        notice: new_row.notice,
-                                        data: new_row.data,
-                                    },
-                                );
-                            }
-                        },
-                    }
-                }
-            }
+    match vault.add(&new_row) {
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+        }
+        Ok(fresh_id) => {
+            let row = ShowableData {
+                id: fresh_id,
+                name: new_row.name.clone(),
+                notice: new_row.notice.clone(),
+                data: new_row.data.clone(),
+                custom_fields: new_row.custom_fields.clone(),
+                folder: new_row.folder,
+                labels: new_row.labels.clone(),
+            };
+            let folders = vault.folders().unwrap_or_default();
+            all_rows
+                .entry(group_key(&row, grouping, &folders))
+                .or_default()
+                .push(row);
         }
     }
 }
-fn update_row_mode(
-    path: &PathBuf,
-    id: i64,
-    key: &[u8; 32],
-    all_rows: &mut BTreeMap<String, Vec<ShowableData>>,
-) {
-    let (db_id, (partision_index, local_index)) = cli::select_id_to_update(all_rows);
+fn update_row_mode(vault: &Vault, all_rows: &mut BTreeMap<String, Vec<ShowableData>>) {
+    let templates = match vault.templates() {
+        Ok(templates) => templates,
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+            return;
+        }
+    };
+    let (_, (partision_index, local_index)) = cli::select_id_to_update(all_rows);
     let mut target: Option<&mut ShowableData> = None;
 
     for part in all_rows.iter_mut().enumerate() {
@@ -219,33 +213,25 @@ fn update_row_mode(
 
     let free_target = target.expect("Каким-то образом ненайденный id прошел мимо expect ранее");
 
-    let updated_data = cli::correct_data(free_target);
-
-    match crypto::encrypt_data(&updated_data.data, key) {
-        Err(e) => throw_err(e),
-        Ok((encrypted, nonce)) => {
-            match crypto::encrypt_str_with_nonce(&updated_data.name, key, &nonce) {
-                Err(e) => throw_err(e),
-                Ok(enc_name) => {
-                    match crypto::encrypt_str_with_nonce(&updated_data.notice, key, &nonce) {
-                        Err(e) => throw_err(e),
-                        Ok(enc_notice) => {
-                            match update_row(path, encrypted, nonce, enc_name, enc_notice, db_id) {
-                                Err(e) => throw_err(e),
-                                Ok(()) => {
-                                    *free_target = updated_data;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    let updated_data = cli::correct_data(free_target, &templates);
+
+    match vault.update(&updated_data) {
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+        }
+        Ok(()) => {
+            *free_target = updated_data;
         }
     }
 }
-fn delete_row_mode(path: &PathBuf, all_rows: &mut BTreeMap<String, Vec<ShowableData>>) {
+fn delete_row_mode(vault: &Vault, all_rows: &mut BTreeMap<String, Vec<ShowableData>>) {
     let (db_id, (partision_index, local_index)) = cli::select_id_to_delete(all_rows);
-    db_work::delete_row(path, db_id).expect("Ошибка удаления из БД");
+    if let Err(e) = vault.delete(db_id) {
+        cli::show_err(e);
+        cli::wait_enter();
+        return;
+    }
 
     for part in all_rows.iter_mut().enumerate() {
         if part.0 == partision_index {
@@ -255,6 +241,209 @@ fn delete_row_mode(path: &PathBuf, all_rows: &mut BTreeMap<String, Vec<ShowableD
     }
 }
 
+fn change_password_mode(vault: &Vault, min_strength: u8) {
+    let login = match vault.login() {
+        Ok(login) => login,
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+            return;
+        }
+    };
+    let (old_password, new_password) = cli::get_password_change(login, min_strength);
+
+    let spinner = cli::spinner(cli::HASHING_MSG);
+    let changed = vault.change_password(&old_password, &new_password);
+    spinner.finish_and_clear();
+
+    match changed {
+        Err(PassHiveError::WrongPassword) => cli::auth_failure(),
+        Err(e) => cli::show_err(e),
+        Ok(()) => {
+            cli::password_changed();
+            match vault.migration_backups() {
+                Ok(backups) if !backups.is_empty() => cli::backups_keep_old_password(&backups),
+                Ok(_) => {}
+                Err(e) => cli::show_err(e),
+            }
+        }
+    }
+    cli::wait_enter();
+}
+
+fn audit_mode(
+    vault: &Vault,
+    all_rows: &mut BTreeMap<String, Vec<ShowableData>>,
+    policy: &AuditPolicy,
+) {
+    match vault.audit(policy) {
+        Err(e) => cli::show_err(e),
+        Ok(report) => {
+            cli::show_audit(&report, all_rows, policy);
+            if !report.is_clean() && cli::offer_audit_fix() {
+                update_row_mode(vault, all_rows);
+                return;
+            }
+        }
+    }
+    cli::wait_enter();
+}
+
+// Ctrl+G: агент запускается с ключами из хранилища и останавливается повторным нажатием.
+// Ключи берутся и из записей, скрытых фильтром
+#[cfg(unix)]
+fn ssh_agent_mode(
+    agent: &mut Option<SshAgent>,
+    all_rows: &BTreeMap<String, Vec<ShowableData>>,
+    hidden: &[ShowableData],
+) {
+    match agent.take() {
+        Some(running) => {
+            drop(running);
+            cli::ssh_agent_stopped();
+        }
+        None => {
+            let keys = ssh::collect_keys(all_rows.values().flatten().chain(hidden));
+            if keys.is_empty() {
+                cli::show_err("В хранилище нет ключей SSH!");
+            } else {
+                match ssh_agent::default_socket_path().and_then(|path| SshAgent::start(&path, keys))
+                {
+                    Err(e) => cli::show_err(e),
+                    Ok(started) => {
+                        cli::ssh_agent_started(started.socket(), started.key_count());
+                        *agent = Some(started);
+                    }
+                }
+            }
+        }
+    }
+    cli::wait_enter();
+}
+
+// Агент выдает ключи в том виде, в каком они сейчас в хранилище
+#[cfg(unix)]
+fn refresh_ssh_agent(
+    agent: &Option<SshAgent>,
+    all_rows: &BTreeMap<String, Vec<ShowableData>>,
+    hidden: &[ShowableData],
+) {
+    if let Some(agent) = agent
+        && let Err(e) = agent.set_keys(ssh::collect_keys(all_rows.values().flatten().chain(hidden)))
+    {
+        cli::show_err(e);
+        cli::wait_enter();
+    }
+}
+
+// Ctrl+F: папки меняются в БД; разложить записи заново должен вызывающий
+fn folders_mode(vault: &Vault, all_rows: &mut BTreeMap<String, Vec<ShowableData>>) {
+    let (folders, counts) = match vault
+        .folders()
+        .and_then(|f| Ok((f, vault.folder_counts()?)))
+    {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            cli::show_err(e);
+            cli::wait_enter();
+            return;
+        }
+    };
+
+    match cli::manage_folders(&folders, &counts, all_rows) {
+        cli::FolderManipulation::Create(path) => match vault.create_folder(&path) {
+            Err(e) => cli::show_err(e),
+            Ok(_) => cli::folder_changed("Папка создана:"),
+        },
+        cli::FolderManipulation::Move(id, target) => match vault.move_entry(id, target) {
+            Err(e) => cli::show_err(e),
+            Ok(()) => {
+                if let Some(row) = all_rows.values_mut().flatten().find(|row| row.id == id) {
+                    row.folder = target;
+                }
+                cli::folder_changed("Запись перенесена:");
+            }
+        },
+        cli::FolderManipulation::Delete(id) => match vault.delete_folder(id) {
+            Err(e) => cli::show_err(e),
+            Ok(()) => cli::folder_changed("Папка удалена:"),
+        },
+        cli::FolderManipulation::Cancel => {}
+    }
+    cli::wait_enter();
+}
+
+// Ctrl+T: после ошибки (например, шаблон с таким названием уже есть) меню показывается снова
+fn templates_mode(vault: &Vault) {
+    loop {
+        let templates = match vault.templates() {
+            Ok(templates) => templates,
+            Err(e) => {
+                cli::show_err(e);
+                break;
+            }
+        };
+
+        match cli::manage_templates(&templates) {
+            cli::TemplateManipulation::Create(template) => match vault.add_template(&template) {
+                Err(e) => cli::show_err(e),
+                Ok(_) => {
+                    cli::template_saved();
+                    break;
+                }
+            },
+            cli::TemplateManipulation::Delete(id) => match vault.delete_template(id) {
+                Err(e) => cli::show_err(e),
+                Ok(()) => {
+                    cli::template_deleted();
+                    break;
+                }
+            },
+            cli::TemplateManipulation::Cancel => break,
+        }
+    }
+    cli::wait_enter();
+}
+
+// Проверяются и скрытые фильтром записи: после смены фильтра проверка не повторяется
+fn check_breaches(
+    index: Option<&BreachIndex>,
+    all_rows: &BTreeMap<String, Vec<ShowableData>>,
+    hidden: &[ShowableData],
+) -> HashMap<i64, u64> {
+    let Some(index) = index else {
+        return HashMap::new();
+    };
+    match breach::check_entries(all_rows.values().flatten().chain(hidden), index) {
+        Ok(breached) => breached,
+        Err(e) => {
+            cli::breach_check_failed(&e);
+            HashMap::new()
+        }
+    }
+}
+
+fn calibrate_kdf_mode(vault: &Vault) {
+    let (target, algorithm, memory_kib, parallelism) = cli::get_calibration_settings();
+
+    let spinner = cli::spinner(cli::CALIBRATING_MSG);
+    let calibrated = crypto::calibrate_kdf(target, algorithm, memory_kib, parallelism);
+    spinner.finish_and_clear();
+
+    match calibrated {
+        Err(e) => cli::show_err(e),
+        Ok(kdf) => {
+            if cli::confirm_calibrated_kdf(&kdf) {
+                match vault.schedule_kdf_upgrade(&kdf) {
+                    Err(e) => cli::show_err(e),
+                    Ok(()) => cli::kdf_upgrade_scheduled(),
+                }
+            }
+        }
+    }
+    cli::wait_enter();
+}
+
 pub fn update_or_save<T>(first: Option<T>, second: T) -> T {
     //Если первый не None - возвращает первый аргумент. Иначе второй
     if let None = first {
@@ -274,6 +463,13 @@ fn main() {
         (255, 255, 255),
     );
 
+    secret::harden_process();
+
+    let mut args = commands::Args::parse();
+    if let Some(command) = args.command.take() {
+        std::process::exit(commands::run(command, &args));
+    }
+
     cli::hi();
 
     let (response, path, os) = os_work::verify_data();
@@ -291,47 +487,76 @@ fn main() {
     if os_work::check_exists(&path, "passhive.db") {
         cli::db_conn_success();
     } else {
-        if let Err(err) = db_work::init_db(&path) {
-            cli::throw_err(err.to_string());
-        }
         cli::success_init_db();
     }
 
-    let mut main_user_id = -1; //ID вошедшего пользователя
-    let mut main_key = [0u8; 32]; // Основной ключ для шифрования
-    let mut exit = false;
+    let mut vault = match Vault::open(&path) {
+        Ok(vault) => vault,
+        Err(err) => {
+            cli::throw_err(err);
+            return;
+        }
+    };
+    if let Some(backup) = vault.migration_backup() {
+        cli::db_migrated(backup);
+    }
+
     loop {
-        if db_work::users_empty(&path) {
-            if !reg(&path) {
+        let has_users = match vault.has_users() {
+            Ok(has) => has,
+            // Например, БД заблокирована другим процессом - можно повторить
+            Err(e) => {
+                cli::show_err(e);
+                cli::wait_enter();
                 continue;
             }
-        } else {
-            match cli::log_or_reg() {
-                cli::AccountManipulation::Regist => {
-                    if !reg(&path) {
-                        continue;
-                    }
+        };
+        if !has_users {
+            reg(&vault, args.min_strength);
+            continue;
+        }
+        match cli::log_or_reg() {
+            cli::AccountManipulation::Regist => {
+                reg(&vault, args.min_strength);
+            }
+            cli::AccountManipulation::Auth => {
+                let report = auth(&mut vault);
+                if report.migrated_rows > 0 {
+                    cli::legacy_rows_migrated(report.migrated_rows);
                 }
-                cli::AccountManipulation::Auth => {
-                    (main_user_id, main_key) = auth(&path);
-                    exit = true;
-                    break;
+                if let Some(kdf) = report.kdf_upgraded {
+                    cli::kdf_upgraded(&kdf);
                 }
+                break;
             }
         }
-        if exit {
-            break;
-        }
     }
 
+    let audit_policy = AuditPolicy {
+        min_score: args.min_strength,
+        max_age_days: args.max_age_days,
+    };
+
     // вход прошел успешно - основной цикл
-    if main_user_id == -1 {
-        cli::throw_err(
-            "Ошибка выхода из цикла входа + регистрации, id пользователя - -1!!!".to_string(),
-        );
-    }
+    let mut grouping = cli::Grouping::Type;
+    let mut global_user_data = init_user_data(&vault, grouping);
+    let mut filter = cli::Filter::default();
+    let mut hidden_rows: Vec<ShowableData> = Vec::new();
 
-    let mut global_user_data = init_user_data(&path, main_user_id, &main_key);
+    // Без выгрузки HIBP проверка на утечки просто не выполняется
+    let breach_index = match &args.hibp {
+        None => None,
+        Some(path) => match BreachIndex::open(path) {
+            Ok(index) => Some(index),
+            Err(e) => {
+                cli::breach_check_failed(&e);
+                None
+            }
+        },
+    };
+    let mut breached = check_breaches(breach_index.as_ref(), &global_user_data, &hidden_rows);
+    #[cfg(unix)]
+    let mut agent: Option<SshAgent> = None;
 
     loop {
         if let Err(e) = enable_raw_mode() {
@@ -343,32 +568,165 @@ fn main() {
         // cli::show_all_data(&global_user_data); минималистичная функция для просмотра записей, включать только для дебага добавления записей
         enable_raw_mode().expect("Ошибка входа в сырой режим!");
         cli::show_hotkeys();
-        cli::show_data_extended(&global_user_data, &default_style);
-        if let Err(_) = event::poll(std::time::Duration::from_millis(500)) {
-            cli::throw_err("Ошибка обработки событий!".to_string());
-        } else {
-            if let Event::Key(KeyEvent {
-                code,
-                modifiers,
-                kind,
-                state,
-            }) = event::read().unwrap()
-            {
-                match (code, modifiers) {
-                    (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
-                        add_row_mode(&path, main_user_id, &main_key, &mut global_user_data);
-                    }
-                    (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
-                        update_row_mode(&path, main_user_id, &main_key, &mut global_user_data);
-                    }
-                    (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
-                        delete_row_mode(&path, &mut global_user_data);
-                    }
-                    (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
-                        disable_raw_mode().unwrap();
-                        std::process::exit(0);
+        cli::show_filter(&filter, hidden_rows.len());
+        cli::show_data_extended(&global_user_data, grouping, &breached, &default_style);
+        match event::poll(std::time::Duration::from_millis(500)) {
+            Err(_) => cli::throw_err("Ошибка обработки событий!".to_string()),
+            // Нажатий не было - таблица перерисовывается, чтобы шел обратный отсчет TOTP
+            Ok(false) => {}
+            Ok(true) => {
+                if let Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    state,
+                }) = event::read().unwrap()
+                {
+                    match (code, modifiers) {
+                        (KeyCode::Char('a'), KeyModifiers::CONTROL) => {
+                            add_row_mode(&vault, &mut global_user_data, grouping);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                            breached = check_breaches(
+                                breach_index.as_ref(),
+                                &global_user_data,
+                                &hidden_rows,
+                            );
+                            #[cfg(unix)]
+                            refresh_ssh_agent(&agent, &global_user_data, &hidden_rows);
+                        }
+                        (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                            update_row_mode(&vault, &mut global_user_data);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                            breached = check_breaches(
+                                breach_index.as_ref(),
+                                &global_user_data,
+                                &hidden_rows,
+                            );
+                            #[cfg(unix)]
+                            refresh_ssh_agent(&agent, &global_user_data, &hidden_rows);
+                        }
+                        (KeyCode::Char('d'), KeyModifiers::CONTROL) => {
+                            delete_row_mode(&vault, &mut global_user_data);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                            breached = check_breaches(
+                                breach_index.as_ref(),
+                                &global_user_data,
+                                &hidden_rows,
+                            );
+                            #[cfg(unix)]
+                            refresh_ssh_agent(&agent, &global_user_data, &hidden_rows);
+                        }
+                        (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                            change_password_mode(&vault, args.min_strength);
+                        }
+                        (KeyCode::Char('t'), KeyModifiers::CONTROL) => {
+                            templates_mode(&vault);
+                        }
+                        (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
+                            folders_mode(&vault, &mut global_user_data);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                        }
+                        (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
+                            grouping = match grouping {
+                                cli::Grouping::Type => cli::Grouping::Folder,
+                                cli::Grouping::Folder => cli::Grouping::Type,
+                            };
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                        }
+                        (KeyCode::Char('l'), KeyModifiers::CONTROL) => {
+                            let known = tag::count(
+                                global_user_data
+                                    .values()
+                                    .flatten()
+                                    .chain(&hidden_rows)
+                                    .map(|row| row.labels.tags.as_slice()),
+                            );
+                            filter.tag = cli::ask_tag_filter(&known);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                        }
+                        (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
+                            filter.favorites_only = !filter.favorites_only;
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                        }
+                        (KeyCode::Char('k'), KeyModifiers::CONTROL) => {
+                            calibrate_kdf_mode(&vault);
+                        }
+                        (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                            audit_mode(&vault, &mut global_user_data, &audit_policy);
+                            regroup(
+                                &vault,
+                                &mut global_user_data,
+                                &mut hidden_rows,
+                                grouping,
+                                &filter,
+                            );
+                            breached = check_breaches(
+                                breach_index.as_ref(),
+                                &global_user_data,
+                                &hidden_rows,
+                            );
+                            #[cfg(unix)]
+                            refresh_ssh_agent(&agent, &global_user_data, &hidden_rows);
+                        }
+                        #[cfg(unix)]
+                        (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                            ssh_agent_mode(&mut agent, &global_user_data, &hidden_rows);
+                        }
+                        (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                            disable_raw_mode().unwrap();
+                            // Агент удаляет сокет и забывает ключи только в деструкторе
+                            #[cfg(unix)]
+                            drop(agent);
+                            // exit не вызывает деструкторы - затираем секреты вручную
+                            drop(global_user_data);
+                            drop(hidden_rows);
+                            vault.lock();
+                            std::process::exit(0);
+                        }
+                        _ => {}
                     }
-                    _ => {}
                 }
             }
         }
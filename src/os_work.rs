@@ -98,3 +98,26 @@ pub fn install_rust() {
         }
     }
 }
+
+// Блокирует SIGINT, SIGTERM и SIGHUP: вызывать до запуска потоков,
+// чтобы они унаследовали маску и сигнал получил только wait_for_signal
+#[cfg(unix)]
+pub fn block_termination_signals() -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            libc::sigaddset(&mut set, signal);
+        }
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    }
+}
+
+#[cfg(unix)]
+pub fn wait_for_signal(set: &libc::sigset_t) {
+    let mut signal = 0;
+    unsafe {
+        libc::sigwait(set, &mut signal);
+    }
+}
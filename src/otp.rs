@@ -0,0 +1,493 @@
+use crate::db::models::{DataType, OtpAlgorithm};
+use crate::error::PassHiveError;
+use data_encoding::{Encoding, Specification};
+use hmac::{Hmac, Mac};
+use percent_encoding::percent_decode_str;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// Одноразовые коды HOTP (RFC 4226) и TOTP (RFC 6238)
+
+// Параметры по умолчанию из RFC и Google Authenticator
+pub const DEFAULT_DIGITS: u32 = 6;
+pub const DEFAULT_PERIOD: u64 = 30;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub struct OtpCode {
+    pub code: Zeroizing<String>,
+    // TOTP - сколько секунд код еще действует, HOTP - None
+    pub remaining: Option<u64>,
+}
+
+// Base32 без паддинга и без проверки хвостовых битов:
+// секреты из разных сервисов бывают записаны небрежно
+fn base32() -> &'static Encoding {
+    static BASE32: OnceLock<Encoding> = OnceLock::new();
+    BASE32.get_or_init(|| {
+        let mut spec = Specification::new();
+        spec.symbols.push_str("ABCDEFGHIJKLMNOPQRSTUVWXYZ234567");
+        spec.check_trailing_bits = false;
+        spec.encoding().expect("Ошибка описания base32")
+    })
+}
+
+// Пробелы, дефисы, паддинг и регистр не важны: "jbsw y3dp-ehpk 3pxp" тоже подходит
+pub fn decode_secret(secret: &str) -> Result<Zeroizing<Vec<u8>>, PassHiveError> {
+    let normalized: Zeroizing<String> = Zeroizing::new(
+        secret
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-' && *c != '=')
+            .map(|c| c.to_ascii_uppercase())
+            .collect(),
+    );
+
+    match base32().decode(normalized.as_bytes()) {
+        Ok(key) if !key.is_empty() => Ok(Zeroizing::new(key)),
+        _ => Err(PassHiveError::InvalidInput(
+            "секрет OTP должен быть непустой строкой base32".to_string(),
+        )),
+    }
+}
+
+fn hmac_digest<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC принимает ключ любой длины");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn hotp(
+    key: &[u8],
+    counter: u64,
+    algorithm: OtpAlgorithm,
+    digits: u32,
+) -> Result<Zeroizing<String>, PassHiveError> {
+    if !(6..=10).contains(&digits) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в коде OTP должно быть от 6 до 10 цифр, указано {}",
+            digits
+        )));
+    }
+
+    let message = counter.to_be_bytes();
+    let digest = Zeroizing::new(match algorithm {
+        OtpAlgorithm::Sha1 => hmac_digest::<Hmac<Sha1>>(key, &message),
+        OtpAlgorithm::Sha256 => hmac_digest::<Hmac<Sha256>>(key, &message),
+        OtpAlgorithm::Sha512 => hmac_digest::<Hmac<Sha512>>(key, &message),
+    });
+
+    // Динамическое усечение: 31 бит со смещения из младших битов последнего байта
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let truncated = u32::from_be_bytes([
+        digest[offset],
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    let code = truncated as u64 % 10u64.pow(digits);
+    Ok(Zeroizing::new(format!(
+        "{:0width$}",
+        code,
+        width = digits as usize
+    )))
+}
+
+pub fn totp(
+    key: &[u8],
+    now: u64,
+    period: u64,
+    algorithm: OtpAlgorithm,
+    digits: u32,
+) -> Result<OtpCode, PassHiveError> {
+    if period == 0 {
+        return Err(PassHiveError::InvalidInput(
+            "период TOTP должен быть больше нуля".to_string(),
+        ));
+    }
+
+    Ok(OtpCode {
+        code: hotp(key, now / period, algorithm, digits)?,
+        remaining: Some(period - now % period),
+    })
+}
+
+// Код записи Otp или Password с полем totp. Для HOTP выдается код текущего
+// счетчика - сдвинуть его после выдачи должен вызывающий через advance_counter
+pub fn code_for(data: &DataType, now: u64) -> Result<OtpCode, PassHiveError> {
+    match data {
+        DataType::Otp {
+            secret,
+            algorithm,
+            digits,
+            period,
+            counter,
+            ..
+        } => {
+            let key = decode_secret(secret)?;
+            match counter {
+                None => totp(&key, now, *period, *algorithm, *digits),
+                Some(counter) => Ok(OtpCode {
+                    code: hotp(&key, *counter, *algorithm, *digits)?,
+                    remaining: None,
+                }),
+            }
+        }
+        DataType::Password {
+            totp: Some(totp), ..
+        } => {
+            // В пароле хранится либо otpauth:// ссылка, либо голый секрет TOTP
+            if totp.starts_with("otpauth://") {
+                code_for(&parse_otpauth(totp)?, now)
+            } else {
+                let key = decode_secret(totp)?;
+                self::totp(
+                    &key,
+                    now,
+                    DEFAULT_PERIOD,
+                    OtpAlgorithm::Sha1,
+                    DEFAULT_DIGITS,
+                )
+            }
+        }
+        _ => Err(PassHiveError::InvalidInput(format!(
+            "у записи типа {} нет одноразовых кодов",
+            data.formal_name()
+        ))),
+    }
+}
+
+// Сдвигает счетчик HOTP после выдачи кода; false - у записи TOTP и сдвигать нечего.
+// У пароля счетчик хранится в параметре counter его otpauth://hotp ссылки
+pub fn advance_counter(data: &mut DataType) -> Result<bool, PassHiveError> {
+    match data {
+        DataType::Otp {
+            counter: Some(counter),
+            ..
+        } => {
+            *counter = next_counter(*counter)?;
+            Ok(true)
+        }
+        DataType::Password {
+            totp: Some(totp), ..
+        } if totp.starts_with("otpauth://") => {
+            let DataType::Otp {
+                counter: Some(counter),
+                ..
+            } = parse_otpauth(totp)?
+            else {
+                return Ok(false);
+            };
+            *totp = with_counter(totp, next_counter(counter)?);
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn next_counter(counter: u64) -> Result<u64, PassHiveError> {
+    counter.checked_add(1).ok_or_else(|| {
+        PassHiveError::InvalidInput("счетчик HOTP исчерпан, создайте новый секрет".to_string())
+    })
+}
+
+// Ссылка с новым значением counter; остальные параметры остаются как были
+fn with_counter(uri: &str, counter: u64) -> String {
+    let (base, query) = uri.trim().split_once('?').unwrap_or((uri.trim(), ""));
+    let mut pairs: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split_once('=').map_or(*pair, |(key, _)| key);
+            !pair.is_empty() && !key.eq_ignore_ascii_case("counter")
+        })
+        .collect();
+    let counter = format!("counter={}", counter);
+    pairs.push(&counter);
+    format!("{}?{}", base, pairs.join("&"))
+}
+
+// Живой код для таблицы: только TOTP. HOTP не показывается,
+// потому что каждый показанный код должен сдвигать счетчик
+pub fn live_code(data: &DataType, now: u64) -> Option<Result<OtpCode, PassHiveError>> {
+    match data {
+        DataType::Otp { counter: None, .. } | DataType::Password { totp: Some(_), .. } => {
+            match code_for(data, now) {
+                Ok(code) if code.remaining.is_none() => None,
+                result => Some(result),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn decode_component(raw: &str) -> Result<String, PassHiveError> {
+    percent_decode_str(&raw.replace('+', " "))
+        .decode_utf8()
+        .map(|text| text.to_string())
+        .map_err(|_| {
+            PassHiveError::InvalidInput("неверная кодировка в otpauth:// ссылке".to_string())
+        })
+}
+
+// otpauth://totp/Сервис:аккаунт?secret=...&issuer=...&algorithm=SHA1&digits=6&period=30
+// otpauth://hotp/...&counter=0 (формат Google Authenticator Key Uri)
+pub fn parse_otpauth(uri: &str) -> Result<DataType, PassHiveError> {
+    let invalid =
+        |reason: &str| PassHiveError::InvalidInput(format!("otpauth:// ссылка: {}", reason));
+
+    let rest = uri
+        .trim()
+        .strip_prefix("otpauth://")
+        .ok_or_else(|| invalid("должна начинаться с otpauth://"))?;
+    let (kind, rest) = rest
+        .split_once('/')
+        .ok_or_else(|| invalid("нет типа totp или hotp"))?;
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let label = decode_component(label)?;
+    let (mut issuer, account) = match label.split_once(':') {
+        Some((issuer, account)) => (issuer.trim().to_string(), account.trim().to_string()),
+        None => (String::new(), label.trim().to_string()),
+    };
+
+    let mut secret = None;
+    let mut algorithm = OtpAlgorithm::Sha1;
+    let mut digits = DEFAULT_DIGITS;
+    let mut period = DEFAULT_PERIOD;
+    let mut counter = None;
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode_component(value)?;
+        match key.to_ascii_lowercase().as_str() {
+            "secret" => secret = Some(value),
+            "issuer" => issuer = value,
+            "algorithm" => {
+                algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => OtpAlgorithm::Sha1,
+                    "SHA256" => OtpAlgorithm::Sha256,
+                    "SHA512" => OtpAlgorithm::Sha512,
+                    _ => return Err(invalid("алгоритм должен быть SHA1, SHA256 или SHA512")),
+                }
+            }
+            "digits" => digits = value.parse().map_err(|_| invalid("digits - не число"))?,
+            "period" => period = value.parse().map_err(|_| invalid("period - не число"))?,
+            "counter" => counter = Some(value.parse().map_err(|_| invalid("counter - не число"))?),
+            // image, color и прочие расширения приложений не нужны
+            _ => {}
+        }
+    }
+
+    let secret = secret.ok_or_else(|| invalid("нет параметра secret"))?;
+    decode_secret(&secret)?;
+
+    let counter = match kind.to_ascii_lowercase().as_str() {
+        "totp" => None,
+        "hotp" => Some(counter.unwrap_or(0)),
+        _ => return Err(invalid("тип должен быть totp или hotp")),
+    };
+
+    let data = DataType::Otp {
+        secret: secret.to_ascii_uppercase(),
+        issuer,
+        account,
+        algorithm,
+        digits,
+        period,
+        counter,
+    };
+    // Проверяем digits и period сразу, а не при первом показе кода
+    code_for(&data, 0)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC_SHA1_KEY: &[u8] = b"12345678901234567890";
+    const RFC_SHA256_KEY: &[u8] = b"12345678901234567890123456789012";
+    const RFC_SHA512_KEY: &[u8] =
+        b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    // RFC 4226, приложение D
+    #[test]
+    fn hotp_matches_rfc4226_vectors() {
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            let actual = hotp(RFC_SHA1_KEY, counter as u64, OtpAlgorithm::Sha1, 6).unwrap();
+            assert_eq!(actual.as_str(), *code, "counter {}", counter);
+        }
+    }
+
+    // RFC 6238, приложение B: 8 цифр, шаг 30 секунд
+    #[test]
+    fn totp_matches_rfc6238_vectors() {
+        let expected = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (now, sha1, sha256, sha512) in expected {
+            for (key, algorithm, code) in [
+                (RFC_SHA1_KEY, OtpAlgorithm::Sha1, sha1),
+                (RFC_SHA256_KEY, OtpAlgorithm::Sha256, sha256),
+                (RFC_SHA512_KEY, OtpAlgorithm::Sha512, sha512),
+            ] {
+                let actual = totp(key, now, 30, algorithm, 8).unwrap();
+                assert_eq!(actual.code.as_str(), code, "{} {}", algorithm, now);
+            }
+        }
+        assert_eq!(
+            totp(RFC_SHA1_KEY, 59, 30, OtpAlgorithm::Sha1, 8)
+                .unwrap()
+                .remaining,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn digits_and_period_are_checked() {
+        assert!(hotp(RFC_SHA1_KEY, 0, OtpAlgorithm::Sha1, 5).is_err());
+        assert!(hotp(RFC_SHA1_KEY, 0, OtpAlgorithm::Sha1, 11).is_err());
+        assert!(totp(RFC_SHA1_KEY, 59, 0, OtpAlgorithm::Sha1, 6).is_err());
+    }
+
+    #[test]
+    fn secret_is_decoded_leniently() {
+        // "GEZDGNBVGY3TQOJQ..." - base32 от "12345678901234567890"
+        let key = decode_secret("gezd gnbv-gy3t qojq GEZDGNBVGY3TQOJQ====").unwrap();
+        assert_eq!(key.as_slice(), RFC_SHA1_KEY);
+        assert!(decode_secret("").is_err());
+        assert!(decode_secret("не base32").is_err());
+    }
+
+    #[test]
+    fn otpauth_uri_is_parsed() {
+        let data = parse_otpauth(
+            "otpauth://totp/ACME%20Co:john@example.com?secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ\
+             &issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert!(matches!(
+            &data,
+            DataType::Otp {
+                issuer,
+                account,
+                algorithm: OtpAlgorithm::Sha256,
+                digits: 8,
+                period: 60,
+                counter: None,
+                ..
+            } if issuer == "ACME Co" && account == "john@example.com"
+        ));
+
+        let data =
+            parse_otpauth("otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=5")
+                .unwrap();
+        assert!(matches!(
+            data,
+            DataType::Otp {
+                counter: Some(5),
+                ..
+            }
+        ));
+
+        assert!(parse_otpauth("otpauth://totp/x?issuer=y").is_err());
+        assert!(parse_otpauth("otpauth://motp/x?secret=GEZDGNBV").is_err());
+        assert!(parse_otpauth("otpauth://totp/x?secret=GEZDGNBV&digits=4").is_err());
+    }
+
+    #[test]
+    fn hotp_entry_uses_its_counter_and_has_no_live_code() {
+        let otp =
+            parse_otpauth("otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=2")
+                .unwrap();
+        assert_eq!(code_for(&otp, 0).unwrap().code.as_str(), "359152");
+        assert!(live_code(&otp, 0).is_none());
+    }
+
+    #[test]
+    fn hotp_counter_advances_in_otp_and_password_entries() {
+        let mut otp =
+            parse_otpauth("otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(code_for(&otp, 0).unwrap().code.as_str(), "755224");
+        assert!(advance_counter(&mut otp).unwrap());
+        assert_eq!(code_for(&otp, 0).unwrap().code.as_str(), "287082");
+
+        let mut password = DataType::Password {
+            password: String::new(),
+            username: String::new(),
+            urls: Vec::new(),
+            totp: Some(
+                "otpauth://hotp/x?counter=1&secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=y"
+                    .to_string(),
+            ),
+        };
+        assert_eq!(code_for(&password, 0).unwrap().code.as_str(), "287082");
+        assert!(advance_counter(&mut password).unwrap());
+        assert!(matches!(
+            &password,
+            DataType::Password { totp: Some(uri), .. }
+                if uri == "otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=y&counter=2"
+        ));
+        assert_eq!(code_for(&password, 0).unwrap().code.as_str(), "359152");
+        // HOTP не показывается в таблице
+        assert!(live_code(&password, 0).is_none());
+
+        let mut plain = DataType::Password {
+            password: String::new(),
+            username: String::new(),
+            urls: Vec::new(),
+            totp: Some("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string()),
+        };
+        assert!(!advance_counter(&mut plain).unwrap());
+        assert_eq!(code_for(&plain, 59).unwrap().code.as_str(), "287082");
+    }
+
+    #[test]
+    fn exhausted_hotp_counter_is_an_error() {
+        let uri = format!(
+            "otpauth://hotp/x?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter={}",
+            u64::MAX
+        );
+        let mut otp = parse_otpauth(&uri).unwrap();
+        assert!(matches!(
+            advance_counter(&mut otp),
+            Err(PassHiveError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            otp,
+            DataType::Otp {
+                counter: Some(u64::MAX),
+                ..
+            }
+        ));
+
+        let mut password = DataType::Password {
+            password: String::new(),
+            username: String::new(),
+            urls: Vec::new(),
+            totp: Some(uri.clone()),
+        };
+        assert!(matches!(
+            advance_counter(&mut password),
+            Err(PassHiveError::InvalidInput(_))
+        ));
+        assert!(matches!(&password, DataType::Password { totp: Some(totp), .. } if *totp == uri));
+    }
+}
@@ -0,0 +1,544 @@
+use crate::card;
+use crate::db::models::{DataType, Sex};
+use crate::error::PassHiveError;
+
+// Машиночитаемая зона паспортов и ID-карт по ICAO 9303:
+// TD3 - 2 строки по 44 символа (паспорта), TD2 - 2 по 36, TD1 - 3 по 30 (ID-карты)
+
+const TD3_LEN: usize = 44;
+const TD2_LEN: usize = 36;
+const TD1_LEN: usize = 30;
+const DOC_NUMBER_LEN: usize = 9;
+
+// Значение символа для контрольной цифры: цифры как есть, A-Z - 10..35, заполнитель - 0
+fn char_value(c: char) -> Result<u32, PassHiveError> {
+    match c {
+        '0'..='9' => Ok(c as u32 - '0' as u32),
+        'A'..='Z' => Ok(c as u32 - 'A' as u32 + 10),
+        '<' => Ok(0),
+        _ => Err(PassHiveError::InvalidInput(format!(
+            "недопустимый символ в MRZ: {}",
+            c
+        ))),
+    }
+}
+
+// Взвешенная сумма с весами 7, 3, 1 по модулю 10
+pub fn check_digit(field: &str) -> Result<char, PassHiveError> {
+    let mut sum = 0;
+    for (c, weight) in field.chars().zip([7, 3, 1].into_iter().cycle()) {
+        sum += char_value(c)? * weight;
+    }
+    Ok(char::from_digit(sum % 10, 10).unwrap_or('0'))
+}
+
+// Пустое необязательное поле может иметь заполнитель вместо контрольной цифры
+fn verify(field: &str, check: char, label: &str) -> Result<(), PassHiveError> {
+    if check == '<' && field.chars().all(|c| c == '<') {
+        return Ok(());
+    }
+    let expected = check_digit(field)?;
+    if check != expected {
+        return Err(PassHiveError::InvalidInput(format!(
+            "MRZ: неверная контрольная цифра поля \"{}\": {}, ожидалась {}",
+            label, check, expected
+        )));
+    }
+    Ok(())
+}
+
+// Транслитерация ФИО по ICAO 9303 (как в загранпаспортах РФ)
+pub fn transliterate(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.to_uppercase().chars() {
+        let latin = match c {
+            'А' => "A",
+            'Б' => "B",
+            'В' => "V",
+            'Г' => "G",
+            'Д' => "D",
+            'Е' | 'Ё' | 'Э' => "E",
+            'Ж' => "ZH",
+            'З' => "Z",
+            'И' | 'Й' => "I",
+            'К' => "K",
+            'Л' => "L",
+            'М' => "M",
+            'Н' => "N",
+            'О' => "O",
+            'П' => "P",
+            'Р' => "R",
+            'С' => "S",
+            'Т' => "T",
+            'У' => "U",
+            'Ф' => "F",
+            'Х' => "KH",
+            'Ц' => "TS",
+            'Ч' => "CH",
+            'Ш' => "SH",
+            'Щ' => "SHCH",
+            'Ъ' => "IE",
+            'Ы' => "Y",
+            'Ь' => "",
+            'Ю' => "IU",
+            'Я' => "IA",
+            'A'..='Z' => {
+                result.push(c);
+                continue;
+            }
+            // Пробелы, дефисы, апострофы и прочее становятся заполнителем
+            _ => "<",
+        };
+        result.push_str(latin);
+    }
+    result
+}
+
+// Дата хранится как ДД.ММ.ГГГГ, так же как дата рождения
+pub fn validate_date(text: &str) -> Result<String, PassHiveError> {
+    let invalid = || {
+        PassHiveError::InvalidInput(format!(
+            "дата указывается как ДД.ММ.ГГГГ, получено {}",
+            text
+        ))
+    };
+
+    let parts: Vec<&str> = text.trim().split('.').collect();
+    let [day, month, year] = parts.as_slice() else {
+        return Err(invalid());
+    };
+    if day.len() != 2 || month.len() != 2 || year.len() != 4 {
+        return Err(invalid());
+    }
+    let day = day.parse::<u32>().map_err(|_| invalid())?;
+    let month = month.parse::<u32>().map_err(|_| invalid())?;
+    let year = year.parse::<u32>().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(month, year)).contains(&day) {
+        return Err(invalid());
+    }
+
+    Ok(text.trim().to_string())
+}
+
+fn days_in_month(month: u32, year: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Код страны ICAO: три латинские буквы (RUS, DEU) или D для Германии
+pub fn normalize_country(text: &str) -> Result<String, PassHiveError> {
+    let code = text.trim().to_uppercase();
+    if code.is_empty() || code.len() > 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "код страны - до трех латинских букв, например RUS, получено {}",
+            text.trim()
+        )));
+    }
+    Ok(code)
+}
+
+pub fn normalize_doc_number(text: &str) -> Result<String, PassHiveError> {
+    let number: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if number.is_empty()
+        || number.len() > DOC_NUMBER_LEN
+        || !number
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        return Err(PassHiveError::InvalidInput(format!(
+            "номер документа - до {} латинских букв и цифр",
+            DOC_NUMBER_LEN
+        )));
+    }
+    Ok(number)
+}
+
+// Проверка паспорта, собранного из полей командной строки: заполненные
+// поля должны быть в тех же форматах, что и при интерактивном вводе
+pub fn validate(data: &DataType) -> Result<(), PassHiveError> {
+    if let DataType::Passport {
+        doc_number,
+        country,
+        nationality,
+        issue_date,
+        expiry_date,
+        ..
+    } = data
+    {
+        if !doc_number.is_empty() {
+            normalize_doc_number(doc_number)?;
+        }
+        for code in [country, nationality] {
+            if !code.is_empty() {
+                normalize_country(code)?;
+            }
+        }
+        for date in [issue_date, expiry_date] {
+            if !date.is_empty() {
+                validate_date(date)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pad(field: &str, len: usize) -> String {
+    let mut padded: String = field.chars().take(len).collect();
+    while padded.len() < len {
+        padded.push('<');
+    }
+    padded
+}
+
+fn strip_filler(field: &str) -> String {
+    field.trim_end_matches('<').to_string()
+}
+
+// ДД.ММ.ГГГГ -> ГГММДД
+fn mrz_date(date: &str, label: &str) -> Result<String, PassHiveError> {
+    if date.is_empty() {
+        return Err(PassHiveError::InvalidInput(format!(
+            "для MRZ нужна {}",
+            label
+        )));
+    }
+    let date = validate_date(date)?;
+    Ok(format!("{}{}{}", &date[8..10], &date[3..5], &date[0..2]))
+}
+
+// ГГММДД -> ДД.ММ.ГГГГ. Век даты рождения - последний, в котором она уже прошла,
+// срок действия всегда в 20xx
+fn stored_date(field: &str, birth: bool) -> Result<String, PassHiveError> {
+    let yy = field[0..2]
+        .parse::<i64>()
+        .map_err(|_| PassHiveError::InvalidInput(format!("MRZ: неверная дата {}", field)))?;
+    let (now_year, _) = card::year_month(crate::otp::unix_now());
+    let year = match birth && 2000 + yy > now_year {
+        true => 1900 + yy,
+        false => 2000 + yy,
+    };
+    validate_date(&format!("{}.{}.{}", &field[4..6], &field[2..4], year))
+}
+
+fn parse_sex(c: char) -> Sex {
+    match c {
+        'M' => Sex::Male,
+        'F' => Sex::Female,
+        _ => Sex::NotDeclaredYet,
+    }
+}
+
+// SURNAME<<GIVEN<NAMES -> "SURNAME GIVEN NAMES"
+fn parse_name(field: &str) -> String {
+    let (surname, given) = field.split_once("<<").unwrap_or((field, ""));
+    let mut words: Vec<&str> = surname.split('<').filter(|w| !w.is_empty()).collect();
+    words.extend(given.split('<').filter(|w| !w.is_empty()));
+    words.join(" ")
+}
+
+// Поля, общие для всех форматов MRZ
+struct MrzFields<'a> {
+    name: &'a str,
+    country: &'a str,
+    doc_number: &'a str,
+    doc_check: char,
+    nationality: &'a str,
+    birth: &'a str,
+    birth_check: char,
+    sex: char,
+    expiry: &'a str,
+    expiry_check: char,
+}
+
+impl MrzFields<'_> {
+    fn into_passport(self) -> Result<DataType, PassHiveError> {
+        verify(self.doc_number, self.doc_check, "номер документа")?;
+        verify(self.birth, self.birth_check, "дата рождения")?;
+        verify(self.expiry, self.expiry_check, "срок действия")?;
+
+        Ok(DataType::Passport {
+            fsl: parse_name(self.name),
+            date: stored_date(self.birth, true)?,
+            sex: parse_sex(self.sex),
+            serial: 0,
+            num: 0,
+            doc_number: strip_filler(self.doc_number),
+            country: strip_filler(self.country),
+            nationality: strip_filler(self.nationality),
+            authority: String::new(),
+            issue_date: String::new(),
+            expiry_date: stored_date(self.expiry, false)?,
+        })
+    }
+}
+
+fn char_at(line: &str, index: usize) -> char {
+    line.as_bytes()[index] as char
+}
+
+// Строки MRZ можно вставлять с переносами или одной строкой;
+// пробелы и регистр не важны
+pub fn parse_mrz(text: &str) -> Result<DataType, PassHiveError> {
+    let joined: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    if let Some(c) = joined
+        .chars()
+        .find(|c| !c.is_ascii_uppercase() && !c.is_ascii_digit() && *c != '<')
+    {
+        return Err(PassHiveError::InvalidInput(format!(
+            "недопустимый символ в MRZ: {}",
+            c
+        )));
+    }
+
+    match joined.len() {
+        len if len == 2 * TD3_LEN => parse_two_lines(&joined, TD3_LEN),
+        len if len == 2 * TD2_LEN => parse_two_lines(&joined, TD2_LEN),
+        len if len == 3 * TD1_LEN => parse_td1(&joined),
+        len => Err(PassHiveError::InvalidInput(format!(
+            "MRZ - это 2 строки по 44 или 36 символов либо 3 строки по 30, получено {} символов",
+            len
+        ))),
+    }
+}
+
+// TD3 и TD2 отличаются только длиной имени и необязательных данных
+fn parse_two_lines(joined: &str, len: usize) -> Result<DataType, PassHiveError> {
+    let (line1, line2) = joined.split_at(len);
+
+    let composite = char_at(line2, len - 1);
+    let composite_field = format!("{}{}{}", &line2[0..10], &line2[13..20], &line2[21..len - 1]);
+    let passport = MrzFields {
+        name: &line1[5..],
+        country: &line1[2..5],
+        doc_number: &line2[0..9],
+        doc_check: char_at(line2, 9),
+        nationality: &line2[10..13],
+        birth: &line2[13..19],
+        birth_check: char_at(line2, 19),
+        sex: char_at(line2, 20),
+        expiry: &line2[21..27],
+        expiry_check: char_at(line2, 27),
+    }
+    .into_passport()?;
+
+    if len == TD3_LEN {
+        verify(&line2[28..42], char_at(line2, 42), "личный номер")?;
+    }
+    verify(&composite_field, composite, "общая")?;
+    Ok(passport)
+}
+
+fn parse_td1(joined: &str) -> Result<DataType, PassHiveError> {
+    let (line1, rest) = joined.split_at(TD1_LEN);
+    let (line2, line3) = rest.split_at(TD1_LEN);
+
+    let composite_field = format!(
+        "{}{}{}{}",
+        &line1[5..30],
+        &line2[0..7],
+        &line2[8..15],
+        &line2[18..29]
+    );
+    let passport = MrzFields {
+        name: line3,
+        country: &line1[2..5],
+        doc_number: &line1[5..14],
+        doc_check: char_at(line1, 14),
+        nationality: &line2[15..18],
+        birth: &line2[0..6],
+        birth_check: char_at(line2, 6),
+        sex: char_at(line2, 7),
+        expiry: &line2[8..14],
+        expiry_check: char_at(line2, 14),
+    }
+    .into_passport()?;
+
+    verify(&composite_field, char_at(line2, 29), "общая")?;
+    Ok(passport)
+}
+
+// MRZ паспорта (TD3) из сохраненных полей. Первое слово ФИО - фамилия,
+// остальные - имена; кириллица транслитерируется
+pub fn generate_mrz(data: &DataType) -> Result<String, PassHiveError> {
+    let DataType::Passport {
+        fsl,
+        date,
+        sex,
+        doc_number,
+        country,
+        nationality,
+        expiry_date,
+        ..
+    } = data
+    else {
+        return Err(PassHiveError::InvalidInput(format!(
+            "MRZ есть только у паспорта, а не у записи типа {}",
+            data.formal_name()
+        )));
+    };
+
+    if doc_number.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "для MRZ нужен номер документа (doc_number)".to_string(),
+        ));
+    }
+    if country.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "для MRZ нужен код страны выдачи (country)".to_string(),
+        ));
+    }
+    let country = normalize_country(country)?;
+    let nationality = match nationality.is_empty() {
+        true => country.clone(),
+        false => normalize_country(nationality)?,
+    };
+
+    let mut words = fsl.split_whitespace().map(transliterate);
+    let surname = words
+        .next()
+        .ok_or_else(|| PassHiveError::InvalidInput("для MRZ нужно ФИО (fsl)".to_string()))?;
+    let given: Vec<String> = words.collect();
+    let name = format!("{}<<{}", surname, given.join("<"));
+
+    let doc_number = pad(&normalize_doc_number(doc_number)?, DOC_NUMBER_LEN);
+    let birth = mrz_date(date, "дата рождения")?;
+    let expiry = mrz_date(expiry_date, "дата окончания срока действия")?;
+    let sex = match sex {
+        Sex::Male => 'M',
+        Sex::Female => 'F',
+        Sex::NotDeclaredYet => '<',
+    };
+    let optional = pad("", 14);
+
+    let line1 = format!("P<{}{}", pad(&country, 3), pad(&name, TD3_LEN - 5));
+    let mut line2 = format!(
+        "{}{}{}{}{}{}{}{}{}<",
+        doc_number,
+        check_digit(&doc_number)?,
+        pad(&nationality, 3),
+        birth,
+        check_digit(&birth)?,
+        sex,
+        expiry,
+        check_digit(&expiry)?,
+        optional,
+    );
+    let composite_field = format!("{}{}{}", &line2[0..10], &line2[13..20], &line2[21..43]);
+    line2.push(check_digit(&composite_field)?);
+
+    Ok(format!("{}\n{}", line1, line2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Образцы из ICAO Doc 9303, части 4-6
+    const TD3: &str = "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\n\
+                       L898902C36UTO7408122F1204159ZE184226B<<<<<10";
+    const TD2: &str = "I<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<\n\
+                       D231458907UTO7408122F1204159<<<<<<<6";
+    const TD1: &str = "I<UTOD231458907<<<<<<<<<<<<<<<\n\
+                       7408122F1204159UTO<<<<<<<<<<<6\n\
+                       ERIKSSON<<ANNA<MARIA<<<<<<<<<<";
+
+    fn assert_specimen(data: &DataType, number: &str) {
+        assert!(matches!(
+            data,
+            DataType::Passport {
+                fsl,
+                date,
+                sex: Sex::Female,
+                doc_number,
+                country,
+                nationality,
+                expiry_date,
+                ..
+            } if fsl == "ERIKSSON ANNA MARIA"
+                && date == "12.08.1974"
+                && doc_number == number
+                && country == "UTO"
+                && nationality == "UTO"
+                && expiry_date == "15.04.2012"
+        ));
+    }
+
+    #[test]
+    fn check_digits_match_icao_specimen() {
+        assert_eq!(check_digit("L898902C3").unwrap(), '6');
+        assert_eq!(check_digit("740812").unwrap(), '2');
+        assert_eq!(check_digit("120415").unwrap(), '9');
+        assert_eq!(check_digit("ZE184226B<<<<<").unwrap(), '1');
+        assert_eq!(check_digit("D23145890").unwrap(), '7');
+        assert!(check_digit("l898").is_err());
+    }
+
+    #[test]
+    fn icao_specimens_are_parsed() {
+        assert_specimen(&parse_mrz(TD3).unwrap(), "L898902C3");
+        assert_specimen(&parse_mrz(TD2).unwrap(), "D23145890");
+        assert_specimen(&parse_mrz(TD1).unwrap(), "D23145890");
+        // Регистр и переносы строк не важны
+        assert_specimen(
+            &parse_mrz(&TD3.to_lowercase().replace('\n', " ")).unwrap(),
+            "L898902C3",
+        );
+    }
+
+    #[test]
+    fn wrong_check_digit_is_rejected() {
+        let broken = TD3.replace("L898902C36", "L898902C37");
+        assert!(parse_mrz(&broken).is_err());
+        let broken = TD3.replace("<<<<<10", "<<<<<11");
+        assert!(parse_mrz(&broken).is_err());
+        assert!(parse_mrz("P<UTO").is_err());
+    }
+
+    #[test]
+    fn generated_mrz_parses_back() {
+        let specimen = parse_mrz(TD3).unwrap();
+        let mrz = generate_mrz(&specimen).unwrap();
+
+        assert_eq!(
+            mrz,
+            "P<UTOERIKSSON<<ANNA<MARIA<<<<<<<<<<<<<<<<<<<\n\
+             L898902C36UTO7408122F1204159<<<<<<<<<<<<<<<8"
+        );
+        assert_specimen(&parse_mrz(&mrz).unwrap(), "L898902C3");
+    }
+
+    #[test]
+    fn cyrillic_names_are_transliterated() {
+        assert_eq!(transliterate("Щукина-Юрьева"), "SHCHUKINA<IUREVA");
+        assert_eq!(transliterate("Алексей Ёлкин"), "ALEKSEI<ELKIN");
+    }
+
+    #[test]
+    fn dates_are_checked_against_the_calendar() {
+        assert_eq!(validate_date(" 29.02.2024 ").unwrap(), "29.02.2024");
+        assert!(validate_date("29.02.2023").is_err());
+        assert!(validate_date("29.02.2000").is_ok());
+        assert!(validate_date("29.02.1900").is_err());
+        assert!(validate_date("31.02.2020").is_err());
+        assert!(validate_date("31.04.2030").is_err());
+        assert!(validate_date("31.12.2030").is_ok());
+        assert!(validate_date("00.01.2030").is_err());
+        assert!(validate_date("01.13.2030").is_err());
+        assert!(validate_date("1.1.2030").is_err());
+        assert!(validate_date("01-01-2030").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::ops::Deref;
+use zeroize::Zeroize;
+
+// Ключ в отдельной куче, которая по возможности закреплена в RAM (mlock),
+// чтобы не попасть в swap, и затирается нулями при удалении
+pub struct SecretKey(Box<[u8; 32]>);
+
+impl SecretKey {
+    pub fn zeroed() -> Self {
+        let key = SecretKey(Box::new([0u8; 32]));
+        lock_memory(key.0.as_ptr(), key.0.len());
+        key
+    }
+
+    pub fn as_mut_bytes(&mut self) -> &mut [u8; 32] {
+        &mut self.0
+    }
+}
+
+impl Deref for SecretKey {
+    type Target = [u8; 32];
+
+    fn deref(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    // munlock не вызывается: страница может быть общей с другим ключом,
+    // а закрепление снимается со всей страницы сразу
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(unix)]
+fn lock_memory(ptr: *const u8, len: usize) {
+    // Ошибка не критична: без CAP_IPC_LOCK лимит RLIMIT_MEMLOCK может быть исчерпан
+    unsafe {
+        libc::mlock(ptr as *const libc::c_void, len);
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_memory(_ptr: *const u8, _len: usize) {}
+
+// Запрещает дампы памяти процесса, чтобы ключи и пароли не оказались в core-файле
+pub fn harden_process() {
+    #[cfg(unix)]
+    unsafe {
+        let no_core = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_starts_zeroed_and_is_writable() {
+        let mut key = SecretKey::zeroed();
+        assert_eq!(*key, [0u8; 32]);
+
+        key.as_mut_bytes()[0] = 7;
+        key.as_mut_bytes()[31] = 9;
+        assert_eq!((key[0], key[1], key[31]), (7, 0, 9));
+    }
+
+    #[test]
+    fn key_is_zeroized_in_place() {
+        let mut key = SecretKey::zeroed();
+        key.as_mut_bytes().fill(0xAB);
+        let buffer = key.as_ptr();
+
+        key.zeroize();
+        assert_eq!(key.as_ptr(), buffer);
+        assert_eq!(*key, [0u8; 32]);
+    }
+
+    #[test]
+    fn process_is_hardened_against_core_dumps() {
+        harden_process();
+        // Повторный вызов ничего не ломает
+        harden_process();
+
+        #[cfg(unix)]
+        unsafe {
+            let mut limit = libc::rlimit {
+                rlim_cur: 1,
+                rlim_max: 1,
+            };
+            assert_eq!(libc::getrlimit(libc::RLIMIT_CORE, &mut limit), 0);
+            assert_eq!((limit.rlim_cur, limit.rlim_max), (0, 0));
+        }
+
+        #[cfg(target_os = "linux")]
+        unsafe {
+            assert_eq!(libc::prctl(libc::PR_GET_DUMPABLE), 0);
+        }
+    }
+}
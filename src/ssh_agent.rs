@@ -0,0 +1,431 @@
+use crate::error::PassHiveError;
+use rsa::pkcs1v15::SigningKey;
+use sha2::{Sha256, Sha512};
+use signature::{SignatureEncoding, Signer};
+use ssh_key::Mpint;
+use ssh_key::PrivateKey;
+use ssh_key::private::{KeypairData, RsaKeypair};
+use std::fs::{self, DirBuilder};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+// Агент SSH (draft-miller-ssh-agent) поверх Unix-сокета. Только выдает ключи
+// и подписывает: добавлять и удалять ключи клиенты не могут, закрытые ключи
+// живут лишь в памяти процесса, пока хранилище открыто
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+// Как в OpenSSH: сообщения длиннее считаются ошибкой клиента
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+struct AgentKey {
+    key: PrivateKey,
+    // Открытый ключ в формате протокола: по нему клиент выбирает ключ для подписи
+    blob: Vec<u8>,
+}
+
+type SharedKeys = Arc<Mutex<Vec<AgentKey>>>;
+
+pub struct SshAgent {
+    socket: PathBuf,
+    keys: SharedKeys,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SshAgent {
+    // Сокет создается только для владельца (0600); существующий файл не перезаписывается
+    pub fn start(socket: &Path, keys: Vec<PrivateKey>) -> Result<Self, PassHiveError> {
+        let listener = UnixListener::bind(socket)?;
+        fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+
+        let keys: SharedKeys = Arc::new(Mutex::new(agent_keys(keys)?));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let keys = Arc::clone(&keys);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || accept_loop(listener, keys, stop))
+        };
+
+        Ok(SshAgent {
+            socket: socket.to_path_buf(),
+            keys,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    // Ключи меняются вместе с записями хранилища, без перезапуска агента
+    pub fn set_keys(&self, keys: Vec<PrivateKey>) -> Result<(), PassHiveError> {
+        let keys = agent_keys(keys)?;
+        *lock_keys(&self.keys) = keys;
+        Ok(())
+    }
+
+    pub fn key_count(&self) -> usize {
+        lock_keys(&self.keys).len()
+    }
+}
+
+impl Drop for SshAgent {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Открытые соединения больше не получат ни одного ключа
+        lock_keys(&self.keys).clear();
+        // accept блокирует поток - будим его пустым подключением
+        let _ = UnixStream::connect(&self.socket);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+// Путь сокета по умолчанию: $XDG_RUNTIME_DIR или каталог /tmp/passhive-UID с правами 0700
+pub fn default_socket_path() -> Result<PathBuf, PassHiveError> {
+    let file = format!("passhive-agent.{}.sock", std::process::id());
+
+    if let Some(runtime) = std::env::var_os("XDG_RUNTIME_DIR") {
+        let runtime = PathBuf::from(runtime);
+        if runtime.is_dir() {
+            return Ok(runtime.join(file));
+        }
+    }
+
+    let uid = unsafe { libc::getuid() };
+    let dir = std::env::temp_dir().join(format!("passhive-{}", uid));
+    match DirBuilder::new().mode(0o700).create(&dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            // Чужой или открытый всем каталог мог подготовить другой пользователь
+            let meta = fs::symlink_metadata(&dir)?;
+            if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "каталог {} должен принадлежать текущему пользователю и иметь права 0700",
+                    dir.display()
+                )));
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(dir.join(file))
+}
+
+fn agent_keys(keys: Vec<PrivateKey>) -> Result<Vec<AgentKey>, PassHiveError> {
+    keys.into_iter()
+        .map(|key| {
+            let blob = key
+                .public_key()
+                .to_bytes()
+                .map_err(|e| PassHiveError::InvalidInput(format!("ключ SSH: {}", e)))?;
+            Ok(AgentKey { key, blob })
+        })
+        .collect()
+}
+
+// Отравленный мьютекс не мешает: список ключей всегда остается целым
+fn lock_keys(keys: &SharedKeys) -> std::sync::MutexGuard<'_, Vec<AgentKey>> {
+    keys.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn accept_loop(listener: UnixListener, keys: SharedKeys, stop: Arc<AtomicBool>) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if !same_user(&stream) {
+            continue;
+        }
+        let keys = Arc::clone(&keys);
+        let stop = Arc::clone(&stop);
+        thread::spawn(move || serve_client(stream, keys, stop));
+    }
+}
+
+// Права на сокет уже закрывают доступ, но как и ssh-agent проверяем владельца процесса
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn same_user(stream: &UnixStream) -> bool {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    rc == 0 && cred.uid == unsafe { libc::getuid() }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn same_user(stream: &UnixStream) -> bool {
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+    let rc = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    rc == 0 && uid == unsafe { libc::getuid() }
+}
+
+fn serve_client(mut stream: UnixStream, keys: SharedKeys, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::SeqCst) {
+        let mut len = [0u8; 4];
+        if stream.read_exact(&mut len).is_err() {
+            return;
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_MESSAGE_LEN {
+            return;
+        }
+        let mut message = vec![0u8; len];
+        if stream.read_exact(&mut message).is_err() {
+            return;
+        }
+
+        let reply = handle(&message, &keys).unwrap_or_else(|| vec![SSH_AGENT_FAILURE]);
+        let mut framed = (reply.len() as u32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&reply);
+        if stream.write_all(&framed).is_err() {
+            return;
+        }
+    }
+}
+
+// None - SSH_AGENT_FAILURE: неизвестный запрос, чужой ключ или ошибка подписи
+fn handle(message: &[u8], keys: &SharedKeys) -> Option<Vec<u8>> {
+    let (&kind, mut body) = message.split_first()?;
+
+    match kind {
+        SSH_AGENTC_REQUEST_IDENTITIES => {
+            let keys = lock_keys(keys);
+            let mut reply = vec![SSH_AGENT_IDENTITIES_ANSWER];
+            put_u32(&mut reply, keys.len() as u32);
+            for agent_key in keys.iter() {
+                put_string(&mut reply, &agent_key.blob);
+                put_string(&mut reply, agent_key.key.comment().as_bytes());
+            }
+            Some(reply)
+        }
+        SSH_AGENTC_SIGN_REQUEST => {
+            let blob = take_string(&mut body)?;
+            let data = take_string(&mut body)?;
+            let flags = take_u32(&mut body)?;
+
+            let keys = lock_keys(keys);
+            let agent_key = keys.iter().find(|agent_key| agent_key.blob == blob)?;
+            let (algorithm, signature) = sign(&agent_key.key, data, flags)?;
+
+            let mut encoded = Vec::new();
+            put_string(&mut encoded, algorithm.as_bytes());
+            put_string(&mut encoded, &signature);
+            let mut reply = vec![SSH_AGENT_SIGN_RESPONSE];
+            put_string(&mut reply, &encoded);
+            Some(reply)
+        }
+        _ => None,
+    }
+}
+
+// RSA подписывается алгоритмом из флагов клиента; устаревший ssh-rsa на SHA-1 не поддерживается
+fn sign(key: &PrivateKey, data: &[u8], flags: u32) -> Option<(String, Vec<u8>)> {
+    match key.key_data() {
+        KeypairData::Rsa(keypair) => {
+            let private = rsa_private_key(keypair)?;
+            if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+                let signature = SigningKey::<Sha512>::new(private).try_sign(data).ok()?;
+                Some(("rsa-sha2-512".to_string(), signature.to_vec()))
+            } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+                let signature = SigningKey::<Sha256>::new(private).try_sign(data).ok()?;
+                Some(("rsa-sha2-256".to_string(), signature.to_vec()))
+            } else {
+                None
+            }
+        }
+        _ => {
+            let signature: ssh_key::Signature = key.try_sign(data).ok()?;
+            Some((
+                signature.algorithm().as_str().to_string(),
+                signature.as_bytes().to_vec(),
+            ))
+        }
+    }
+}
+
+// TryFrom из ssh-key 0.6 передает в rsa множитель p дважды вместо p и q,
+// поэтому ключ собирается из компонентов здесь
+fn rsa_private_key(keypair: &RsaKeypair) -> Option<rsa::RsaPrivateKey> {
+    let uint =
+        |mpint: &Mpint| rsa::BigUint::from_bytes_be(mpint.as_positive_bytes().unwrap_or(&[]));
+    rsa::RsaPrivateKey::from_components(
+        uint(&keypair.public.n),
+        uint(&keypair.public.e),
+        uint(&keypair.private.d),
+        vec![uint(&keypair.private.p), uint(&keypair.private.q)],
+    )
+    .ok()
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    put_u32(buf, value.len() as u32);
+    buf.extend_from_slice(value);
+}
+
+fn take_u32(buf: &mut &[u8]) -> Option<u32> {
+    let (head, rest) = buf.split_first_chunk::<4>()?;
+    *buf = rest;
+    Some(u32::from_be_bytes(*head))
+}
+
+fn take_string<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = take_u32(buf)? as usize;
+    if buf.len() < len {
+        return None;
+    }
+    let (value, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use signature::Verifier;
+    use ssh_key::{Algorithm, Signature};
+    use tempfile::TempDir;
+
+    fn ed25519(comment: &str) -> PrivateKey {
+        let mut key =
+            PrivateKey::random(&mut ssh_key::rand_core::OsRng, Algorithm::Ed25519).unwrap();
+        key.set_comment(comment);
+        key
+    }
+
+    fn shared(keys: Vec<PrivateKey>) -> SharedKeys {
+        Arc::new(Mutex::new(agent_keys(keys).unwrap()))
+    }
+
+    fn sign_request(blob: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut message = vec![SSH_AGENTC_SIGN_REQUEST];
+        put_string(&mut message, blob);
+        put_string(&mut message, data);
+        put_u32(&mut message, 0);
+        message
+    }
+
+    #[test]
+    fn strings_are_length_prefixed() {
+        let mut buf = Vec::new();
+        put_string(&mut buf, b"ssh-ed25519");
+        put_u32(&mut buf, 7);
+        assert_eq!(&buf[..4], &[0, 0, 0, 11]);
+
+        let mut rest = buf.as_slice();
+        assert_eq!(take_string(&mut rest), Some(&b"ssh-ed25519"[..]));
+        assert_eq!(take_u32(&mut rest), Some(7));
+        assert!(rest.is_empty());
+        assert_eq!(take_u32(&mut rest), None);
+
+        // Длина больше остатка сообщения
+        let mut short: &[u8] = &[0, 0, 0, 5, b'a'];
+        assert_eq!(take_string(&mut short), None);
+    }
+
+    #[test]
+    fn identities_list_every_key_with_comment() {
+        let keys = shared(vec![ed25519("work"), ed25519("home")]);
+        let reply = handle(&[SSH_AGENTC_REQUEST_IDENTITIES], &keys).unwrap();
+
+        let (&kind, mut body) = reply.split_first().unwrap();
+        assert_eq!(kind, SSH_AGENT_IDENTITIES_ANSWER);
+        assert_eq!(take_u32(&mut body), Some(2));
+        for comment in ["work", "home"] {
+            let blob = take_string(&mut body).unwrap();
+            assert!(ssh_key::PublicKey::from_bytes(blob).is_ok());
+            assert_eq!(take_string(&mut body), Some(comment.as_bytes()));
+        }
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn sign_request_returns_verifiable_signature() {
+        let key = ed25519("work");
+        let public = key.public_key().clone();
+        let blob = public.to_bytes().unwrap();
+        let keys = shared(vec![key]);
+
+        let reply = handle(&sign_request(&blob, b"session"), &keys).unwrap();
+        let (&kind, mut body) = reply.split_first().unwrap();
+        assert_eq!(kind, SSH_AGENT_SIGN_RESPONSE);
+        let mut encoded = take_string(&mut body).unwrap();
+        let algorithm = take_string(&mut encoded).unwrap();
+        let signature = take_string(&mut encoded).unwrap();
+        assert_eq!(algorithm, b"ssh-ed25519");
+
+        let signature = Signature::new(Algorithm::Ed25519, signature.to_vec()).unwrap();
+        assert!(Verifier::verify(public.key_data(), b"session", &signature).is_ok());
+        assert!(Verifier::verify(public.key_data(), b"other", &signature).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_and_requests_fail() {
+        let keys = shared(vec![ed25519("work")]);
+        let stranger = ed25519("чужой").public_key().to_bytes().unwrap();
+
+        assert!(handle(&sign_request(&stranger, b"session"), &keys).is_none());
+        // SSH_AGENTC_ADD_IDENTITY: менять ключи клиентам нельзя
+        assert!(handle(&[17], &keys).is_none());
+        assert!(handle(&[], &keys).is_none());
+    }
+
+    #[test]
+    fn agent_answers_over_socket() {
+        let dir = TempDir::new().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let agent = SshAgent::start(&socket, vec![ed25519("work")]).unwrap();
+        assert_eq!(
+            fs::metadata(&socket).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+
+        let mut stream = UnixStream::connect(agent.socket()).unwrap();
+        stream
+            .write_all(&[0, 0, 0, 1, SSH_AGENTC_REQUEST_IDENTITIES])
+            .unwrap();
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).unwrap();
+        let mut reply = vec![0u8; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[0], SSH_AGENT_IDENTITIES_ANSWER);
+        assert_eq!(&reply[1..5], &[0, 0, 0, 1]);
+
+        agent.set_keys(Vec::new()).unwrap();
+        assert_eq!(agent.key_count(), 0);
+        drop(agent);
+        assert!(!socket.exists());
+    }
+}
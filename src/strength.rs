@@ -0,0 +1,816 @@
+use crate::key_generator::Wordlist;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Оценка стойкости пароля в духе zxcvbn: пароль разбирается на шаблоны
+// (словарные слова, ряды клавиш, повторы, последовательности, даты),
+// для каждого считается число попыток перебора, и выбирается разбиение
+// с минимальным их произведением - так думает атакующий.
+
+// Популярные пароли, по убыванию частоты
+const COMMON_PASSWORDS: &str = include_str!("wordlists/common_passwords.txt");
+
+// Перебор оценивается в 10 вариантов на символ, как в zxcvbn
+const BRUTEFORCE_LOG10_PER_CHAR: f64 = 1.0;
+const MIN_GUESSES_SUBSTRING: f64 = 50.0;
+const MIN_YEAR_SPACE: f64 = 20.0;
+// Длиннее не анализируем: дольше, а оценка и так максимальная
+const MAX_ANALYZED_LEN: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    CommonPassword { rank: usize },
+    Dictionary,
+    UserInput,
+    Spatial,
+    Repeat,
+    Sequence,
+    Date,
+}
+
+struct Match {
+    start: usize,
+    end: usize, // включительно
+    pattern: Pattern,
+    guesses: f64,
+    l33t: bool,
+    reversed: bool,
+}
+
+// Итог оценки. score - от 0 (угадывается мгновенно) до 4 (очень стойкий)
+pub struct Strength {
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub warning: Option<&'static str>,
+    pub suggestions: Vec<&'static str>,
+}
+
+impl Strength {
+    pub fn label(&self) -> &'static str {
+        score_label(self.score)
+    }
+}
+
+pub fn score_label(score: u8) -> &'static str {
+    match score {
+        0 => "очень слабый",
+        1 => "слабый",
+        2 => "средний",
+        3 => "хороший",
+        _ => "отличный",
+    }
+}
+
+// user_inputs - слова, которых не должно быть в пароле: логин, название записи
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+    let chars: Vec<char> = password.chars().take(MAX_ANALYZED_LEN).collect();
+    let matches = find_matches(&chars, user_inputs);
+    let (guesses_log10, sequence) = most_guessable(&chars, &matches);
+
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+
+    let (warning, suggestions) = feedback(score, &sequence, chars.len());
+    Strength {
+        score,
+        guesses_log10,
+        warning,
+        suggestions,
+    }
+}
+
+fn find_matches(chars: &[char], user_inputs: &[&str]) -> Vec<Match> {
+    let mut matches = dictionary_matches(chars, user_inputs);
+    matches.extend(spatial_matches(chars));
+    matches.extend(repeat_matches(chars));
+    matches.extend(sequence_matches(chars));
+    matches.extend(date_matches(chars));
+    matches
+}
+
+// Динамика по префиксам: минимальный log10 числа попыток для первых k символов.
+// Итог домножается на число перестановок найденных кусков, как в zxcvbn.
+fn most_guessable<'a>(chars: &[char], matches: &'a [Match]) -> (f64, Vec<&'a Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (0.0, Vec::new());
+    }
+
+    let mut best = vec![f64::INFINITY; n + 1];
+    // Как пришли в позицию: None - символ перебором, Some - шаблон
+    let mut back: Vec<Option<&Match>> = vec![None; n + 1];
+    best[0] = 0.0;
+
+    for k in 1..=n {
+        best[k] = best[k - 1] + BRUTEFORCE_LOG10_PER_CHAR;
+        back[k] = None;
+        for m in matches.iter().filter(|m| m.end + 1 == k) {
+            let candidate = best[m.start] + m.guesses.max(MIN_GUESSES_SUBSTRING).log10();
+            if candidate < best[k] {
+                best[k] = candidate;
+                back[k] = Some(m);
+            }
+        }
+    }
+
+    let mut sequence = Vec::new();
+    let mut segments = 0;
+    let mut k = n;
+    let mut in_bruteforce = false;
+    while k > 0 {
+        match back[k] {
+            Some(m) => {
+                sequence.push(m);
+                segments += 1;
+                in_bruteforce = false;
+                k = m.start;
+            }
+            None => {
+                if !in_bruteforce {
+                    segments += 1;
+                }
+                in_bruteforce = true;
+                k -= 1;
+            }
+        }
+    }
+    sequence.reverse();
+
+    let permutations: f64 = (1..=segments).map(|i| (i as f64).log10()).sum();
+    (best[n] + permutations, sequence)
+}
+
+fn feedback(
+    score: u8,
+    sequence: &[&Match],
+    len: usize,
+) -> (Option<&'static str>, Vec<&'static str>) {
+    if len == 0 {
+        return (
+            Some("Пароль пустой"),
+            vec!["Используйте несколько слов, избегайте известных фраз"],
+        );
+    }
+    if score >= 3 {
+        return (None, Vec::new());
+    }
+
+    let mut suggestions = vec!["Добавьте еще одно-два слова: необычные слова лучше"];
+    let longest = sequence.iter().max_by_key(|m| m.end - m.start);
+
+    let warning = match longest {
+        None => None,
+        Some(m) => {
+            if m.l33t || m.reversed {
+                suggestions.push("Замены вроде @ вместо a и слова задом наперед почти не помогают");
+            }
+            match m.pattern {
+                Pattern::CommonPassword { rank } if rank <= 10 => {
+                    Some("Это один из десяти самых популярных паролей")
+                }
+                Pattern::CommonPassword { .. } => Some("Это очень распространенный пароль"),
+                Pattern::Dictionary => Some("Отдельное слово легко угадать по словарю"),
+                Pattern::UserInput => Some("Пароль содержит логин или название"),
+                Pattern::Spatial => Some("Ряды клавиш вроде qwerty или йцукен легко угадать"),
+                Pattern::Repeat => Some("Повторы вроде aaa или abcabc легко угадать"),
+                Pattern::Sequence => Some("Последовательности вроде abc или 6543 легко угадать"),
+                Pattern::Date => {
+                    suggestions.push("Избегайте дат и лет, связанных с вами");
+                    Some("Даты и годы легко угадать")
+                }
+            }
+        }
+    };
+
+    (warning, suggestions)
+}
+
+// ---------- Словари ----------
+
+struct Dictionaries {
+    common: HashMap<String, usize>,
+    words: HashMap<String, usize>,
+}
+
+fn dictionaries() -> &'static Dictionaries {
+    static DICTIONARIES: OnceLock<Dictionaries> = OnceLock::new();
+    DICTIONARIES.get_or_init(|| {
+        let common = COMMON_PASSWORDS
+            .lines()
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(idx, word)| (word.to_string(), idx + 1))
+            .collect();
+
+        // Словари без частот: любое слово стоит размера словаря
+        let mut words = HashMap::new();
+        for wordlist in [Wordlist::English, Wordlist::Russian] {
+            let list = wordlist.words();
+            for word in list.iter() {
+                words.insert(word.to_string(), list.len());
+            }
+        }
+
+        Dictionaries { common, words }
+    })
+}
+
+const L33T_TABLES: [&[(char, char)]; 2] = [
+    &[
+        ('4', 'a'),
+        ('@', 'a'),
+        ('8', 'b'),
+        ('(', 'c'),
+        ('3', 'e'),
+        ('6', 'g'),
+        ('1', 'i'),
+        ('!', 'i'),
+        ('0', 'o'),
+        ('$', 's'),
+        ('5', 's'),
+        ('7', 't'),
+        ('+', 't'),
+        ('2', 'z'),
+    ],
+    &[('1', 'l'), ('|', 'l'), ('0', 'o'), ('@', 'a'), ('$', 's')],
+];
+
+fn dictionary_matches(chars: &[char], user_inputs: &[&str]) -> Vec<Match> {
+    let dicts = dictionaries();
+    let lower: Vec<char> = chars.iter().map(|c| to_lower(*c)).collect();
+    let user: HashMap<String, usize> = user_inputs
+        .iter()
+        .map(|input| input.to_lowercase())
+        .filter(|input| input.chars().count() >= 3)
+        .enumerate()
+        .map(|(idx, input)| (input, idx + 1))
+        .collect();
+
+    let lookup = |word: &str| -> Option<(Pattern, usize)> {
+        if let Some(rank) = user.get(word) {
+            return Some((Pattern::UserInput, *rank));
+        }
+        if let Some(rank) = dicts.common.get(word) {
+            return Some((Pattern::CommonPassword { rank: *rank }, *rank));
+        }
+        dicts
+            .words
+            .get(word)
+            .map(|rank| (Pattern::Dictionary, *rank))
+    };
+
+    let mut matches = Vec::new();
+    let n = lower.len();
+
+    // Прямые совпадения и слова задом наперед
+    let reversed: Vec<char> = lower.iter().rev().copied().collect();
+    for (text, is_reversed) in [(&lower, false), (&reversed, true)] {
+        for i in 0..n {
+            for j in (i + 2)..n {
+                let word: String = text[i..=j].iter().collect();
+                if let Some((pattern, rank)) = lookup(&word) {
+                    let (start, end) = match is_reversed {
+                        false => (i, j),
+                        true => (n - 1 - j, n - 1 - i),
+                    };
+                    if is_reversed && word.chars().eq(word.chars().rev()) {
+                        continue;
+                    }
+                    let reversal = if is_reversed { 2.0 } else { 1.0 };
+                    matches.push(Match {
+                        start,
+                        end,
+                        pattern,
+                        guesses: rank as f64 * uppercase_variations(&chars[start..=end]) * reversal,
+                        l33t: false,
+                        reversed: is_reversed,
+                    });
+                }
+            }
+        }
+    }
+
+    // l33t: p@ssw0rd -> password
+    for table in L33T_TABLES.iter() {
+        let substituted: Vec<char> = lower
+            .iter()
+            .map(|c| match table.iter().find(|(from, _)| from == c) {
+                Some((_, to)) => *to,
+                None => *c,
+            })
+            .collect();
+        if substituted == lower {
+            continue;
+        }
+
+        for i in 0..n {
+            for j in (i + 2)..n {
+                let subs = (i..=j).filter(|k| substituted[*k] != lower[*k]).count();
+                if subs == 0 {
+                    continue;
+                }
+                let word: String = substituted[i..=j].iter().collect();
+                if let Some((pattern, rank)) = lookup(&word) {
+                    matches.push(Match {
+                        start: i,
+                        end: j,
+                        pattern,
+                        guesses: rank as f64
+                            * uppercase_variations(&chars[i..=j])
+                            * 2f64.powi(subs.min(8) as i32),
+                        l33t: true,
+                        reversed: false,
+                    });
+                }
+            }
+        }
+    }
+
+    matches
+}
+
+fn to_lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+// Сколько вариантов регистра перебирает атакующий: Password и PASSWORD - 2,
+// произвольный регистр - сумма сочетаний
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+
+    if upper == 0 {
+        return 1.0;
+    }
+    let first_only = word.first().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    let last_only = word.last().is_some_and(|c| c.is_uppercase()) && upper == 1;
+    if lower == 0 || first_only || last_only {
+        return 2.0;
+    }
+
+    (1..=upper.min(lower))
+        .map(|k| n_choose_k(upper + lower, k))
+        .sum()
+}
+
+fn n_choose_k(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (1..=k).fold(1.0, |acc, i| acc * (n + 1 - i) as f64 / i as f64)
+}
+
+// ---------- Ряды клавиш ----------
+
+// (строка без Shift, с Shift, сдвиг строки в ширинах клавиши)
+type KeyboardRow = (&'static str, &'static str, f64);
+
+const QWERTY: [KeyboardRow; 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+", 0.0),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|", 1.5),
+    ("asdfghjkl;'", "ASDFGHJKL:\"", 1.75),
+    ("zxcvbnm,./", "ZXCVBNM<>?", 2.25),
+];
+
+const JCUKEN: [KeyboardRow; 4] = [
+    ("ё1234567890-=", "Ё!\"№;%:?*()_+", 0.0),
+    ("йцукенгшщзхъ\\", "ЙЦУКЕНГШЩЗХЪ/", 1.5),
+    ("фывапролджэ", "ФЫВАПРОЛДЖЭ", 1.75),
+    ("ячсмитьбю.", "ЯЧСМИТЬБЮ,", 2.25),
+];
+
+struct Keyboard {
+    // символ -> (ряд, положение по горизонтали, нажат ли Shift)
+    keys: HashMap<char, (usize, f64, bool)>,
+    starting_positions: f64,
+    average_degree: f64,
+}
+
+impl Keyboard {
+    fn new(rows: &[KeyboardRow]) -> Self {
+        let mut keys = HashMap::new();
+        for (row, (plain, shifted, offset)) in rows.iter().enumerate() {
+            for (col, c) in plain.chars().enumerate() {
+                keys.insert(c, (row, col as f64 + offset, false));
+            }
+            for (col, c) in shifted.chars().enumerate() {
+                keys.insert(c, (row, col as f64 + offset, true));
+            }
+        }
+
+        let plain: Vec<(usize, f64)> = keys
+            .values()
+            .filter(|(_, _, shifted)| !shifted)
+            .map(|(row, x, _)| (*row, *x))
+            .collect();
+        let degrees: usize = plain
+            .iter()
+            .map(|a| plain.iter().filter(|b| adjacent(*a, **b)).count())
+            .sum();
+
+        Keyboard {
+            starting_positions: plain.len() as f64,
+            average_degree: degrees as f64 / plain.len() as f64,
+            keys,
+        }
+    }
+}
+
+fn keyboards() -> &'static [Keyboard; 2] {
+    static KEYBOARDS: OnceLock<[Keyboard; 2]> = OnceLock::new();
+    KEYBOARDS.get_or_init(|| [Keyboard::new(&QWERTY), Keyboard::new(&JCUKEN)])
+}
+
+fn adjacent(a: (usize, f64), b: (usize, f64)) -> bool {
+    let dx = (a.1 - b.1).abs();
+    match a.0.abs_diff(b.0) {
+        0 => (dx - 1.0).abs() < 0.01,
+        1 => dx < 1.0,
+        _ => false,
+    }
+}
+
+fn spatial_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    for keyboard in keyboards().iter() {
+        let mut i = 0;
+        while i + 2 < chars.len() {
+            let mut j = i;
+            let mut turns = 0;
+            let mut last_direction = None;
+            let mut shifted = match keyboard.keys.get(&chars[i]) {
+                Some((_, _, true)) => 1,
+                _ => 0,
+            };
+
+            while j + 1 < chars.len() {
+                let (Some(a), Some(b)) = (
+                    keyboard.keys.get(&chars[j]),
+                    keyboard.keys.get(&chars[j + 1]),
+                ) else {
+                    break;
+                };
+                if !adjacent((a.0, a.1), (b.0, b.1)) {
+                    break;
+                }
+                let direction = (b.0 as i64 - a.0 as i64, (b.1 - a.1).signum() as i64);
+                if last_direction != Some(direction) {
+                    turns += 1;
+                    last_direction = Some(direction);
+                }
+                if b.2 {
+                    shifted += 1;
+                }
+                j += 1;
+            }
+
+            let len = j - i + 1;
+            if len >= 3 {
+                matches.push(Match {
+                    start: i,
+                    end: j,
+                    pattern: Pattern::Spatial,
+                    guesses: spatial_guesses(keyboard, len, turns, shifted),
+                    l33t: false,
+                    reversed: false,
+                });
+            }
+            i = if j > i { j } else { i + 1 };
+        }
+    }
+
+    matches
+}
+
+fn spatial_guesses(keyboard: &Keyboard, len: usize, turns: usize, shifted: usize) -> f64 {
+    let mut guesses = 0.0;
+    for i in 2..=len {
+        for j in 1..=turns.min(i - 1) {
+            guesses += n_choose_k(i - 1, j - 1)
+                * keyboard.starting_positions
+                * keyboard.average_degree.powi(j as i32);
+        }
+    }
+
+    if shifted > 0 {
+        let unshifted = len - shifted;
+        if unshifted == 0 {
+            guesses *= 2.0;
+        } else {
+            guesses *= (1..=shifted.min(unshifted))
+                .map(|k| n_choose_k(shifted + unshifted, k))
+                .sum::<f64>();
+        }
+    }
+    guesses
+}
+
+// ---------- Повторы ----------
+
+fn repeat_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let n = chars.len();
+
+    for i in 0..n {
+        let mut best: Option<(usize, usize)> = None; // (длина звена, число повторов)
+        for unit in 1..=(n - i) / 2 {
+            let mut count = 1;
+            while i + (count + 1) * unit <= n
+                && chars[i..i + unit] == chars[i + count * unit..i + (count + 1) * unit]
+            {
+                count += 1;
+            }
+            let covered = unit * count;
+            if count >= 2 && covered >= 3 && best.is_none_or(|(u, c)| covered > u * c) {
+                best = Some((unit, count));
+            }
+        }
+
+        if let Some((unit, count)) = best {
+            let unit_chars = &chars[i..i + unit];
+            let base_log10 = if unit == 1 {
+                BRUTEFORCE_LOG10_PER_CHAR
+            } else {
+                let matches = find_matches(unit_chars, &[]);
+                most_guessable(unit_chars, &matches).0
+            };
+            matches.push(Match {
+                start: i,
+                end: i + unit * count - 1,
+                pattern: Pattern::Repeat,
+                guesses: 10f64.powf(base_log10) * count as f64,
+                l33t: false,
+                reversed: false,
+            });
+        }
+    }
+
+    matches
+}
+
+// ---------- Последовательности ----------
+
+fn char_class(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(0),
+        'A'..='Z' => Some(1),
+        '0'..='9' => Some(2),
+        'а'..='я' => Some(3),
+        'А'..='Я' => Some(4),
+        _ => None,
+    }
+}
+
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let n = chars.len();
+    let mut i = 0;
+
+    while i + 2 < n {
+        let class = char_class(chars[i]);
+        let delta = chars[i + 1] as i64 - chars[i] as i64;
+        let mut j = i + 1;
+
+        if class.is_some() && delta.abs() == 1 && char_class(chars[j]) == class {
+            while j + 1 < n
+                && char_class(chars[j + 1]) == class
+                && chars[j + 1] as i64 - chars[j] as i64 == delta
+            {
+                j += 1;
+            }
+        }
+
+        let len = j - i + 1;
+        if len >= 3 && class.is_some() && delta.abs() == 1 {
+            let first = chars[i];
+            let base = if "aAzZ019аАяЯ".contains(first) {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            matches.push(Match {
+                start: i,
+                end: j,
+                pattern: Pattern::Sequence,
+                guesses: base * len as f64 * direction,
+                l33t: false,
+                reversed: false,
+            });
+            i = j;
+        } else {
+            i += 1;
+        }
+    }
+
+    matches
+}
+
+// ---------- Даты ----------
+
+fn reference_year() -> i64 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    1970 + (secs / 31_556_952) as i64
+}
+
+fn date_regex() -> &'static Regex {
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    DATE.get_or_init(|| {
+        Regex::new(r"^(\d{1,4})([\s/\\_.-])(\d{1,2})([\s/\\_.-])(\d{1,4})$")
+            .expect("Ошибка регулярного выражения даты")
+    })
+}
+
+// Двузначный год - ближайший к текущему из 19xx и 20xx
+fn full_year(year: i64, digits: usize) -> i64 {
+    match digits {
+        1 | 2 if year > 50 => 1900 + year,
+        1 | 2 => 2000 + year,
+        _ => year,
+    }
+}
+
+fn valid_date(day: i64, month: i64, year: i64) -> bool {
+    (1..=31).contains(&day) && (1..=12).contains(&month) && (1900..=2049).contains(&year)
+}
+
+// Ближайший к текущему год среди допустимых прочтений (д, м, г)
+fn best_date_year(candidates: &[(i64, i64, i64, usize)], reference: i64) -> Option<i64> {
+    candidates
+        .iter()
+        .map(|(day, month, year, digits)| (*day, *month, full_year(*year, *digits)))
+        .filter(|(day, month, year)| valid_date(*day, *month, *year))
+        .map(|(_, _, year)| year)
+        .min_by_key(|year| (year - reference).abs())
+}
+
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let reference = reference_year();
+    let year_space = |year: i64| ((year - reference).abs() as f64).max(MIN_YEAR_SPACE);
+    let mut matches = Vec::new();
+    let n = chars.len();
+
+    for i in 0..n {
+        for j in (i + 3)..n.min(i + 10) {
+            let token: String = chars[i..=j].iter().collect();
+            let mut found: Option<(i64, f64)> = None; // (год, множитель)
+
+            if token.chars().all(|c| c.is_ascii_digit()) {
+                let num =
+                    |from: usize, len: usize| token[from..from + len].parse::<i64>().unwrap_or(0);
+                match token.len() {
+                    4 => {
+                        let year = num(0, 4);
+                        if (1900..=2049).contains(&year) {
+                            matches.push(Match {
+                                start: i,
+                                end: j,
+                                pattern: Pattern::Date,
+                                guesses: year_space(year),
+                                l33t: false,
+                                reversed: false,
+                            });
+                        }
+                    }
+                    6 => {
+                        let candidates = [
+                            (num(0, 2), num(2, 2), num(4, 2), 2), // ддммгг
+                            (num(2, 2), num(0, 2), num(4, 2), 2), // ммддгг
+                            (num(4, 2), num(2, 2), num(0, 2), 2), // ггммдд
+                        ];
+                        found = best_date_year(&candidates, reference).map(|y| (y, 1.0));
+                    }
+                    8 => {
+                        let candidates = [
+                            (num(0, 2), num(2, 2), num(4, 4), 4), // ддммгггг
+                            (num(2, 2), num(0, 2), num(4, 4), 4), // ммддгггг
+                            (num(6, 2), num(4, 2), num(0, 4), 4), // ггггммдд
+                        ];
+                        found = best_date_year(&candidates, reference).map(|y| (y, 1.0));
+                    }
+                    _ => {}
+                }
+            } else if let Some(caps) = date_regex().captures(&token)
+                && caps[2] == caps[4]
+            {
+                let part = |k: usize| caps[k].parse::<i64>().unwrap_or(0);
+                let (a, b, c) = (part(1), part(3), part(5));
+                let candidates = [
+                    (a, b, c, caps[5].len()), // дд.мм.гггг
+                    (b, a, c, caps[5].len()), // мм/дд/гггг
+                    (c, b, a, caps[1].len()), // гггг-мм-дд
+                ];
+                // Разделитель - еще 4 варианта перебора
+                found = best_date_year(&candidates, reference).map(|y| (y, 4.0));
+            }
+
+            if let Some((year, separator)) = found {
+                matches.push(Match {
+                    start: i,
+                    end: j,
+                    pattern: Pattern::Date,
+                    guesses: year_space(year) * 365.0 * separator,
+                    l33t: false,
+                    reversed: false,
+                });
+            }
+        }
+    }
+
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covers(chars: &str, matches: &[Match], pattern: Pattern) -> bool {
+        let len = chars.chars().count();
+        matches
+            .iter()
+            .any(|m| m.pattern == pattern && m.start == 0 && m.end == len - 1)
+    }
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn common_and_patterned_passwords_are_weak() {
+        for password in [
+            "password", "123456", "qwerty", "aaaaaaaa", "abcdefg", "P@ssw0rd",
+        ] {
+            let strength = estimate(password, &[]);
+            assert!(strength.score <= 1, "{}: {}", password, strength.score);
+            assert!(strength.warning.is_some(), "{}", password);
+        }
+    }
+
+    #[test]
+    fn random_long_password_is_strong() {
+        let strength = estimate("t9#Vq2!mLx8&Rz4pW", &[]);
+        assert_eq!(strength.score, 4);
+        assert!(strength.guesses_log10 >= 10.0);
+    }
+
+    #[test]
+    fn user_inputs_lower_the_score() {
+        let plain = estimate("alicewonderland", &[]);
+        let with_login = estimate("alicewonderland", &["alicewonderland"]);
+        assert!(with_login.guesses_log10 < plain.guesses_log10);
+        assert_eq!(with_login.score, 0);
+    }
+
+    #[test]
+    fn keyboard_sequence_and_date_patterns_are_found() {
+        assert!(covers(
+            "qwerty",
+            &spatial_matches(&chars("qwerty")),
+            Pattern::Spatial
+        ));
+        assert!(covers(
+            "йцукен",
+            &spatial_matches(&chars("йцукен")),
+            Pattern::Spatial
+        ));
+        assert!(covers(
+            "abcdef",
+            &sequence_matches(&chars("abcdef")),
+            Pattern::Sequence
+        ));
+        assert!(covers(
+            "zzzz",
+            &repeat_matches(&chars("zzzz")),
+            Pattern::Repeat
+        ));
+        assert!(covers(
+            "12.05.1990",
+            &date_matches(&chars("12.05.1990")),
+            Pattern::Date
+        ));
+        assert!(!covers(
+            "45.13.1990",
+            &date_matches(&chars("45.13.1990")),
+            Pattern::Date
+        ));
+    }
+
+    #[test]
+    fn score_labels_cover_all_scores() {
+        assert_eq!(score_label(0), "очень слабый");
+        assert_eq!(score_label(4), "отличный");
+    }
+}
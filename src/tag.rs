@@ -0,0 +1,142 @@
+use crate::error::PassHiveError;
+
+// Теги записей: произвольные метки пользователя ("работа", "банк").
+// Сравниваются без учета регистра, в записи хранятся по алфавиту без повторов
+
+// В --tags и в запросе тегов в таблице теги перечисляются через запятую
+pub const SEPARATOR: char = ',';
+
+pub fn validate_name(tag: &str) -> Result<(), PassHiveError> {
+    if tag.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "тег не может быть пустым".to_string(),
+        ));
+    }
+    if tag.contains([SEPARATOR, '\n']) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в теге {} не может быть запятой и перевода строки",
+            tag
+        )));
+    }
+    Ok(())
+}
+
+pub fn validate(tags: &[String]) -> Result<(), PassHiveError> {
+    for (idx, tag) in tags.iter().enumerate() {
+        validate_name(tag)?;
+        if tags[..idx].iter().any(|other| same(other, tag)) {
+            return Err(PassHiveError::InvalidInput(format!(
+                "тег {} повторяется",
+                tag
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.trim().to_lowercase()
+}
+
+// Уже имеющийся тег (в любом регистре) не добавляется повторно
+pub fn add(tags: &mut Vec<String>, tag: &str) -> Result<(), PassHiveError> {
+    let tag = tag.trim();
+    validate_name(tag)?;
+    if !has(tags, tag) {
+        tags.push(tag.to_string());
+        tags.sort_by_key(|tag| tag.to_lowercase());
+    }
+    Ok(())
+}
+
+// false - такого тега у записи не было
+pub fn remove(tags: &mut Vec<String>, tag: &str) -> bool {
+    let before = tags.len();
+    tags.retain(|other| !same(other, tag));
+    tags.len() != before
+}
+
+pub fn has(tags: &[String], tag: &str) -> bool {
+    tags.iter().any(|other| same(other, tag))
+}
+
+// "работа, банк" -> ["банк", "работа"]; пустые элементы пропускаются
+pub fn parse_list(input: &str) -> Result<Vec<String>, PassHiveError> {
+    let mut tags = Vec::new();
+    for tag in input.split(SEPARATOR).map(str::trim) {
+        if !tag.is_empty() {
+            add(&mut tags, tag)?;
+        }
+    }
+    Ok(tags)
+}
+
+// Все теги записей с числом записей у каждого; регистр - как при первой встрече
+pub fn count<'a>(tag_lists: impl IntoIterator<Item = &'a [String]>) -> Vec<(String, usize)> {
+    let mut counted: Vec<(String, usize)> = Vec::new();
+    for tags in tag_lists {
+        for tag in tags {
+            match counted.iter_mut().find(|(other, _)| same(other, tag)) {
+                Some((_, count)) => *count += 1,
+                None => counted.push((tag.clone(), 1)),
+            }
+        }
+    }
+    counted.sort_by_key(|(tag, _)| tag.to_lowercase());
+    counted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn add_ignores_case_and_keeps_order() {
+        let mut list = Vec::new();
+        add(&mut list, " работа ").unwrap();
+        add(&mut list, "Банк").unwrap();
+        add(&mut list, "РАБОТА").unwrap();
+        assert_eq!(list, tags(&["Банк", "работа"]));
+
+        assert!(add(&mut list, "").is_err());
+        assert!(add(&mut list, "a,b").is_err());
+        assert!(has(&list, "банк"));
+        assert!(!has(&list, "дом"));
+    }
+
+    #[test]
+    fn remove_reports_missing_tag() {
+        let mut list = tags(&["банк", "работа"]);
+        assert!(remove(&mut list, "Работа"));
+        assert!(!remove(&mut list, "работа"));
+        assert_eq!(list, tags(&["банк"]));
+    }
+
+    #[test]
+    fn list_is_parsed_and_validated() {
+        assert_eq!(
+            parse_list("работа, банк,,Работа ").unwrap(),
+            tags(&["банк", "работа"])
+        );
+        assert!(parse_list(" , ").unwrap().is_empty());
+
+        assert!(validate(&tags(&["банк", "работа"])).is_ok());
+        assert!(validate(&tags(&["банк", "Банк"])).is_err());
+        assert!(validate(&tags(&["a\nb"])).is_err());
+    }
+
+    #[test]
+    fn tags_are_counted_across_entries() {
+        let first = tags(&["работа", "банк"]);
+        let second = tags(&["Работа"]);
+        let counted = count([first.as_slice(), second.as_slice(), &[]]);
+        assert_eq!(
+            counted,
+            [("банк".to_string(), 1), ("работа".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::db::models::{DataType, TemplateValue};
+use crate::error::PassHiveError;
+use crate::passport;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use strum_macros::EnumIter;
+use zeroize::Zeroize;
+
+// Шаблоны записей пользователя: "База данных", "Лицензия" и т.п.
+// Запись по шаблону хранит поля вместе с их видами, поэтому остается
+// читаемой после правки или удаления шаблона
+
+// В JSON вид поля записывается как formal_name: "text", "secret"...
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Zeroize, EnumIter, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    #[default]
+    Text,
+    Secret,
+    Number,
+    Date,
+    Url,
+    Multiline,
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldKind::Text => write!(f, "текст"),
+            FieldKind::Secret => write!(f, "секрет"),
+            FieldKind::Number => write!(f, "число"),
+            FieldKind::Date => write!(f, "дата"),
+            FieldKind::Url => write!(f, "адрес"),
+            FieldKind::Multiline => write!(f, "многострочный текст"),
+        }
+    }
+}
+
+impl FieldKind {
+    pub fn formal_name(&self) -> &'static str {
+        match self {
+            FieldKind::Text => "text",
+            FieldKind::Secret => "secret",
+            FieldKind::Number => "number",
+            FieldKind::Date => "date",
+            FieldKind::Url => "url",
+            FieldKind::Multiline => "multiline",
+        }
+    }
+
+    pub fn from_formal_name(formal_name: &str) -> Option<FieldKind> {
+        match formal_name {
+            "text" => Some(FieldKind::Text),
+            "secret" => Some(FieldKind::Secret),
+            "number" => Some(FieldKind::Number),
+            "date" => Some(FieldKind::Date),
+            "url" => Some(FieldKind::Url),
+            "multiline" => Some(FieldKind::Multiline),
+            _ => None,
+        }
+    }
+
+    // Проверяет значение и приводит его к виду для хранения. Пустое значение допустимо всегда
+    pub fn normalize(&self, value: &str) -> Result<String, PassHiveError> {
+        if *self == FieldKind::Multiline {
+            return Ok(value.trim_end().to_string());
+        }
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(String::new());
+        }
+        match self {
+            FieldKind::Number => match value.parse::<f64>() {
+                Ok(num) if num.is_finite() => Ok(value.to_string()),
+                _ => Err(PassHiveError::InvalidInput(format!("{} - не число", value))),
+            },
+            FieldKind::Date => passport::validate_date(value),
+            FieldKind::Url if value.contains(char::is_whitespace) => Err(
+                PassHiveError::InvalidInput("в адресе не может быть пробелов".to_string()),
+            ),
+            _ => Ok(value.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TemplateField {
+    pub name: String,
+    pub kind: FieldKind,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Template {
+    // id строки в templates; в зашифрованный JSON не попадает
+    #[serde(skip)]
+    pub id: i64,
+    pub name: String,
+    pub fields: Vec<TemplateField>,
+}
+
+impl Template {
+    pub fn new(name: &str, fields: Vec<TemplateField>) -> Result<Self, PassHiveError> {
+        let template = Template {
+            id: 0,
+            name: name.trim().to_string(),
+            fields,
+        };
+        template.validate()?;
+        Ok(template)
+    }
+
+    pub fn validate(&self) -> Result<(), PassHiveError> {
+        if self.name.is_empty() {
+            return Err(PassHiveError::InvalidInput(
+                "у шаблона должно быть название".to_string(),
+            ));
+        }
+        // Встроенные типы выбираются по формальному имени раньше шаблонов
+        if DataType::from_formal_name(&self.name.to_lowercase()).is_some() {
+            return Err(PassHiveError::InvalidInput(format!(
+                "название {} занято встроенным типом",
+                self.name
+            )));
+        }
+        if self.fields.is_empty() {
+            return Err(PassHiveError::InvalidInput(
+                "в шаблоне должно быть хотя бы одно поле".to_string(),
+            ));
+        }
+        for (idx, field) in self.fields.iter().enumerate() {
+            validate_field_name(&field.name)?;
+            if self.fields[..idx]
+                .iter()
+                .any(|other| other.name == field.name)
+            {
+                return Err(PassHiveError::InvalidInput(format!(
+                    "поле {} повторяется",
+                    field.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Пустая запись по шаблону: поля в порядке шаблона
+    pub fn new_entry(&self) -> DataType {
+        DataType::Templated {
+            template: self.name.clone(),
+            fields: self
+                .fields
+                .iter()
+                .map(|field| TemplateValue {
+                    name: field.name.clone(),
+                    kind: field.kind,
+                    value: String::new(),
+                })
+                .collect(),
+        }
+    }
+
+    // Запись, пересобранная по текущему шаблону: поля и их порядок - из шаблона,
+    // значения переносятся по имени. Непустые поля, которых в шаблоне уже нет, остаются в конце
+    pub fn relayout(&self, data: &DataType) -> DataType {
+        let DataType::Templated { fields: old, .. } = data else {
+            return data.clone();
+        };
+
+        let mut result = self.new_entry();
+        if let DataType::Templated { fields, .. } = &mut result {
+            for field in fields.iter_mut() {
+                if let Some(previous) = old.iter().find(|previous| previous.name == field.name) {
+                    // Значение, не подходящее под новый вид поля, сохраняется как есть
+                    field.value = field
+                        .kind
+                        .normalize(&previous.value)
+                        .unwrap_or_else(|_| previous.value.clone());
+                }
+            }
+            for previous in old {
+                if !previous.value.is_empty() && !fields.iter().any(|f| f.name == previous.name) {
+                    fields.push(previous.clone());
+                }
+            }
+        }
+        result
+    }
+
+    // "ПОЛЕ:ВИД" из командной строки; без вида - текст
+    pub fn parse_field(spec: &str) -> Result<TemplateField, PassHiveError> {
+        let (name, kind) = match spec.rsplit_once(':') {
+            Some((name, kind)) => match FieldKind::from_formal_name(kind.trim()) {
+                Some(kind) => (name, kind),
+                None => {
+                    return Err(PassHiveError::InvalidInput(format!(
+                        "неизвестный вид поля {}: доступны text, secret, number, date, url, multiline",
+                        kind
+                    )));
+                }
+            },
+            None => (spec, FieldKind::Text),
+        };
+        Ok(TemplateField {
+            name: name.trim().to_string(),
+            kind,
+        })
+    }
+}
+
+// Имена полей используются в --set ПОЛЕ=ЗНАЧЕНИЕ и в выводе get
+pub fn validate_field_name(name: &str) -> Result<(), PassHiveError> {
+    if name.is_empty() {
+        return Err(PassHiveError::InvalidInput(
+            "у поля шаблона должно быть имя".to_string(),
+        ));
+    }
+    if name.contains(['=', ',', '\n']) {
+        return Err(PassHiveError::InvalidInput(format!(
+            "в имени поля {} не может быть символов =, запятой и перевода строки",
+            name
+        )));
+    }
+    if name == "template" {
+        return Err(PassHiveError::InvalidInput(
+            "имя поля template зарезервировано".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Шаблон по названию без учета регистра
+pub fn find<'a>(templates: &'a [Template], name: &str) -> Option<&'a Template> {
+    templates
+        .iter()
+        .find(|template| template.name.to_lowercase() == name.trim().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database() -> Template {
+        Template::new(
+            " База данных ",
+            vec![
+                Template::parse_field("host:url").unwrap(),
+                Template::parse_field("port:number").unwrap(),
+                Template::parse_field("password:secret").unwrap(),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn field_values_are_normalized_by_kind() {
+        assert_eq!(FieldKind::Number.normalize(" 5432 ").unwrap(), "5432");
+        assert!(FieldKind::Number.normalize("порт").is_err());
+        assert!(FieldKind::Number.normalize("inf").is_err());
+        assert_eq!(
+            FieldKind::Date.normalize("01.02.2030").unwrap(),
+            "01.02.2030"
+        );
+        assert!(FieldKind::Date.normalize("31.02.2030").is_err());
+        assert!(FieldKind::Url.normalize("db.local /x").is_err());
+        assert_eq!(
+            FieldKind::Multiline.normalize("  a\nb\n\n").unwrap(),
+            "  a\nb"
+        );
+        assert_eq!(FieldKind::Number.normalize("  ").unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        let field = |spec: &str| Template::parse_field(spec).unwrap();
+
+        assert!(Template::new("", vec![field("host")]).is_err());
+        assert!(Template::new("Password", vec![field("host")]).is_err());
+        assert!(Template::new("Сервер", Vec::new()).is_err());
+        assert!(Template::new("Сервер", vec![field("host"), field("host:url")]).is_err());
+        assert!(Template::new("Сервер", vec![field("template")]).is_err());
+        assert!(Template::new("Сервер", vec![field("a=b")]).is_err());
+        assert!(Template::parse_field("host:ip").is_err());
+        assert_eq!(database().name, "База данных");
+    }
+
+    #[test]
+    fn relayout_follows_the_template_and_keeps_old_values() {
+        let mut entry = database().new_entry();
+        entry.set_field("host", "db.local").unwrap();
+        entry.set_field("port", "5432").unwrap();
+        assert!(entry.set_field("port", "пять").is_err());
+        assert!(entry.set_field("user", "admin").is_err());
+
+        let changed = Template::new(
+            "База данных",
+            vec![
+                Template::parse_field("user").unwrap(),
+                Template::parse_field("host:url").unwrap(),
+            ],
+        )
+        .unwrap();
+        let relaid = changed.relayout(&entry);
+
+        assert_eq!(
+            relaid.fields().unwrap(),
+            vec![
+                ("template".to_string(), "База данных".to_string()),
+                ("user".to_string(), String::new()),
+                ("host".to_string(), "db.local".to_string()),
+                ("port".to_string(), "5432".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn templates_are_found_case_insensitively() {
+        let templates = vec![database()];
+        assert!(find(&templates, "база ДАННЫХ ").is_some());
+        assert!(find(&templates, "лицензия").is_none());
+    }
+}
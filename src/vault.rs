@@ -0,0 +1,1072 @@
+use crate::attachment;
+use crate::audit::{self, AuditPolicy, AuditReport};
+use crate::crypto::{self, StreamDecryptor, StreamEncryptor};
+use crate::custom_fields;
+use crate::db::models::{
+    Attachment, AttachmentMeta, Credentials, DataAndMeta, KdfParams, Labels, ShowableData, UserData,
+};
+use crate::db::{db_work, migrations};
+use crate::error::PassHiveError;
+use crate::folder::{self, Folder};
+use crate::secret::SecretKey;
+use crate::tag;
+use crate::template::{self, Template};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+// Хранилище без TUI: открывается по каталогу с passhive.db,
+// после unlock держит логин, id пользователя и ключ хранилища в памяти
+pub struct Vault {
+    path: PathBuf,
+    session: Option<Session>,
+    backup: Option<PathBuf>,
+}
+
+struct Session {
+    user_id: i64,
+    login: String,
+    key: SecretKey,
+}
+
+// Что произошло с хранилищем при входе
+pub struct UnlockReport {
+    // Сколько старых записей перешифровано с отдельным nonce на поле
+    // (у старого аккаунта - еще и новым случайным ключом хранилища)
+    pub migrated_rows: usize,
+    // Новые параметры KDF, если они были применены при входе
+    pub kdf_upgraded: Option<KdfParams>,
+}
+
+impl Vault {
+    // Создает или обновляет схему БД до версии этой сборки
+    pub fn open(dir: &PathBuf) -> Result<Self, PassHiveError> {
+        let backup = migrations::migrate(dir)?;
+
+        Ok(Vault {
+            path: dir.clone(),
+            session: None,
+            backup,
+        })
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    // Копия БД, сделанная перед миграцией при этом открытии
+    pub fn migration_backup(&self) -> Option<&PathBuf> {
+        self.backup.as_ref()
+    }
+
+    // Все копии до миграций, в том числе с прошлых запусков. В них записи
+    // зашифрованы прежним паролем, и смена пароля их не касается
+    pub fn migration_backups(&self) -> Result<Vec<PathBuf>, PassHiveError> {
+        migrations::backups(&self.path)
+    }
+
+    pub fn has_users(&self) -> Result<bool, PassHiveError> {
+        Ok(!db_work::users_empty(&self.path)?)
+    }
+
+    pub fn register(&self, login: &str, password: &str) -> Result<i64, PassHiveError> {
+        if db_work::user_exists(&self.path, login)? {
+            return Err(PassHiveError::UserExists);
+        }
+
+        let vault_key = crypto::create_vault_key()?;
+        let creds = seal_credentials(password, &vault_key, KdfParams::recommended())?;
+        db_work::reg_new_user(&self.path, login, &creds)
+    }
+
+    // Неизвестный логин и неверный пароль неразличимы: оба дают WrongPassword
+    pub fn unlock(&mut self, login: &str, password: &str) -> Result<UnlockReport, PassHiveError> {
+        self.lock();
+
+        let (user_id, hash) = match db_work::find_by_login(&self.path, login)? {
+            Some(found) => found,
+            None => return Err(PassHiveError::WrongPassword),
+        };
+
+        if !crypto::check_password(&hash, password)? {
+            return Err(PassHiveError::WrongPassword);
+        }
+
+        let (key, kdf_upgraded, migrated_rows) = unlock_vault_key(&self.path, user_id, password)?;
+
+        self.session = Some(Session {
+            user_id,
+            login: login.to_string(),
+            key,
+        });
+
+        Ok(UnlockReport {
+            migrated_rows,
+            kdf_upgraded,
+        })
+    }
+
+    // Ключ хранилища затирается при удалении сессии
+    pub fn lock(&mut self) {
+        self.session = None;
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn user_id(&self) -> Result<i64, PassHiveError> {
+        Ok(self.session()?.user_id)
+    }
+
+    pub fn login(&self) -> Result<&str, PassHiveError> {
+        Ok(&self.session()?.login)
+    }
+
+    fn session(&self) -> Result<&Session, PassHiveError> {
+        self.session.as_ref().ok_or(PassHiveError::Locked)
+    }
+
+    pub fn list(&self) -> Result<Vec<ShowableData>, PassHiveError> {
+        let session = self.session()?;
+
+        db_work::get_all_user_data(&self.path, session.user_id)?
+            .iter()
+            .map(|row| decrypt_row(row, &session.key))
+            .collect()
+    }
+
+    pub fn get(&self, id: i64) -> Result<ShowableData, PassHiveError> {
+        let session = self.session()?;
+
+        let row = db_work::get_row(&self.path, session.user_id, id)?;
+        decrypt_row(&row, &session.key)
+    }
+
+    pub fn add(&self, entry: &DataAndMeta) -> Result<i64, PassHiveError> {
+        let session = self.session()?;
+
+        custom_fields::validate(&entry.custom_fields)?;
+        tag::validate(&entry.labels.tags)?;
+        if let Some(folder) = entry.folder {
+            self.check_folder(folder)?;
+        }
+        let fields = crypto::encrypt_row(
+            &entry.data,
+            &entry.name,
+            &entry.notice,
+            &entry.custom_fields,
+            &entry.labels,
+            &session.key,
+        )?;
+        db_work::insert_row(
+            &self.path,
+            session.user_id,
+            &entry.data.formal_name(),
+            &fields,
+            entry.folder,
+        )
+    }
+
+    pub fn update(&self, entry: &ShowableData) -> Result<(), PassHiveError> {
+        self.save(entry, None)
+    }
+
+    // Правка и перенос в папку одной транзакцией: ошибка не оставит правку наполовину
+    pub fn update_and_move(
+        &self,
+        entry: &ShowableData,
+        folder: Option<i64>,
+    ) -> Result<(), PassHiveError> {
+        self.save(entry, Some(folder))
+    }
+
+    // folder: None - папка записи не меняется
+    fn save(&self, entry: &ShowableData, folder: Option<Option<i64>>) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        custom_fields::validate(&entry.custom_fields)?;
+        tag::validate(&entry.labels.tags)?;
+        if let Some(Some(folder)) = folder {
+            self.check_folder(folder)?;
+        }
+        // Переименование не делает пароль новее - возраст секрета считается отдельно
+        let current = self.get(entry.id)?;
+        let field = entry.data.secret_field();
+        let secret_changed = current.data.formal_name() != entry.data.formal_name()
+            || current.data.field_map()?.get(&field) != entry.data.field_map()?.get(&field);
+
+        let fields = crypto::encrypt_row(
+            &entry.data,
+            &entry.name,
+            &entry.notice,
+            &entry.custom_fields,
+            &entry.labels,
+            &session.key,
+        )?;
+        db_work::update_row(
+            &self.path,
+            session.user_id,
+            entry.id,
+            &fields,
+            secret_changed,
+            folder,
+        )
+    }
+
+    pub fn delete(&self, id: i64) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        db_work::delete_row(&self.path, session.user_id, id)
+    }
+
+    pub fn templates(&self) -> Result<Vec<Template>, PassHiveError> {
+        let session = self.session()?;
+
+        db_work::get_templates(&self.path, session.user_id)?
+            .iter()
+            .map(|(id, data, nonce)| {
+                let json = Zeroizing::new(
+                    crypto::decrypt_str(data, nonce, &session.key)
+                        .map_err(|e| corrupt_template(*id, e))?,
+                );
+                let mut template: Template = serde_json::from_str(&json)
+                    .map_err(|e| corrupt_template(*id, PassHiveError::Crypto(e.to_string())))?;
+                template.id = *id;
+                Ok(template)
+            })
+            .collect()
+    }
+
+    // Названия шаблонов уникальны без учета регистра: по ним выбирается тип в add
+    pub fn add_template(&self, template: &Template) -> Result<i64, PassHiveError> {
+        let session = self.session()?;
+
+        template.validate()?;
+        if template::find(&self.templates()?, &template.name).is_some() {
+            return Err(PassHiveError::InvalidInput(format!(
+                "шаблон {} уже существует",
+                template.name
+            )));
+        }
+
+        let json = Zeroizing::new(
+            serde_json::to_string(template).map_err(|e| PassHiveError::Crypto(e.to_string()))?,
+        );
+        let (data, nonce) = crypto::encrypt_str(&json, &session.key)?;
+        db_work::insert_template(&self.path, session.user_id, &data, &nonce)
+    }
+
+    // Записи по шаблону хранят свои поля и после удаления шаблона не меняются
+    pub fn delete_template(&self, id: i64) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        db_work::delete_template(&self.path, session.user_id, id)
+    }
+
+    pub fn folders(&self) -> Result<Vec<Folder>, PassHiveError> {
+        let session = self.session()?;
+
+        let rows = db_work::get_folders(&self.path, session.user_id)?
+            .iter()
+            .map(|(id, parent, name, nonce)| {
+                let name = crypto::decrypt_str(name, nonce, &session.key)
+                    .map_err(|e| corrupt_folder(*id, e))?;
+                Ok((*id, *parent, name))
+            })
+            .collect::<Result<Vec<_>, PassHiveError>>()?;
+        Ok(folder::with_paths(rows))
+    }
+
+    // Как mkdir -p: недостающие родительские папки создаются. Возвращает id последней
+    pub fn create_folder(&self, path: &str) -> Result<i64, PassHiveError> {
+        let session = self.session()?;
+
+        let parts = folder::split_path(path)?;
+        let mut folders = self.folders()?;
+        if let Some(existing) = folder::find(&folders, path) {
+            return Err(PassHiveError::InvalidInput(format!(
+                "папка {} уже существует",
+                existing.path
+            )));
+        }
+
+        let mut parent: Option<i64> = None;
+        for depth in 1..=parts.len() {
+            let current = parts[..depth].join(&folder::SEPARATOR.to_string());
+            parent = match folder::find(&folders, &current) {
+                Some(existing) => Some(existing.id),
+                None => {
+                    let name = &parts[depth - 1];
+                    let (encrypted, nonce) = crypto::encrypt_str(name, &session.key)?;
+                    let id = db_work::insert_folder(
+                        &self.path,
+                        session.user_id,
+                        parent,
+                        &encrypted,
+                        &nonce,
+                    )?;
+                    folders.push(Folder {
+                        id,
+                        parent,
+                        name: name.clone(),
+                        path: current,
+                    });
+                    Some(id)
+                }
+            };
+        }
+
+        parent.ok_or_else(|| PassHiveError::InvalidInput("пустой путь папки".to_string()))
+    }
+
+    // Удаляется только пустая папка: записи и вложенные папки сначала переносятся
+    pub fn delete_folder(&self, id: i64) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        let folders = self.folders()?;
+        let target = self.check_folder(id)?;
+        if folders.iter().any(|folder| folder.parent == Some(id)) {
+            return Err(PassHiveError::InvalidInput(format!(
+                "в папке {} есть вложенные папки",
+                target.path
+            )));
+        }
+        if self.folder_counts()?.contains_key(&id) {
+            return Err(PassHiveError::InvalidInput(format!(
+                "в папке {} есть записи",
+                target.path
+            )));
+        }
+
+        db_work::delete_folder(&self.path, session.user_id, id)
+    }
+
+    // id папки -> сколько в ней записей, без учета вложенных папок
+    pub fn folder_counts(&self) -> Result<HashMap<i64, i64>, PassHiveError> {
+        let session = self.session()?;
+
+        db_work::count_by_folder(&self.path, session.user_id)
+    }
+
+    // folder: None - запись больше не лежит ни в одной папке
+    pub fn move_entry(&self, id: i64, folder: Option<i64>) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        if let Some(folder) = folder {
+            self.check_folder(folder)?;
+        }
+        db_work::set_folder(&self.path, session.user_id, id, folder)
+    }
+
+    fn check_folder(&self, id: i64) -> Result<Folder, PassHiveError> {
+        self.folders()?
+            .into_iter()
+            .find(|folder| folder.id == id)
+            .ok_or_else(|| PassHiveError::InvalidInput(format!("нет папки с id {}", id)))
+    }
+
+    // Содержимое читается и шифруется частями - файл целиком в памяти не держится.
+    // mime: None - тип по расширению имени файла
+    pub fn attach(
+        &self,
+        entry: i64,
+        filename: &str,
+        mime: Option<&str>,
+        reader: impl Read,
+    ) -> Result<i64, PassHiveError> {
+        let session = self.session()?;
+
+        let filename = attachment::validate_filename(filename)?;
+        let mime = match mime.map(str::trim) {
+            Some(mime) if !mime.is_empty() => mime.to_string(),
+            _ => attachment::guess_mime(&filename).to_string(),
+        };
+        let json = Zeroizing::new(
+            serde_json::to_string(&AttachmentMeta { filename, mime })
+                .map_err(|e| PassHiveError::Crypto(e.to_string()))?,
+        );
+        let (meta, meta_nonce) = crypto::encrypt_str(&json, &session.key)?;
+
+        let stream_nonce = crypto::create_stream_nonce()?;
+        let chunks = StreamEncryptor::new(reader, &stream_nonce, &session.key);
+        db_work::insert_attachment(
+            &self.path,
+            session.user_id,
+            entry,
+            &meta,
+            &meta_nonce,
+            &stream_nonce,
+            chunks,
+        )
+    }
+
+    // entry: None - вложения всех записей
+    pub fn attachments(&self, entry: Option<i64>) -> Result<Vec<Attachment>, PassHiveError> {
+        let session = self.session()?;
+
+        db_work::get_attachments(&self.path, session.user_id, entry)?
+            .into_iter()
+            .map(|raw| {
+                let json = Zeroizing::new(
+                    crypto::decrypt_str(&raw.meta, &raw.meta_nonce, &session.key)
+                        .map_err(|e| corrupt_attachment(raw.id, e))?,
+                );
+                let meta: AttachmentMeta = serde_json::from_str(&json).map_err(|e| {
+                    corrupt_attachment(raw.id, PassHiveError::Crypto(e.to_string()))
+                })?;
+                Ok(Attachment {
+                    id: raw.id,
+                    entry: raw.entry,
+                    filename: meta.filename,
+                    mime: meta.mime,
+                    size: raw.size,
+                    created_at: raw.created_at,
+                })
+            })
+            .collect()
+    }
+
+    // Части расшифровываются и сразу пишутся в writer. Поврежденная или отрезанная
+    // часть дает ошибку, но то, что было до нее, уже записано - файл удаляет вызывающий
+    pub fn extract_attachment(
+        &self,
+        id: i64,
+        mut writer: impl Write,
+    ) -> Result<u64, PassHiveError> {
+        let session = self.session()?;
+
+        let stream_nonce = db_work::get_stream_nonce(&self.path, session.user_id, id)?;
+        let mut decryptor = StreamDecryptor::new(&stream_nonce, &session.key);
+        let mut written = 0u64;
+        db_work::read_attachment_chunks(&self.path, session.user_id, id, |chunk, last| {
+            let plain = decryptor
+                .decrypt_chunk(chunk, last)
+                .map_err(|e| corrupt_attachment(id, e))?;
+            writer.write_all(&plain)?;
+            written += plain.len() as u64;
+            Ok(())
+        })?;
+        decryptor.finish().map_err(|e| corrupt_attachment(id, e))?;
+        writer.flush()?;
+
+        Ok(written)
+    }
+
+    pub fn delete_attachment(&self, id: i64) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        db_work::delete_attachment(&self.path, session.user_id, id)
+    }
+
+    // Записи не перешифровываются: меняется только обертка ключа хранилища
+    pub fn change_password(
+        &self,
+        old_password: &str,
+        new_password: &str,
+    ) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        let hash = db_work::get_hash_by_id(&self.path, session.user_id)?;
+        if !crypto::check_password(&hash, old_password)? {
+            return Err(PassHiveError::WrongPassword);
+        }
+
+        let (current, pending) = db_work::get_kdf_params(&self.path, session.user_id)?;
+        let kdf = pending.or(current).unwrap_or(KdfParams::recommended());
+        let creds = seal_credentials(new_password, &session.key, kdf)?;
+        db_work::update_credentials(&self.path, session.user_id, &creds)
+    }
+
+    // Проверка паролей и Wi-Fi: повторы, слабые и давно не менявшиеся
+    pub fn audit(&self, policy: &AuditPolicy) -> Result<AuditReport, PassHiveError> {
+        let session = self.session()?;
+
+        let entries = self.list()?;
+        let updated_at = db_work::get_secret_updated_at(&self.path, session.user_id)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        Ok(audit::audit(&entries, &updated_at, now, policy))
+    }
+
+    // Калибровка не требует пароля: новые параметры сохраняются как отложенные
+    // и применяются при следующем входе, когда пароль снова известен
+    pub fn schedule_kdf_upgrade(&self, kdf: &KdfParams) -> Result<(), PassHiveError> {
+        let session = self.session()?;
+
+        db_work::set_pending_kdf(&self.path, session.user_id, kdf)
+    }
+}
+
+fn decrypt_row(row: &UserData, key: &[u8; 32]) -> Result<ShowableData, PassHiveError> {
+    let corrupt = |e| PassHiveError::corrupt_row(row.id, e);
+
+    let data = crypto::decrypt_data(&row.data, &row.nonce, key).map_err(corrupt)?;
+    let name = crypto::decrypt_str(&row.name, row.name_nonce(), key).map_err(corrupt)?;
+    let notice = crypto::decrypt_str(&row.notice, row.notice_nonce(), key).map_err(corrupt)?;
+    let custom_fields = match &row.custom_fields {
+        Some((fields, nonce)) => {
+            crypto::decrypt_custom_fields(fields, nonce, key).map_err(corrupt)?
+        }
+        None => Vec::new(),
+    };
+    let labels = match &row.labels {
+        Some((labels, nonce)) => crypto::decrypt_labels(labels, nonce, key).map_err(corrupt)?,
+        None => Labels::default(),
+    };
+
+    Ok(ShowableData {
+        id: row.id,
+        name,
+        notice,
+        data,
+        custom_fields,
+        folder: row.folder,
+        labels,
+    })
+}
+
+fn corrupt_template(id: i64, cause: PassHiveError) -> PassHiveError {
+    PassHiveError::CorruptRow {
+        id,
+        reason: format!("шаблон не читается: {}", cause),
+    }
+}
+
+fn corrupt_folder(id: i64, cause: PassHiveError) -> PassHiveError {
+    PassHiveError::CorruptRow {
+        id,
+        reason: format!("папка не читается: {}", cause),
+    }
+}
+
+fn corrupt_attachment(id: i64, cause: PassHiveError) -> PassHiveError {
+    PassHiveError::CorruptRow {
+        id,
+        reason: format!("вложение не читается: {}", cause),
+    }
+}
+
+// Старые записи шифровали data, name и notice одним nonce.
+// Перешифровываем их с отдельным nonce на каждое поле одной транзакцией.
+// rekey - новый ключ хранилища и учетные данные с ним: все записи переходят
+// на этот ключ, и он сохраняется в той же транзакции
+fn migrate_legacy_rows(
+    path: &PathBuf,
+    id: i64,
+    key: &[u8; 32],
+    rekey: Option<(&[u8; 32], &Credentials)>,
+) -> Result<usize, PassHiveError> {
+    let rows = db_work::get_all_user_data(path, id)?;
+    let new_key = rekey.map_or(key, |(new_key, _)| new_key);
+
+    let mut migrated = Vec::new();
+    for row in rows.iter().filter(|row| rekey.is_some() || row.is_legacy()) {
+        let entry = decrypt_row(row, key)?;
+        migrated.push((
+            row.id,
+            crypto::encrypt_row(
+                &entry.data,
+                &entry.name,
+                &entry.notice,
+                &entry.custom_fields,
+                &entry.labels,
+                new_key,
+            )?,
+        ));
+    }
+
+    let creds = rekey.map(|(_, creds)| creds);
+    if !migrated.is_empty() || creds.is_some() {
+        db_work::update_rows(path, id, &migrated, creds)?;
+    }
+
+    Ok(migrated.len())
+}
+
+// Хэш пароля, новая соль и ключ хранилища, зашифрованный ключом из пароля
+fn seal_credentials(
+    password: &str,
+    vault_key: &[u8; 32],
+    kdf: KdfParams,
+) -> Result<Credentials, PassHiveError> {
+    let (hash, salt) = crypto::encode(password, &kdf)?;
+    let kek = crypto::create_crypto_key(&salt, password, &kdf)?;
+    let (wrapped_key, key_nonce) = crypto::wrap_key(vault_key, &kek)?;
+
+    Ok(Credentials {
+        hash,
+        salt,
+        wrapped_key,
+        key_nonce,
+        kdf,
+    })
+}
+
+// Ключ хранилища расшифровывается ключом из пароля.
+// У старых пользователей ключом хранилища был сам ключ из пароля: он остается прежним.
+// Старые пользователи и пользователи с отложенной калибровкой при входе
+// получают новые параметры KDF - записи при этом не перешифровываются.
+// Ключ хранилища, новые параметры KDF, если они применены, и число перешифрованных записей
+fn unlock_vault_key(
+    path: &PathBuf,
+    id: i64,
+    password: &str,
+) -> Result<(SecretKey, Option<KdfParams>, usize), PassHiveError> {
+    let salt = db_work::get_salt_by_id(path, id)?;
+    let (current, pending) = db_work::get_kdf_params(path, id)?;
+    let kek = crypto::create_crypto_key(
+        salt.as_str(),
+        password,
+        &current.unwrap_or(KdfParams::legacy()),
+    )?;
+
+    let upgrade = match (pending, current) {
+        (Some(pending), _) => Some(pending),
+        (None, None) => Some(KdfParams::recommended()),
+        _ => None,
+    };
+
+    let Some((wrapped, nonce)) = db_work::get_wrapped_key(path, id)? else {
+        // Старый аккаунт шифровал записи ключом из пароля. Он заменяется случайным
+        // ключом хранилища, иначе после смены пароля старый пароль расшифровывал бы записи
+        let kdf = upgrade.or(current).unwrap_or(KdfParams::recommended());
+        let vault_key = crypto::create_vault_key()?;
+        let creds = seal_credentials(password, &vault_key, kdf)?;
+        let migrated = migrate_legacy_rows(path, id, &kek, Some((&vault_key, &creds)))?;
+        return Ok((vault_key, Some(kdf), migrated));
+    };
+
+    let vault_key = crypto::unwrap_key(&wrapped, &nonce, &kek)?;
+    if let Some(kdf) = upgrade {
+        let creds = seal_credentials(password, &vault_key, kdf)?;
+        db_work::update_credentials(path, id, &creds)?;
+    }
+    let migrated = migrate_legacy_rows(path, id, &vault_key, None)?;
+
+    Ok((vault_key, upgrade, migrated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::DataType;
+    use chacha20poly1305::{Key, KeyInit, XChaCha20Poly1305, XNonce, aead::Aead};
+    use rusqlite::{Connection, params};
+    use tempfile::TempDir;
+
+    // passhive.db в том виде, в каком ее оставляла версия без миграций:
+    // user_version = 0, ключ из пароля по Argon2::default(), один nonce на data, name и notice
+    fn baseline_vault(login: &str, password: &str) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        let connection = Connection::open(dir.path().join("passhive.db")).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE meta (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    user_login TEXT NOT NULL,
+                    user_create_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                    user_passwd TEXT NOT NULL,
+                    salt TEXT NOT NULL
+                );
+                CREATE TABLE users (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner INTEGER NOT NULL,
+                    data_type TEXT NOT NULL,
+                    data BLOB NOT NULL,
+                    name BLOB,
+                    notice BLOB,
+                    nonce BLOB NOT NULL
+                );
+                CREATE TABLE keys (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    owner INTEGER NOT NULL,
+                    pubkey BLOB NOT NULL
+                );",
+            )
+            .unwrap();
+
+        let (hash, salt) = crypto::encode(password, &KdfParams::legacy()).unwrap();
+        connection
+            .execute(
+                "INSERT INTO meta (user_login, user_passwd, salt) VALUES (?1, ?2, ?3)",
+                params![login, hash, salt],
+            )
+            .unwrap();
+        let owner = connection.last_insert_rowid();
+
+        let key = crypto::create_crypto_key(&salt, password, &KdfParams::legacy()).unwrap();
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&*key));
+        let nonce = [7u8; 24];
+        let seal = |text: &str| {
+            cipher
+                .encrypt(XNonce::from_slice(&nonce), text.as_bytes())
+                .unwrap()
+        };
+        connection
+            .execute(
+                "INSERT INTO users (owner, data_type, data, name, notice, nonce)
+                VALUES (?1, 'password', ?2, ?3, ?4, ?5)",
+                params![
+                    owner,
+                    seal(r#"{"Password":{"password":"hunter2"}}"#),
+                    seal("github"),
+                    seal("старая запись"),
+                    nonce
+                ],
+            )
+            .unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn unlock_reencrypts_legacy_rows() {
+        let dir = baseline_vault("alice", "pw1");
+        let path = dir.path().to_path_buf();
+
+        let mut vault = Vault::open(&path).unwrap();
+        assert!(vault.migration_backup().is_some());
+        let salt = db_work::get_salt_by_id(&path, 1).unwrap();
+        let legacy_key = crypto::create_crypto_key(&salt, "pw1", &KdfParams::legacy()).unwrap();
+        let report = vault.unlock("alice", "pw1").unwrap();
+        assert_eq!(report.migrated_rows, 1);
+        assert_eq!(report.kdf_upgraded, Some(KdfParams::recommended()));
+
+        let rows = db_work::get_all_user_data(&path, vault.user_id().unwrap()).unwrap();
+        assert!(rows.iter().all(|row| !row.is_legacy()));
+        assert_ne!(rows[0].name_nonce(), rows[0].notice_nonce());
+        // Записи перешифрованы случайным ключом хранилища, а не ключом из пароля
+        assert!(decrypt_row(&rows[0], &legacy_key).is_err());
+
+        let entries = vault.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "github");
+        assert_eq!(entries[0].notice, "старая запись");
+        assert!(matches!(
+            &entries[0].data,
+            DataType::Password { password, username, totp: None, .. }
+                if password == "hunter2" && username.is_empty()
+        ));
+
+        // Повторный вход: записи уже перешифрованы, ключ хранилища обернут
+        let mut vault = Vault::open(&path).unwrap();
+        assert!(vault.migration_backup().is_none());
+        let report = vault.unlock("alice", "pw1").unwrap();
+        assert_eq!(report.migrated_rows, 0);
+        assert_eq!(report.kdf_upgraded, None);
+        assert_eq!(vault.list().unwrap()[0].name, "github");
+    }
+
+    #[test]
+    fn change_password_keeps_entries_readable() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+        let mut vault = Vault::open(&path).unwrap();
+        vault.register("alice", "pw1").unwrap();
+        vault.unlock("alice", "pw1").unwrap();
+        let entry = DataAndMeta::new(
+            DataType::Document {
+                text: "текст".to_string(),
+            },
+            "заметка".to_string(),
+            String::new(),
+        );
+        let id = vault.add(&entry).unwrap();
+
+        assert!(matches!(
+            vault.change_password("pw2", "pw3"),
+            Err(PassHiveError::WrongPassword)
+        ));
+        vault.change_password("pw1", "pw2").unwrap();
+
+        let mut vault = Vault::open(&path).unwrap();
+        assert!(matches!(
+            vault.unlock("alice", "pw1"),
+            Err(PassHiveError::WrongPassword)
+        ));
+        vault.unlock("alice", "pw2").unwrap();
+        assert_eq!(vault.get(id).unwrap().name, "заметка");
+    }
+
+    #[test]
+    fn old_password_cannot_decrypt_migrated_account_after_change() {
+        let dir = baseline_vault("alice", "pw1");
+        let path = dir.path().to_path_buf();
+        let legacy_salt = db_work::get_salt_by_id(&path, 1).unwrap();
+        let legacy_key =
+            crypto::create_crypto_key(&legacy_salt, "pw1", &KdfParams::legacy()).unwrap();
+
+        let mut vault = Vault::open(&path).unwrap();
+        vault.unlock("alice", "pw1").unwrap();
+        let salt = db_work::get_salt_by_id(&path, 1).unwrap();
+        let old_kek = crypto::create_crypto_key(&salt, "pw1", &KdfParams::recommended()).unwrap();
+        vault.change_password("pw1", "pw2").unwrap();
+
+        assert!(matches!(
+            vault.unlock("alice", "pw1"),
+            Err(PassHiveError::WrongPassword)
+        ));
+        let (wrapped, nonce) = db_work::get_wrapped_key(&path, 1).unwrap().unwrap();
+        assert!(crypto::unwrap_key(&wrapped, &nonce, &old_kek).is_err());
+        let rows = db_work::get_all_user_data(&path, 1).unwrap();
+        assert!(decrypt_row(&rows[0], &legacy_key).is_err());
+        assert!(decrypt_row(&rows[0], &old_kek).is_err());
+
+        // Старым паролем открывается только копия до миграции - о ней предупреждает CLI
+        assert_eq!(
+            vault.migration_backups().unwrap(),
+            [path.join("passhive.db.v0.bak")]
+        );
+        vault.unlock("alice", "pw2").unwrap();
+        assert_eq!(vault.list().unwrap()[0].name, "github");
+    }
+
+    // Хранилище с зарегистрированным и открытым пользователем alice
+    fn unlocked_vault() -> (TempDir, Vault) {
+        let dir = TempDir::new().unwrap();
+        let mut vault = Vault::open(&dir.path().to_path_buf()).unwrap();
+        vault.register("alice", "pw1").unwrap();
+        vault.unlock("alice", "pw1").unwrap();
+        (dir, vault)
+    }
+
+    fn document(name: &str) -> DataAndMeta {
+        DataAndMeta::new(
+            DataType::Document {
+                text: "текст".to_string(),
+            },
+            name.to_string(),
+            String::new(),
+        )
+    }
+
+    #[test]
+    fn vault_key_is_random_not_derived_from_password() {
+        let (dir, vault) = unlocked_vault();
+        let path = dir.path().to_path_buf();
+        let id = vault.user_id().unwrap();
+        let salt = db_work::get_salt_by_id(&path, id).unwrap();
+        let kek = crypto::create_crypto_key(&salt, "pw1", &KdfParams::recommended()).unwrap();
+
+        let (key, upgraded, migrated) = unlock_vault_key(&path, id, "pw1").unwrap();
+        assert_ne!(*key, *kek);
+        assert_eq!((upgraded, migrated), (None, 0));
+        assert_eq!(*unlock_vault_key(&path, id, "pw1").unwrap().0, *key);
+    }
+
+    #[test]
+    fn locked_vault_refuses_entry_access() {
+        let (_dir, mut vault) = unlocked_vault();
+        let id = vault.add(&document("заметка")).unwrap();
+        vault.lock();
+
+        assert!(!vault.is_unlocked());
+        assert!(matches!(vault.list(), Err(PassHiveError::Locked)));
+        assert!(matches!(vault.get(id), Err(PassHiveError::Locked)));
+        assert!(matches!(vault.delete(id), Err(PassHiveError::Locked)));
+        assert!(matches!(vault.login(), Err(PassHiveError::Locked)));
+
+        vault.unlock("alice", "pw1").unwrap();
+        assert_eq!(vault.login().unwrap(), "alice");
+        assert_eq!(vault.list().unwrap().len(), 1);
+        assert!(matches!(
+            vault.get(id + 1),
+            Err(PassHiveError::NoSuchEntry(_))
+        ));
+    }
+
+    #[test]
+    fn custom_fields_survive_encryption_in_order() {
+        let (_dir, vault) = unlocked_vault();
+        let mut entry = document("заметка");
+        custom_fields::set(&mut entry.custom_fields, "PIN", "1234", true);
+        custom_fields::set(&mut entry.custom_fields, "Код", "42", false);
+        let id = vault.add(&entry).unwrap();
+
+        let mut stored = vault.get(id).unwrap();
+        assert_eq!(stored.custom_fields, entry.custom_fields);
+
+        custom_fields::set(&mut stored.custom_fields, "PIN", "", false);
+        vault.update(&stored).unwrap();
+        assert_eq!(vault.get(id).unwrap().custom_fields.len(), 1);
+
+        custom_fields::set(&mut stored.custom_fields, "Код", "1\n2", false);
+        assert!(vault.update(&stored).is_err());
+    }
+
+    #[test]
+    fn attachment_round_trips_and_goes_with_its_entry() {
+        let (_dir, vault) = unlocked_vault();
+        let entry = vault.add(&document("договор")).unwrap();
+        let content: Vec<u8> = (0..crypto::CHUNK_SIZE * 2 + 17)
+            .map(|i| (i % 253) as u8)
+            .collect();
+        let id = vault
+            .attach(entry, "../скан.pdf", None, content.as_slice())
+            .unwrap();
+
+        let attachments = vault.attachments(Some(entry)).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].id, id);
+        assert_eq!(attachments[0].filename, "скан.pdf");
+        assert_eq!(attachments[0].mime, "application/pdf");
+        assert_eq!(attachments[0].size, content.len() as u64);
+
+        let mut extracted = Vec::new();
+        let written = vault.extract_attachment(id, &mut extracted).unwrap();
+        assert_eq!(written, content.len() as u64);
+        assert_eq!(extracted, content);
+
+        // Удаление записи удаляет и ее вложения вместе с частями
+        vault.delete(entry).unwrap();
+        assert!(vault.attachments(None).unwrap().is_empty());
+        assert!(vault.extract_attachment(id, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn only_empty_folders_are_deleted() {
+        let (_dir, vault) = unlocked_vault();
+        let aws = vault.create_folder("Работа/AWS").unwrap();
+        assert!(vault.create_folder("Работа / AWS").is_err());
+        let work = folder::find(&vault.folders().unwrap(), "Работа")
+            .unwrap()
+            .id;
+        let entry = vault.add(&document("ключ")).unwrap();
+        vault.move_entry(entry, Some(aws)).unwrap();
+
+        assert!(vault.delete_folder(work).is_err());
+        assert!(vault.delete_folder(aws).is_err());
+
+        // Внешний ключ users.folder не дает удалить папку с записями и в обход проверок
+        let owner = vault.user_id().unwrap();
+        assert!(matches!(
+            db_work::delete_folder(vault.path(), owner, aws),
+            Err(PassHiveError::InvalidInput(message)) if message.contains("не пуста")
+        ));
+
+        vault.move_entry(entry, None).unwrap();
+        vault.delete_folder(aws).unwrap();
+        vault.delete_folder(work).unwrap();
+        assert!(vault.folders().unwrap().is_empty());
+    }
+
+    #[test]
+    fn update_and_move_change_nothing_on_error() {
+        let (_dir, vault) = unlocked_vault();
+        let folder = vault.create_folder("Работа").unwrap();
+        let id = vault.add(&document("старое")).unwrap();
+
+        let mut entry = vault.get(id).unwrap();
+        entry.name = "новое".to_string();
+        assert!(vault.update_and_move(&entry, Some(folder + 1)).is_err());
+        let stored = vault.get(id).unwrap();
+        assert_eq!(stored.name, "старое");
+        assert_eq!(stored.folder, None);
+
+        vault.update_and_move(&entry, Some(folder)).unwrap();
+        let stored = vault.get(id).unwrap();
+        assert_eq!(stored.name, "новое");
+        assert_eq!(stored.folder, Some(folder));
+    }
+
+    #[test]
+    fn unreadable_folders_and_templates_are_corrupt_rows() {
+        let (dir, vault) = unlocked_vault();
+        let folder = vault.create_folder("Работа").unwrap();
+        let fields = vec![Template::parse_field("host:url").unwrap()];
+        let template = vault
+            .add_template(&Template::new("Сервер", fields).unwrap())
+            .unwrap();
+
+        let connection = Connection::open(dir.path().join("passhive.db")).unwrap();
+        connection
+            .execute_batch("UPDATE folders SET name = x'00'; UPDATE templates SET data = x'00';")
+            .unwrap();
+
+        assert!(matches!(
+            vault.folders(),
+            Err(PassHiveError::CorruptRow { id, reason }) if id == folder && reason.contains("папка")
+        ));
+        assert!(matches!(
+            vault.templates(),
+            Err(PassHiveError::CorruptRow { id, reason }) if id == template && reason.contains("шаблон")
+        ));
+    }
+
+    #[test]
+    fn labels_are_stored_encrypted_and_validated() {
+        let (_dir, vault) = unlocked_vault();
+        let mut entry = document("выписка");
+        entry.labels.tags = tag::parse_list("банк, работа").unwrap();
+        entry.labels.favorite = true;
+        let id = vault.add(&entry).unwrap();
+
+        let mut stored = vault.get(id).unwrap();
+        assert_eq!(stored.labels.tags, ["банк", "работа"]);
+        assert!(stored.labels.favorite);
+
+        stored.labels.tags.push("Банк".to_string());
+        assert!(vault.update(&stored).is_err());
+        let mut duplicated = document("дубль");
+        duplicated.labels.tags = vec!["дом".to_string(), "ДОМ".to_string()];
+        assert!(vault.add(&duplicated).is_err());
+    }
+
+    #[test]
+    fn scheduled_kdf_upgrade_applies_on_next_unlock() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().to_path_buf();
+        let mut vault = Vault::open(&path).unwrap();
+        let id = vault.register("alice", "pw1").unwrap();
+        vault.unlock("alice", "pw1").unwrap();
+
+        let cheap = KdfParams {
+            iterations: 1,
+            memory_kib: 1024,
+            ..KdfParams::recommended()
+        };
+        vault.schedule_kdf_upgrade(&cheap).unwrap();
+        assert_eq!(
+            db_work::get_kdf_params(&path, id).unwrap(),
+            (Some(KdfParams::recommended()), Some(cheap))
+        );
+
+        let report = vault.unlock("alice", "pw1").unwrap();
+        assert_eq!(report.kdf_upgraded, Some(cheap));
+        assert_eq!(
+            db_work::get_kdf_params(&path, id).unwrap(),
+            (Some(cheap), None)
+        );
+        assert!(
+            db_work::get_hash_by_id(&path, id)
+                .unwrap()
+                .contains("m=1024,t=1,p=3")
+        );
+
+        let report = vault.unlock("alice", "pw1").unwrap();
+        assert_eq!(report.kdf_upgraded, None);
+    }
+
+    #[test]
+    fn unlock_rejects_wrong_password_and_unknown_login() {
+        let dir = baseline_vault("alice", "pw1");
+        let mut vault = Vault::open(&dir.path().to_path_buf()).unwrap();
+
+        assert!(matches!(
+            vault.unlock("alice", "pw2"),
+            Err(PassHiveError::WrongPassword)
+        ));
+        assert!(matches!(
+            vault.unlock("bob", "pw1"),
+            Err(PassHiveError::WrongPassword)
+        ));
+        assert!(!vault.is_unlocked());
+        assert!(matches!(vault.list(), Err(PassHiveError::Locked)));
+    }
+}